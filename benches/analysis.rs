@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use evmodin::{opcode::*, *};
+
+/// Analysis of PUSH32-dense code of growing size, whose time should grow linearly.
+fn analysis(c: &mut Criterion) {
+    let mut group = c.benchmark_group("analysis");
+    for size in [6 * 1024, 12 * 1024, 24 * 1024] {
+        group.throughput(Throughput::Bytes(size as u64));
+
+        // Every byte is PUSH32, so only every 33rd one is executable.
+        let push32 = vec![OpCode::PUSH32.to_u8(); size];
+        group.bench_with_input(BenchmarkId::new("push32", size), &push32, |b, code| {
+            b.iter(|| AnalyzedCode::analyze(code.as_slice()))
+        });
+
+        // PUSH32 with immediates of JUMPDEST bytes, none of which are jump destinations.
+        let jumpdests = [OpCode::PUSH32.to_u8()]
+            .iter()
+            .chain(&[OpCode::JUMPDEST.to_u8(); 32])
+            .copied()
+            .cycle()
+            .take(size)
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::new("push32_jumpdest", size),
+            &jumpdests,
+            |b, code| b.iter(|| AnalyzedCode::analyze(code.as_slice())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, analysis);
+criterion_main!(benches);
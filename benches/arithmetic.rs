@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use evmodin::{tracing::NoopTracer, util::*, *};
+
+/// Loop incrementing a counter from `start` 0x1000 times.
+fn increment_loop(start: u128) -> AnalyzedCode {
+    AnalyzedCode::analyze(asm(&format!(
+        "PUSH9 {} \
+         JUMPDEST \
+         PUSH1 1 ADD \
+         DUP1 PUSH9 {} GT PUSH1 10 JUMPI \
+         STOP",
+        start,
+        start + 0x1000
+    )))
+}
+
+/// Loop comparing a counter from `start` with constants 0x1000 times.
+fn comparison_loop(start: u128) -> AnalyzedCode {
+    AnalyzedCode::analyze(asm(&format!(
+        "PUSH9 {} \
+         JUMPDEST \
+         DUP1 PUSH9 {} LT POP \
+         DUP1 PUSH9 {} EQ POP \
+         PUSH1 1 ADD \
+         DUP1 PUSH9 {} GT PUSH1 10 JUMPI \
+         STOP",
+        start,
+        start + 0x800,
+        start + 0x800,
+        start + 0x1000
+    )))
+}
+
+fn run(c: &mut Criterion, group: &str, code: impl Fn(u128) -> AnalyzedCode) {
+    let message = Message::builder().gas(100_000_000).build();
+
+    let mut group = c.benchmark_group(group);
+    // Counters fit into one limb and take the fast paths, or start above it and fall back to full width.
+    for (name, start) in [("small", 0), ("wide", 1 << 64)] {
+        let code = code(start);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                code.execute(
+                    &mut mocked_host::MockedHost::default(),
+                    &mut NoopTracer,
+                    None,
+                    message.clone(),
+                    Revision::Berlin,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn arithmetic(c: &mut Criterion) {
+    run(c, "increment_loop", increment_loop);
+    run(c, "comparison_loop", comparison_loop);
+}
+
+criterion_group!(benches, arithmetic);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ethereum_types::Address;
+use evmodin::{tracing::NoopTracer, util::*, *};
+
+/// Contract touching its memory and calling itself with all its gas, until the gas or the call depth runs out.
+///
+/// Compares frames allocating their own memory against frames reusing released buffers.
+fn recursive_calls(c: &mut Criterion) {
+    let code = asm("PUSH1 1 PUSH2 0x03e0 MSTORE \
+         PUSH1 0 DUP1 DUP1 DUP1 DUP1 ADDRESS GAS CALL \
+         STOP");
+    let message = Message::builder().gas(100_000_000).build();
+
+    let mut inner = mocked_host::MockedHost::default();
+    inner.accounts.entry(Address::zero()).or_default().code = code.clone().into();
+    let code = AnalyzedCode::analyze(code);
+
+    let mut group = c.benchmark_group("recursive_calls");
+    for (name, reuse_memory) in [("fresh", false), ("pooled", true)] {
+        let config = Config {
+            reuse_memory,
+            ..Config::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    DelegatingHost::new(inner.clone())
+                        .execute_calls(Revision::Berlin)
+                        .config(config.clone())
+                },
+                |mut host| {
+                    code.execute_with_config(
+                        &mut host,
+                        &mut NoopTracer,
+                        None,
+                        message.clone(),
+                        Revision::Berlin,
+                        config.clone(),
+                    )
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, recursive_calls);
+criterion_main!(benches);
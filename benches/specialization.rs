@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use evmodin::{tracing::NoopTracer, util::*, *};
+
+/// Loop of cold and warm storage and account access, with the revision checked on every instruction.
+fn access_heavy_code() -> AnalyzedCode {
+    AnalyzedCode::analyze(asm("PUSH2 0x0400 \
+         JUMPDEST \
+         DUP1 SLOAD POP PUSH1 1 SLOAD POP \
+         DUP1 BALANCE POP PUSH1 0xbb BALANCE POP \
+         DUP1 DUP1 SSTORE \
+         PUSH1 1 SWAP1 SUB DUP1 PUSH1 3 JUMPI \
+         STOP"))
+}
+
+fn specialization(c: &mut Criterion) {
+    let code = access_heavy_code();
+    let message = Message::builder().gas(100_000_000).build();
+
+    let mut group = c.benchmark_group("berlin");
+    group.bench_function("generic", |b| {
+        // Rules differing from mainnet only in BLOCKHASH, which the code does not use, select the generic interpreter.
+        let config = Config {
+            spec: |revision| Spec {
+                block_hash_window: 257,
+                ..Spec::mainnet(revision)
+            },
+            ..Config::default()
+        };
+        b.iter(|| {
+            code.execute_with_config(
+                &mut mocked_host::MockedHost::default(),
+                &mut NoopTracer,
+                None,
+                message.clone(),
+                Revision::Berlin,
+                config.clone(),
+            )
+        })
+    });
+    group.bench_function("specialized", |b| {
+        b.iter(|| {
+            code.execute_specialized::<BerlinSpec, _, _>(
+                &mut mocked_host::MockedHost::default(),
+                &mut NoopTracer,
+                None,
+                message.clone(),
+                Config::default(),
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, specialization);
+criterion_main!(benches);
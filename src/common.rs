@@ -1,8 +1,14 @@
-use std::{convert::TryFrom, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    str::FromStr,
+};
 
+use crate::opcode::OpCode;
 use bytes::Bytes;
 use ethereum_types::{Address, H256, U256};
 use serde::Serialize;
+use sha3::{Digest, Keccak256};
 use strum_macros::Display;
 
 /// EVM revision.
@@ -16,9 +22,11 @@ pub enum Revision {
     Homestead = 1,
 
     /// [The Tangerine Whistle revision.](https://eips.ethereum.org/EIPS/eip-608)
+    #[strum(serialize = "TangerineWhistle")]
     Tangerine = 2,
 
     /// [The Spurious Dragon revision.](https://eips.ethereum.org/EIPS/eip-607)
+    #[strum(serialize = "SpuriousDragon")]
     Spurious = 3,
 
     /// [The Byzantium revision.](https://eips.ethereum.org/EIPS/eip-609)
@@ -39,8 +47,18 @@ pub enum Revision {
     /// [The London revision.](https://github.com/ethereum/eth1.0-specs/blob/master/network-upgrades/mainnet-upgrades/london.md)
     London = 9,
 
+    /// [The Paris revision.](https://github.com/ethereum/execution-specs/blob/master/network-upgrades/mainnet-upgrades/paris.md)
+    /// Also known as The Merge.
+    Paris = 10,
+
     /// The Shanghai revision.
-    Shanghai = 10,
+    Shanghai = 11,
+
+    /// The Cancun revision.
+    ///
+    /// Only its precompiles are supported: the instructions it adds are not, so code executes with
+    /// the instructions of Shanghai. Therefore it is not [`Revision::latest`].
+    Cancun = 12,
 }
 
 impl Revision {
@@ -56,16 +74,20 @@ impl Revision {
             Self::Istanbul,
             Self::Berlin,
             Self::London,
+            Self::Paris,
             Self::Shanghai,
+            Self::Cancun,
         ]
     }
 
+    /// Latest revision with complete semantics.
     pub const fn latest() -> Self {
         Self::Shanghai
     }
 
+    /// Number of revisions, including ones newer than [`Revision::latest`].
     pub const fn len() -> usize {
-        Self::latest() as usize + 1
+        Self::Cancun as usize + 1
     }
 }
 
@@ -75,15 +97,17 @@ impl FromStr for Revision {
         match s.to_lowercase().as_str() {
             "frontier" => Ok(Self::Frontier),
             "homestead" => Ok(Self::Homestead),
-            "tangerine" => Ok(Self::Tangerine),
-            "spurious" => Ok(Self::Spurious),
+            "tangerine" | "tangerinewhistle" => Ok(Self::Tangerine),
+            "spurious" | "spuriousdragon" => Ok(Self::Spurious),
             "byzantium" => Ok(Self::Byzantium),
             "constantinople" => Ok(Self::Constantinople),
             "petersburg" => Ok(Self::Petersburg),
             "istanbul" => Ok(Self::Istanbul),
             "berlin" => Ok(Self::Berlin),
             "london" => Ok(Self::London),
+            "paris" | "merge" => Ok(Self::Paris),
             "shanghai" => Ok(Self::Shanghai),
+            "cancun" => Ok(Self::Cancun),
             _ => Err(()),
         }
     }
@@ -97,6 +121,156 @@ impl TryFrom<&str> for Revision {
     }
 }
 
+/// EIPs that can be switched on or off regardless of the revision, for chains activating them out of mainnet order.
+///
+/// Everything else follows the revision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spec {
+    pub revision: Revision,
+    /// [EIP-2929](https://eips.ethereum.org/EIPS/eip-2929): cold and warm state access costs. Berlin on mainnet.
+    pub eip2929: bool,
+    /// [EIP-3855](https://eips.ethereum.org/EIPS/eip-3855): PUSH0 instruction. Shanghai on mainnet.
+    pub eip3855: bool,
+    /// State access costs with EIP-2929, for chains that price them differently.
+    pub access_costs: AccessCosts,
+    /// Number of most recent blocks BLOCKHASH can look up, 256 on mainnet. Older blocks read as zero.
+    ///
+    /// The host is asked for the hashes inside the window, so it decides where they come from,
+    /// e.g. the history contract of [EIP-2935](https://eips.ethereum.org/EIPS/eip-2935).
+    pub block_hash_window: u64,
+    /// [EIP-2935](https://eips.ethereum.org/EIPS/eip-2935): BLOCKHASH reads the hashes from the storage of
+    /// [`HISTORY_STORAGE_ADDRESS`] instead of asking the host for them. The window is then capped to
+    /// [`HISTORY_SERVE_WINDOW`]. Prague on mainnet, which is not supported yet.
+    pub eip2935: bool,
+}
+
+pub(crate) const COLD_SLOAD_COST: u16 = 2100;
+pub(crate) const COLD_ACCOUNT_ACCESS_COST: u16 = 2600;
+pub(crate) const WARM_STORAGE_READ_COST: u16 = 100;
+/// Number of most recent blocks whose hashes BLOCKHASH returns on mainnet.
+pub(crate) const BLOCK_HASH_WINDOW: u64 = 256;
+
+/// Costs of cold and warm state access with [EIP-2929](https://eips.ethereum.org/EIPS/eip-2929).
+///
+/// Only access is affected: the write part of SSTORE costs the same as on mainnet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessCosts {
+    /// First SLOAD or SSTORE of a slot in the transaction.
+    pub cold_sload: u16,
+    /// Access to a slot or an account already accessed in the transaction.
+    pub warm_storage_read: u16,
+    /// First access to an account in the transaction.
+    pub cold_account_access: u16,
+}
+
+impl AccessCosts {
+    pub const MAINNET: Self = Self {
+        cold_sload: COLD_SLOAD_COST,
+        warm_storage_read: WARM_STORAGE_READ_COST,
+        cold_account_access: COLD_ACCOUNT_ACCESS_COST,
+    };
+
+    /// Charged on top of the warm cost from the instruction table for a cold slot.
+    pub(crate) fn additional_cold_sload(&self) -> i64 {
+        i64::from(self.cold_sload) - i64::from(self.warm_storage_read)
+    }
+
+    /// Charged on top of the warm cost from the instruction table for a cold account.
+    pub(crate) fn additional_cold_account_access(&self) -> i64 {
+        i64::from(self.cold_account_access) - i64::from(self.warm_storage_read)
+    }
+}
+
+impl Default for AccessCosts {
+    fn default() -> Self {
+        Self::MAINNET
+    }
+}
+
+impl Spec {
+    /// EIPs active in the revision on mainnet.
+    pub fn mainnet(revision: Revision) -> Self {
+        Self {
+            revision,
+            eip2929: revision >= Revision::Berlin,
+            eip3855: revision >= Revision::Shanghai,
+            access_costs: AccessCosts::MAINNET,
+            block_hash_window: BLOCK_HASH_WINDOW,
+            // TODO: Switch on from Prague once it is added to `Revision`.
+            eip2935: false,
+        }
+    }
+}
+
+impl From<Revision> for Spec {
+    fn from(revision: Revision) -> Self {
+        Self::mainnet(revision)
+    }
+}
+
+/// Revision known at compile time, for an interpreter specialized to it with
+/// [`AnalyzedCode::execute_specialized`](crate::AnalyzedCode::execute_specialized).
+pub trait RevisionSpec: 'static {
+    const REVISION: Revision;
+}
+
+macro_rules! revision_specs {
+    ($($name:ident => $revision:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("[`Revision::", stringify!($revision), "`] as a type.")]
+            #[derive(Clone, Copy, Debug)]
+            pub struct $name;
+
+            impl RevisionSpec for $name {
+                const REVISION: Revision = Revision::$revision;
+            }
+        )*
+    };
+}
+
+revision_specs! {
+    FrontierSpec => Frontier,
+    HomesteadSpec => Homestead,
+    TangerineSpec => Tangerine,
+    SpuriousSpec => Spurious,
+    ByzantiumSpec => Byzantium,
+    ConstantinopleSpec => Constantinople,
+    PetersburgSpec => Petersburg,
+    IstanbulSpec => Istanbul,
+    BerlinSpec => Berlin,
+    LondonSpec => London,
+    ParisSpec => Paris,
+    ShanghaiSpec => Shanghai,
+    CancunSpec => Cancun,
+}
+
+/// Reason for rejecting contract code at deployment.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CodeError {
+    /// Code exceeds the [EIP-170](https://eips.ethereum.org/EIPS/eip-170) size limit.
+    TooLarge { size: usize },
+
+    /// Code starts with the 0xEF byte reserved by [EIP-3541](https://eips.ethereum.org/EIPS/eip-3541).
+    ReservedPrefix,
+
+    /// Code contains an instruction undefined in the target revision.
+    UndefinedInstruction { pc: usize, opcode: u8 },
+}
+
+impl std::fmt::Display for CodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { size } => write!(f, "code size {} exceeds limit", size),
+            Self::ReservedPrefix => write!(f, "code starts with reserved 0xEF byte"),
+            Self::UndefinedInstruction { pc, opcode } => {
+                write!(f, "undefined instruction 0x{:02x} at {}", opcode, pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodeError {}
+
 /// Message status code.
 #[must_use]
 #[derive(Clone, Debug, Display, PartialEq)]
@@ -175,11 +349,109 @@ pub enum StatusCode {
     #[strum(serialize = "insufficient balance")]
     InsufficientBalance,
 
+    /// LOG data exceeded the limit set in [`Config::max_log_data`].
+    #[strum(serialize = "log data limit exceeded")]
+    LogDataLimitExceeded,
+
+    /// RETURN or REVERT data exceeded the limit set in [`Config::max_output_size`].
+    #[strum(serialize = "output size limit exceeded")]
+    OutputSizeLimitExceeded,
+
+    /// Execution reached the number of instructions set in [`Config::max_steps`].
+    #[strum(serialize = "step limit exceeded")]
+    StepLimitExceeded,
+
     /// EVM implementation generic internal error.
     #[strum(serialize = "internal error")]
     InternalError(String),
 }
 
+/// Execution limits and modes on top of the consensus rules.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Maximum size of data in a single LOG instruction.
+    /// LOG with more data fails with [`StatusCode::LogDataLimitExceeded`] regardless of gas left.
+    pub max_log_data: Option<usize>,
+    /// Maximum size of data returned by RETURN or REVERT.
+    /// Larger output fails with [`StatusCode::OutputSizeLimitExceeded`] regardless of gas left.
+    pub max_output_size: Option<usize>,
+    /// Maximum number of instructions executed in a single frame.
+    /// Execution then fails with [`StatusCode::StepLimitExceeded`] regardless of gas left.
+    pub max_steps: Option<u64>,
+    /// Do not commit state changes.
+    ///
+    /// SSTORE, LOG and SELFDESTRUCT interrupts are advisory, the host may ignore them.
+    /// Gas is charged as if they took effect. Storage writes are only visible to the frame that made them.
+    pub dry_run: bool,
+    /// Set of precompiled contracts, for chains that extend the one of mainnet.
+    ///
+    /// Precompiles are always warm: calls to them and BALANCE, EXTCODESIZE, EXTCODECOPY, EXTCODEHASH and
+    /// SELFDESTRUCT on them never pay the cold account access cost, and the host is not asked about them.
+    pub is_precompile: fn(Address, Revision) -> bool,
+    /// EIPs active in the revision, for chains that do not follow mainnet.
+    pub spec: fn(Revision) -> Spec,
+    /// Split gas used by category into [`Output::gas_breakdown`]. Slows execution down.
+    pub gas_breakdown: bool,
+    /// Charge gas for execution. Enabled by default.
+    ///
+    /// Without metering execution does not run out of gas, for analysis tools exploring all paths.
+    /// The gas left stays at the gas of the message, so the output reports no gas used. Stack limits
+    /// still apply, and memory is capped to what the gas of the message would pay for. Calls and
+    /// creations forward the gas they ask for, up to the gas of the message.
+    pub metering: bool,
+    /// Record the executed instructions into [`Output::opcode_trace`].
+    pub opcode_trace: bool,
+    /// Send the storage keys the code reads with constant keys to the host at the start of each frame.
+    ///
+    /// See [`AnalyzedCode::constant_storage_keys`](crate::AnalyzedCode::constant_storage_keys).
+    pub prefetch_storage: bool,
+    /// Collect the accounts and storage keys the frame accesses into [`Output::access_list`].
+    pub access_list: bool,
+    /// Reuse memory buffers of finished frames for new ones, instead of allocating each frame its own.
+    /// Enabled by default.
+    ///
+    /// Buffers are kept per thread. Reused memory reads as zero like fresh memory does.
+    pub reuse_memory: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_log_data: None,
+            max_output_size: None,
+            max_steps: None,
+            dry_run: false,
+            is_precompile: is_mainnet_precompile,
+            spec: Spec::mainnet,
+            gas_breakdown: false,
+            metering: true,
+            opcode_trace: false,
+            prefetch_storage: false,
+            access_list: false,
+            reuse_memory: true,
+        }
+    }
+}
+
+/// Whether the address is one of the precompiled contracts active on mainnet in the given revision.
+pub fn is_mainnet_precompile(address: Address, revision: Revision) -> bool {
+    if address > Address::from_low_u64_be(u64::MAX) {
+        return false;
+    }
+
+    let last = if revision >= Revision::Cancun {
+        0x0a
+    } else if revision >= Revision::Istanbul {
+        0x09
+    } else if revision >= Revision::Byzantium {
+        0x08
+    } else {
+        0x04
+    };
+
+    (1..=last).contains(&address.to_low_u64_be())
+}
+
 /// The kind of call-like instruction.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CallKind {
@@ -219,6 +491,99 @@ pub struct Message {
     pub value: U256,
 }
 
+impl Message {
+    /// Start building a zero-depth, non-static `CallKind::Call` message.
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+}
+
+/// Builder for [`Message`] with sensible defaults.
+///
+/// ```
+/// use evmodin::{CallKind, Message};
+/// use ethereum_types::Address;
+///
+/// let message = Message::builder()
+///     .is_static(true)
+///     .destination(Address::from_low_u64_be(0xaa))
+///     .input_data(vec![0xde, 0xad])
+///     .gas(100_000)
+///     .build();
+///
+/// assert_eq!(message.kind, CallKind::Call);
+/// assert!(message.is_static);
+/// assert_eq!(&*message.input_data, &[0xde, 0xad]);
+/// assert!(message.value.is_zero());
+/// ```
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+    message: Message,
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self {
+            message: Message {
+                kind: CallKind::Call,
+                is_static: false,
+                depth: 0,
+                gas: i64::MAX,
+                destination: Address::zero(),
+                sender: Address::zero(),
+                input_data: Bytes::new(),
+                value: U256::zero(),
+            },
+        }
+    }
+}
+
+impl MessageBuilder {
+    pub fn kind(mut self, kind: CallKind) -> Self {
+        self.message.kind = kind;
+        self
+    }
+
+    pub fn is_static(mut self, is_static: bool) -> Self {
+        self.message.is_static = is_static;
+        self
+    }
+
+    pub fn depth(mut self, depth: i32) -> Self {
+        self.message.depth = depth;
+        self
+    }
+
+    pub fn gas(mut self, gas: i64) -> Self {
+        self.message.gas = gas;
+        self
+    }
+
+    pub fn destination(mut self, destination: impl Into<Address>) -> Self {
+        self.message.destination = destination.into();
+        self
+    }
+
+    pub fn sender(mut self, sender: impl Into<Address>) -> Self {
+        self.message.sender = sender.into();
+        self
+    }
+
+    pub fn input_data(mut self, input_data: impl Into<Bytes>) -> Self {
+        self.message.input_data = input_data.into();
+        self
+    }
+
+    pub fn value(mut self, value: impl Into<U256>) -> Self {
+        self.message.value = value.into();
+        self
+    }
+
+    pub fn build(self) -> Message {
+        self.message
+    }
+}
+
 /// Output of EVM execution.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Output {
@@ -226,10 +591,70 @@ pub struct Output {
     pub status_code: StatusCode,
     /// How much gas was left after execution
     pub gas_left: i64,
+    /// Gas refund from SSTORE accumulated by this frame and the nested frames that succeeded.
+    /// Zero if execution failed or reverted.
+    ///
+    /// The refund may be negative while the transaction runs, e.g. when a frame sets a slot that its caller
+    /// deleted. Clamping it and capping it to a share of the gas used is up to whoever finishes the
+    /// transaction. The refund of SELFDESTRUCT before London is not included.
+    pub gas_refund: i64,
     /// Output data returned.
     pub output_data: Bytes,
     /// Contract creation address.
     pub create_address: Option<Address>,
+    /// Instruction that caused the failure, if execution failed in this frame.
+    pub failure_location: Option<FailureLocation>,
+    /// Gas used by category, if enabled with [`Config::gas_breakdown`] and execution did not fail.
+    pub gas_breakdown: Option<GasBreakdown>,
+    /// Position and opcode of every executed instruction in order, if enabled with [`Config::opcode_trace`].
+    /// The STOP implied past the end of code is not included. If execution failed, the trace ends with
+    /// the instruction it failed at.
+    pub opcode_trace: Option<Vec<(usize, OpCode)>>,
+    /// Accounts and storage keys accessed by this frame and its nested frames, if enabled with
+    /// [`Config::access_list`]. Also reported if execution failed.
+    pub access_list: Option<AccessList>,
+    /// Memory size in bytes at the end of execution, which is the largest it has been in this frame
+    /// as memory never shrinks. Zero if execution failed.
+    pub peak_memory: usize,
+}
+
+/// Accounts accessed during execution, with the storage keys accessed in each (EIP-2930).
+///
+/// Covers the accounts touched by BALANCE, EXTCODESIZE, EXTCODECOPY, EXTCODEHASH, calls and SELFDESTRUCT,
+/// and the keys of SLOAD and SSTORE, in every revision. Accesses of nested frames are merged in from their
+/// outputs, whether they succeeded or not, so the host has to execute them with the access list enabled
+/// too. The sender, the destination and precompiles, which are always warm, are included if
+/// the code touches them; dropping them for a transaction access list is up to the caller.
+pub type AccessList = BTreeMap<Address, BTreeSet<H256>>;
+
+/// Gas used by a frame, by what it was spent on. The categories add up to the gas used.
+///
+/// Refunds are not included, they are reported in [`Output::gas_refund`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasBreakdown {
+    /// Static cost of the executed instructions.
+    pub base: i64,
+    /// Memory expansion.
+    pub memory: i64,
+    /// Dynamic cost of SLOAD and SSTORE.
+    pub storage: i64,
+    /// Dynamic cost of calls and creates, including gas used by the nested frames.
+    pub calls: i64,
+    /// Any other dynamic cost: copying, hashing, logs, EXP, account access.
+    pub other: i64,
+}
+
+impl GasBreakdown {
+    pub fn total(&self) -> i64 {
+        self.base + self.memory + self.storage + self.calls + self.other
+    }
+}
+
+/// Position and opcode of the instruction execution failed at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FailureLocation {
+    pub pc: usize,
+    pub opcode: OpCode,
 }
 
 /// EVM execution output if no error has occurred.
@@ -239,8 +664,18 @@ pub struct SuccessfulOutput {
     pub reverted: bool,
     /// How much gas was left after execution.
     pub gas_left: i64,
+    /// Gas refund accumulated by the frame.
+    pub gas_refund: i64,
     /// Output data returned.
     pub output_data: Bytes,
+    /// Gas used by category, if enabled.
+    pub gas_breakdown: Option<GasBreakdown>,
+    /// Executed instructions, if enabled.
+    pub opcode_trace: Option<Vec<(usize, OpCode)>>,
+    /// Accessed accounts and storage keys, if enabled.
+    pub access_list: Option<AccessList>,
+    /// Memory size in bytes at the end of execution.
+    pub peak_memory: usize,
 }
 
 impl From<SuccessfulOutput> for Output {
@@ -248,7 +683,12 @@ impl From<SuccessfulOutput> for Output {
         SuccessfulOutput {
             reverted,
             gas_left,
+            gas_refund,
             output_data,
+            gas_breakdown,
+            opcode_trace,
+            access_list,
+            peak_memory,
         }: SuccessfulOutput,
     ) -> Self {
         Self {
@@ -258,12 +698,136 @@ impl From<SuccessfulOutput> for Output {
                 StatusCode::Success
             },
             gas_left,
+            gas_refund: if reverted { 0 } else { gas_refund },
             output_data,
             create_address: None,
+            failure_location: None,
+            gas_breakdown,
+            opcode_trace,
+            access_list,
+            peak_memory,
         }
     }
 }
 
+/// EVM execution output if an error has occurred.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedOutput {
+    /// Why execution failed.
+    pub status_code: StatusCode,
+    /// Instruction execution failed at.
+    pub location: FailureLocation,
+    /// Executed instructions up to and including the failed one, if enabled.
+    pub opcode_trace: Option<Vec<(usize, OpCode)>>,
+    /// Accessed accounts and storage keys up to the failure, if enabled.
+    pub access_list: Option<AccessList>,
+}
+
+impl From<FailedOutput> for Output {
+    fn from(
+        FailedOutput {
+            status_code,
+            location,
+            opcode_trace,
+            access_list,
+        }: FailedOutput,
+    ) -> Self {
+        Self {
+            status_code,
+            gas_left: 0,
+            gas_refund: 0,
+            output_data: Bytes::new(),
+            create_address: None,
+            failure_location: Some(location),
+            gas_breakdown: None,
+            opcode_trace,
+            access_list,
+            peak_memory: 0,
+        }
+    }
+}
+
+/// Selector of the Solidity `Error(string)` revert payload.
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Decode the reason string of a Solidity `Error(string)` revert payload.
+///
+/// Returns `None` for any other output, including `Panic(uint256)` and raw revert data.
+pub fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    fn read_usize(data: &[u8], offset: usize) -> Option<usize> {
+        let word = data.get(offset..offset.checked_add(32)?)?;
+        let value = U256::from_big_endian(word);
+        if value > U256::from(usize::MAX) {
+            return None;
+        }
+        Some(value.as_usize())
+    }
+
+    let data = output.strip_prefix(&ERROR_STRING_SELECTOR[..])?;
+
+    let offset = read_usize(data, 0)?;
+    let len = read_usize(data, offset)?;
+    let start = offset.checked_add(32)?;
+    let reason = data.get(start..start.checked_add(len)?)?;
+
+    String::from_utf8(reason.to_vec()).ok()
+}
+
+/// Keccak-256 hash of empty input, the code hash of accounts without code.
+///
+/// EXTCODEHASH returns it for existing accounts with no code, and zero for non-existent ones.
+pub const EMPTY_CODE_HASH: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
+
+/// Block history contract of [EIP-2935](https://eips.ethereum.org/EIPS/eip-2935).
+pub const HISTORY_STORAGE_ADDRESS: Address = Address([
+    0x00, 0x00, 0xf9, 0x08, 0x27, 0xf1, 0xc5, 0x3a, 0x10, 0xcb, 0x7a, 0x02, 0x33, 0x5b, 0x17, 0x53,
+    0x20, 0x00, 0x29, 0x35,
+]);
+
+/// Number of block hashes kept by the history contract, which stores the hash of block `n`
+/// in slot `n % HISTORY_SERVE_WINDOW`.
+pub const HISTORY_SERVE_WINDOW: u64 = 8191;
+
+/// Address of a contract deployed with `CREATE` by `sender` at the given account nonce.
+///
+/// The interpreter leaves address derivation to the host; this is a helper for hosts and tests.
+pub fn create_address(sender: Address, nonce: u64) -> Address {
+    // RLP of the list `[sender, nonce]`. Both items are short, so the list is too.
+    let nonce_bytes = nonce.to_be_bytes();
+    let nonce_bytes = &nonce_bytes[nonce.leading_zeros() as usize / 8..];
+
+    let mut rlp = Vec::with_capacity(1 + 21 + 9);
+    rlp.push(0);
+    rlp.push(0x80 + 20);
+    rlp.extend_from_slice(&sender.0);
+    match nonce_bytes {
+        [b] if *b < 0x80 => rlp.push(*b),
+        _ => {
+            rlp.push(0x80 + nonce_bytes.len() as u8);
+            rlp.extend_from_slice(nonce_bytes);
+        }
+    }
+    rlp[0] = 0xc0 + (rlp.len() - 1) as u8;
+
+    Address::from_slice(&Keccak256::digest(&rlp)[12..])
+}
+
+/// Address of a contract deployed with `CREATE2` by `sender`, as defined in
+/// [EIP-1014](https://eips.ethereum.org/EIPS/eip-1014).
+pub fn create2_address(sender: Address, salt: H256, init_code_hash: H256) -> Address {
+    let mut buf = [0; 1 + 20 + 32 + 32];
+    buf[0] = 0xff;
+    buf[1..21].copy_from_slice(&sender.0);
+    buf[21..53].copy_from_slice(&salt.0);
+    buf[53..].copy_from_slice(&init_code_hash.0);
+
+    Address::from_slice(&Keccak256::digest(&buf)[12..])
+}
+
+/// Address from the low 20 bytes of the word, the rest is ignored.
 pub(crate) fn u256_to_address(v: U256) -> Address {
     H256(v.into()).into()
 }
@@ -271,3 +835,137 @@ pub(crate) fn u256_to_address(v: U256) -> Address {
 pub(crate) fn address_to_u256(v: Address) -> U256 {
     U256::from_big_endian(&v.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revision_roundtrip() {
+        for revision in Revision::iter() {
+            assert_eq!(revision.to_string().parse::<Revision>(), Ok(revision));
+        }
+
+        assert_eq!(Revision::Tangerine.to_string(), "TangerineWhistle");
+        assert_eq!(Revision::Spurious.to_string(), "SpuriousDragon");
+        assert_eq!(Revision::Paris.to_string(), "Paris");
+        assert_eq!(Revision::Cancun.to_string(), "Cancun");
+    }
+
+    #[test]
+    fn latest_revision_is_complete() {
+        assert_eq!(Revision::latest(), Revision::Shanghai);
+        assert_eq!(Revision::iter().into_iter().count(), Revision::len());
+    }
+
+    #[test]
+    fn revision_aliases() {
+        assert_eq!("Merge".parse::<Revision>(), Ok(Revision::Paris));
+        assert_eq!("paris".parse::<Revision>(), Ok(Revision::Paris));
+        assert_eq!("Tangerine".parse::<Revision>(), Ok(Revision::Tangerine));
+        assert_eq!("SPURIOUS".parse::<Revision>(), Ok(Revision::Spurious));
+        assert_eq!("Olympic".parse::<Revision>(), Err(()));
+    }
+
+    #[test]
+    fn revert_reason() {
+        // require(false, "msg")
+        let output = hex_literal::hex!(
+            "08c379a0"
+            "0000000000000000000000000000000000000000000000000000000000000020"
+            "0000000000000000000000000000000000000000000000000000000000000003"
+            "6d73670000000000000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(decode_revert_reason(&output), Some("msg".to_string()));
+
+        // Truncated payload.
+        assert_eq!(decode_revert_reason(&output[..4 + 64 + 2]), None);
+
+        // Panic(0x01), i.e. a failed assert.
+        let output = hex_literal::hex!(
+            "4e487b71"
+            "0000000000000000000000000000000000000000000000000000000000000001"
+        );
+        assert_eq!(decode_revert_reason(&output), None);
+
+        assert_eq!(decode_revert_reason(&[]), None);
+    }
+
+    #[test]
+    fn empty_code_hash() {
+        assert_eq!(EMPTY_CODE_HASH, H256::from_slice(&Keccak256::digest(&[])));
+    }
+
+    #[test]
+    fn address_from_word() {
+        // The high 12 bytes are ignored, like for address operands of the instructions.
+        assert_eq!(
+            u256_to_address(U256::max_value()),
+            Address::repeat_byte(0xff)
+        );
+        assert_eq!(
+            u256_to_address((U256::from(0xdead) << 160) | U256::from(0x42)),
+            Address::from_low_u64_be(0x42)
+        );
+
+        assert_eq!(
+            address_to_u256(u256_to_address(U256::max_value())),
+            (U256::one() << 160) - 1
+        );
+    }
+
+    #[test]
+    fn contract_addresses() {
+        use hex_literal::hex;
+
+        let sender = Address::from(hex!("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0"));
+        for (nonce, address) in [
+            (0, hex!("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")),
+            (1, hex!("343c43a37d37dff08ae8c4a11544c718abb4fcf8")),
+            (2, hex!("f778b86fa74e846c4f0a1fbd1335fe81c00a0c91")),
+            (3, hex!("fffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c")),
+        ] {
+            assert_eq!(create_address(sender, nonce), Address::from(address));
+        }
+
+        // The deterministic deployment proxy, deployed by a keyless transaction.
+        assert_eq!(
+            create_address(hex!("3fab184622dc19b6109349b94811493bf2a45362").into(), 0),
+            hex!("4e59b44847b379578588920ca78fbf26c0b4956c").into()
+        );
+
+        // Examples from EIP-1014.
+        for (sender, salt, init_code, address) in [
+            (
+                hex!("0000000000000000000000000000000000000000"),
+                hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+                &hex!("00")[..],
+                hex!("4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"),
+            ),
+            (
+                hex!("deadbeef00000000000000000000000000000000"),
+                hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+                &hex!("00")[..],
+                hex!("b928f69bb1d91cd65274e3c79d8986362984fda3"),
+            ),
+            (
+                hex!("00000000000000000000000000000000deadbeef"),
+                hex!("00000000000000000000000000000000000000000000000000000000cafebabe"),
+                &hex!("deadbeef")[..],
+                hex!("60f3f640a8508fc6a86d45df051962668e1e8ac7"),
+            ),
+            (
+                hex!("0000000000000000000000000000000000000000"),
+                hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+                &hex!("")[..],
+                hex!("e33c0c7f7df4809055c3eba6c09cfe4baf1bd9e0"),
+            ),
+        ] {
+            let init_code_hash = H256::from_slice(&Keccak256::digest(init_code));
+            assert_eq!(
+                create2_address(sender.into(), salt.into(), init_code_hash),
+                address.into()
+            );
+        }
+    }
+}
@@ -9,7 +9,7 @@ macro_rules! interrupt {
                     dyn Coroutine<
                             Yield = InterruptDataVariant,
                             Resume = ResumeDataVariant,
-                            Return = Result<SuccessfulOutput, StatusCode>,
+                            Return = Result<SuccessfulOutput, FailedOutput>,
                         > + Send
                         + Sync
                         + Unpin,
@@ -45,6 +45,52 @@ interrupt! {
     InstructionStartInterrupt,
     Box<InstructionStart> => StateModifier
 }
+
+impl InstructionStartInterrupt {
+    /// Resume with an action that may mock the instruction instead of executing it, for test tools.
+    ///
+    /// ```
+    /// use ethereum_types::U256;
+    /// use evmodin::{
+    ///     continuation::{interrupt::*, resume_data::StepAction, Interrupt},
+    ///     opcode::OpCode,
+    ///     AnalyzedCode, Message, Revision,
+    /// };
+    ///
+    /// // Return the balance of 0xaa, answered without asking the host.
+    /// let code = AnalyzedCode::analyze(vec![
+    ///     OpCode::PUSH1.to_u8(), 0xaa,
+    ///     OpCode::BALANCE.to_u8(),
+    ///     OpCode::PUSH1.to_u8(), 0,
+    ///     OpCode::MSTORE.to_u8(),
+    ///     OpCode::PUSH1.to_u8(), 32,
+    ///     OpCode::PUSH1.to_u8(), 0,
+    ///     OpCode::RETURN.to_u8(),
+    /// ]);
+    ///
+    /// let mut interrupt = code
+    ///     .execute_resumable(true, Message::builder().build(), Revision::latest())
+    ///     .resume(());
+    /// let output = loop {
+    ///     interrupt = match interrupt {
+    ///         InterruptVariant::InstructionStart(i) if i.data().opcode == OpCode::BALANCE => {
+    ///             i.resume_with_action(StepAction::Override {
+    ///                 push: vec![1000.into()],
+    ///             })
+    ///         }
+    ///         InterruptVariant::InstructionStart(i) => i.resume(None),
+    ///         InterruptVariant::Complete(result) => break result.unwrap(),
+    ///         other => panic!("unexpected {}", <&'static str>::from(&other)),
+    ///     };
+    /// };
+    ///
+    /// assert_eq!(U256::from_big_endian(&output.output_data), 1000.into());
+    /// ```
+    pub fn resume_with_action(self, action: StepAction) -> InterruptVariant {
+        resume_interrupt(self.inner, action.into())
+    }
+}
+
 interrupt! {
     /// Does this account exist?
     AccountExistsInterrupt,
@@ -116,8 +162,75 @@ interrupt! {
     AccessStorage => AccessStorageStatus
 }
 
+interrupt! {
+    /// Access this storage key, return its status and value.
+    AccessAndGetStorageInterrupt,
+    AccessAndGetStorage => AccessedStorageValue
+}
+interrupt! {
+    /// Access this account, return its status and balance.
+    AccessAndGetBalanceInterrupt,
+    AccessAndGetBalance => AccessedBalance
+}
+interrupt! {
+    /// Frame is about to start, these storage slots will likely be read.
+    PrefetchStorageInterrupt,
+    PrefetchStorage => ()
+}
+
 /// Collection of all possible interrupts. Match on this to get the specific interrupt returned.
-#[derive(From)]
+///
+/// Each interrupt is resumed with the data of its own type, and returns the next one,
+/// until execution is `Complete`:
+///
+/// ```
+/// use ethereum_types::{Address, H256, U256};
+/// use evmodin::{
+///     continuation::{interrupt::*, resume_data::*, Interrupt},
+///     opcode::OpCode,
+///     *,
+/// };
+///
+/// // Return the storage value at key 7.
+/// let code = AnalyzedCode::analyze(vec![
+///     OpCode::PUSH1.to_u8(), 7,
+///     OpCode::SLOAD.to_u8(),
+///     OpCode::PUSH1.to_u8(), 0,
+///     OpCode::MSTORE.to_u8(),
+///     OpCode::PUSH1.to_u8(), 32,
+///     OpCode::PUSH1.to_u8(), 0,
+///     OpCode::RETURN.to_u8(),
+/// ]);
+/// let message = Message {
+///     kind: CallKind::Call,
+///     is_static: false,
+///     depth: 0,
+///     gas: 10000,
+///     destination: Address::zero(),
+///     sender: Address::zero(),
+///     input_data: vec![].into(),
+///     value: U256::zero(),
+/// };
+///
+/// let mut interrupt = code
+///     .execute_resumable(false, message, Revision::Istanbul)
+///     .resume(());
+/// let output = loop {
+///     interrupt = match interrupt {
+///         InterruptVariant::GetStorage(i) => {
+///             assert_eq!(i.data().key, H256::from_low_u64_be(7));
+///             i.resume(StorageValue {
+///                 value: H256::from_low_u64_be(42),
+///             })
+///         }
+///         InterruptVariant::Complete(result) => break result.unwrap(),
+///         other => panic!("unexpected {}", <&'static str>::from(&other)),
+///     };
+/// };
+///
+/// assert_eq!(U256::from_big_endian(&output.output_data), 42.into());
+/// ```
+#[derive(From, strum_macros::IntoStaticStr)]
 pub enum InterruptVariant {
     InstructionStart(InstructionStartInterrupt),
     AccountExists(AccountExistsInterrupt),
@@ -134,5 +247,8 @@ pub enum InterruptVariant {
     EmitLog(EmitLogInterrupt),
     AccessAccount(AccessAccountInterrupt),
     AccessStorage(AccessStorageInterrupt),
-    Complete(Result<SuccessfulOutput, StatusCode>),
+    AccessAndGetStorage(AccessAndGetStorageInterrupt),
+    AccessAndGetBalance(AccessAndGetBalanceInterrupt),
+    PrefetchStorage(PrefetchStorageInterrupt),
+    Complete(Result<SuccessfulOutput, FailedOutput>),
 }
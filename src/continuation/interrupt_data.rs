@@ -7,6 +7,14 @@ pub struct InstructionStart {
     pub state: ExecutionState,
 }
 
+impl InstructionStart {
+    /// Capture the frame-local state before this instruction, to roll back to it later with
+    /// [`StepAction::Restore`](super::resume_data::StepAction::Restore).
+    pub fn snapshot(&self) -> StateSnapshot {
+        self.state.snapshot(self.pc)
+    }
+}
+
 #[derive(Debug)]
 pub struct AccountExists {
     pub address: Address,
@@ -23,6 +31,8 @@ pub struct SetStorage {
     pub address: Address,
     pub key: H256,
     pub value: H256,
+    /// Write must not be committed. Resume value is ignored.
+    pub dry_run: bool,
 }
 
 #[derive(Debug)]
@@ -51,10 +61,25 @@ pub struct CopyCode {
 pub struct Selfdestruct {
     pub address: Address,
     pub beneficiary: Address,
+    /// Selfdestruct must not be committed.
+    pub dry_run: bool,
+}
+
+/// Instruction that started a call or create.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallScheme {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+    Create2,
 }
 
 #[derive(Debug)]
 pub struct Call {
+    /// Unlike `message.kind`, tells `STATICCALL` apart from `CALL` in a static context.
+    pub scheme: CallScheme,
     pub message: Message,
 }
 
@@ -68,6 +93,15 @@ pub struct EmitLog {
     pub address: Address,
     pub data: Bytes,
     pub topics: ArrayVec<H256, 4>,
+    /// Log must not be committed.
+    pub dry_run: bool,
+}
+
+/// Storage keys the frame is likely to read, found by static analysis of its code.
+#[derive(Debug)]
+pub struct PrefetchStorage {
+    pub address: Address,
+    pub keys: Vec<H256>,
 }
 
 #[derive(Debug)]
@@ -81,6 +115,17 @@ pub struct AccessStorage {
     pub key: H256,
 }
 
+#[derive(Debug)]
+pub struct AccessAndGetStorage {
+    pub address: Address,
+    pub key: H256,
+}
+
+#[derive(Debug)]
+pub struct AccessAndGetBalance {
+    pub address: Address,
+}
+
 #[derive(Debug)]
 pub enum InterruptDataVariant {
     InstructionStart(Box<InstructionStart>),
@@ -98,4 +143,7 @@ pub enum InterruptDataVariant {
     EmitLog(EmitLog),
     AccessAccount(AccessAccount),
     AccessStorage(AccessStorage),
+    AccessAndGetStorage(AccessAndGetStorage),
+    AccessAndGetBalance(AccessAndGetBalance),
+    PrefetchStorage(PrefetchStorage),
 }
@@ -36,12 +36,39 @@ pub trait Interrupt: sealed::Sealed {
     fn resume(self, resume_data: Self::ResumeData) -> InterruptVariant;
 }
 
+/// Result of running the interpreter for a limited number of instructions.
+///
+/// Returned by [`InterruptVariant::run_n_steps`] and its variants. Unlike a free
+/// `run_n_steps(state, host, n) -> (ExecutionState, RunResult)`, stepping resumes an interrupt: the
+/// execution state lives inside the paused coroutine and cannot be handed out by value without ending it.
+/// It is inspected through [`RunResult::state`] instead, and stepping continues by resuming the interrupt again.
+pub enum RunResult {
+    /// Execution has been paused before the next instruction. Its state can be inspected via `data()`.
+    Paused(InstructionStartInterrupt),
+    /// Execution has finished.
+    Halted(Output),
+    /// Execution requires input from the host to proceed.
+    NeedHostInput(InterruptVariant),
+}
+
+impl RunResult {
+    /// State of the paused execution, before the next instruction.
+    ///
+    /// `None` if execution has finished or waits for the host.
+    pub fn state(&self) -> Option<&ExecutionState> {
+        match self {
+            Self::Paused(i) => Some(&i.data().state),
+            Self::Halted(_) | Self::NeedHostInput(_) => None,
+        }
+    }
+}
+
 type InnerCoroutine = Pin<
     Box<
         dyn Coroutine<
                 Yield = InterruptDataVariant,
                 Resume = ResumeDataVariant,
-                Return = Result<SuccessfulOutput, StatusCode>,
+                Return = Result<SuccessfulOutput, FailedOutput>,
             > + Send
             + Sync
             + Unpin,
@@ -78,7 +105,65 @@ fn resume_interrupt(mut inner: InnerCoroutine, resume_data: ResumeDataVariant) -
             InterruptDataVariant::AccessStorage(data) => {
                 AccessStorageInterrupt { inner, data }.into()
             }
+            InterruptDataVariant::AccessAndGetStorage(data) => {
+                AccessAndGetStorageInterrupt { inner, data }.into()
+            }
+            InterruptDataVariant::AccessAndGetBalance(data) => {
+                AccessAndGetBalanceInterrupt { inner, data }.into()
+            }
+            InterruptDataVariant::PrefetchStorage(data) => {
+                PrefetchStorageInterrupt { inner, data }.into()
+            }
         },
         GeneratorState::Complete(res) => InterruptVariant::Complete(res),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(
+        debug_assertions,
+        should_panic(expected = "GetBalance interrupt answered with CodeSize")
+    )]
+    fn mismatched_resume_data() {
+        let code =
+            AnalyzedCode::analyze(vec![OpCode::PUSH1.to_u8(), 0xaa, OpCode::BALANCE.to_u8()]);
+        let message = Message {
+            kind: CallKind::Call,
+            is_static: false,
+            depth: 0,
+            gas: 10000,
+            destination: Address::zero(),
+            sender: Address::zero(),
+            input_data: Default::default(),
+            value: U256::zero(),
+        };
+
+        let interrupt = match code
+            .execute_resumable(false, message, Revision::Istanbul)
+            .resume(())
+        {
+            InterruptVariant::GetBalance(i) => i,
+            _ => panic!("expected balance interrupt"),
+        };
+
+        match resume_interrupt(
+            interrupt.inner,
+            CodeSize {
+                code_size: U256::zero(),
+            }
+            .into(),
+        ) {
+            InterruptVariant::Complete(Err(FailedOutput {
+                status_code: StatusCode::InternalError(message),
+                ..
+            })) => {
+                assert_eq!(message, "GetBalance interrupt answered with CodeSize")
+            }
+            _ => panic!("expected internal error"),
+        }
+    }
+}
@@ -4,6 +4,34 @@ use std::sync::Arc;
 
 pub type StateModifier = Option<Arc<dyn Fn(&mut ExecutionState) + Send + Sync>>;
 
+/// What to do with the instruction execution is paused at,
+/// see [`InstructionStartInterrupt::resume_with_action`](super::interrupt::InstructionStartInterrupt::resume_with_action).
+#[derive(Educe)]
+#[educe(Debug)]
+pub enum StepAction {
+    /// Execute the instruction, after applying the modifier if any. Same as resuming with the modifier.
+    Continue(#[educe(Debug(false))] StateModifier),
+    /// Do not execute the instruction, replace the stack items it requires with `push` instead.
+    ///
+    /// Stack limits are checked and the static gas cost is charged as usual. Nothing else the instruction
+    /// would have done happens: no dynamic gas, no memory expansion, no host interrupts.
+    Override { push: Vec<U256> },
+    /// End the frame before the instruction.
+    ///
+    /// `Success` and `Revert` end it as RETURN and REVERT would, with `output_data` and the gas left.
+    /// Any other status code fails the frame at this instruction, `output_data` is then ignored.
+    Halt {
+        status_code: StatusCode,
+        output_data: Bytes,
+    },
+    /// Roll back to a snapshot taken earlier in the same frame, and pause again at the instruction
+    /// it was taken at.
+    ///
+    /// Changes made through the host are not covered. Instructions executed since the snapshot still
+    /// count toward [`Config::max_steps`](crate::Config::max_steps).
+    Restore(StateSnapshot),
+}
+
 #[derive(Debug)]
 pub struct AccountExistsStatus {
     pub exists: bool,
@@ -64,13 +92,29 @@ pub struct AccessStorageStatus {
     pub status: AccessStatus,
 }
 
+#[derive(Debug)]
+pub struct AccessedStorageValue {
+    pub status: AccessStatus,
+    pub value: H256,
+}
+
+#[derive(Debug)]
+pub struct AccessedBalance {
+    pub status: AccessStatus,
+    pub balance: U256,
+}
+
 /// All resumed data variants.
-#[derive(Educe, EnumAsInner, From)]
+///
+/// Each interrupt is resumed with its own data type, so the interpreter always gets the variant it
+/// expects back. A mismatch is a bug in this crate, see [`ResumeDataVariant::mismatched`].
+#[derive(Educe, EnumAsInner, From, strum_macros::IntoStaticStr)]
 #[educe(Debug)]
 pub(crate) enum ResumeDataVariant {
     #[from(ignore)]
     Empty,
     StateModifier(#[educe(Debug(false))] StateModifier),
+    StepAction(StepAction),
     AccountExistsStatus(AccountExistsStatus),
     Balance(Balance),
     CodeSize(CodeSize),
@@ -83,9 +127,28 @@ pub(crate) enum ResumeDataVariant {
     CallOutput(CallOutput),
     AccessAccountStatus(AccessAccountStatus),
     AccessStorageStatus(AccessStorageStatus),
+    AccessedStorageValue(AccessedStorageValue),
+    AccessedBalance(AccessedBalance),
     Done(Infallible),
 }
 
+impl ResumeDataVariant {
+    /// Report that the interrupt was resumed with the wrong data.
+    ///
+    /// Panics in debug builds, execution fails with [`StatusCode::InternalError`] otherwise.
+    pub(crate) fn mismatched(self, interrupt: &'static str) -> StatusCode {
+        let message = format!(
+            "{} interrupt answered with {}",
+            interrupt,
+            <&'static str>::from(&self)
+        );
+        if cfg!(debug_assertions) {
+            panic!("{}", message);
+        }
+        StatusCode::InternalError(message)
+    }
+}
+
 impl From<()> for ResumeDataVariant {
     fn from(_: ()) -> Self {
         Self::Empty
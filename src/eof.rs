@@ -0,0 +1,219 @@
+//! [EIP-3540](https://eips.ethereum.org/EIPS/eip-3540) EOF container parsing and header validation.
+use bytes::Bytes;
+
+/// Magic bytes every EOF container starts with.
+pub const MAGIC: [u8; 2] = [0xef, 0x00];
+/// The only EOF version defined so far.
+pub const VERSION: u8 = 0x01;
+
+const KIND_TYPES: u8 = 0x01;
+const KIND_CODE: u8 = 0x02;
+const KIND_DATA: u8 = 0x04;
+const TERMINATOR: u8 = 0x00;
+
+/// Size of a single entry in the type section.
+const TYPE_ENTRY_SIZE: usize = 4;
+/// Maximum number of code sections in a container.
+pub const MAX_CODE_SECTIONS: usize = 1024;
+/// Output count marking a code section that never returns.
+pub const NON_RETURNING: u8 = 0x80;
+const MAX_STACK_HEIGHT: u16 = 0x03ff;
+
+/// Reason why bytes are not a valid EOF container.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EofError {
+    /// Container does not start with `0xEF00`.
+    InvalidMagic,
+    /// Version byte is not supported.
+    InvalidVersion(u8),
+    /// Container ends in the middle of the header.
+    IncompleteHeader,
+    /// Section header has an unexpected kind.
+    InvalidSectionKind { expected: u8, found: u8 },
+    /// Header is not terminated with `0x00`.
+    MissingTerminator,
+    /// Container has no code sections, or more than [MAX_CODE_SECTIONS].
+    InvalidCodeSectionCount(usize),
+    /// Code section of zero size.
+    EmptyCodeSection(usize),
+    /// Type section size does not match the number of code sections.
+    InvalidTypeSectionSize(usize),
+    /// Section sizes declared in the header do not add up to the container size.
+    InvalidContainerSize { expected: usize, found: usize },
+    /// First code section must take no inputs and never return.
+    InvalidFirstSectionType,
+    /// Type entry has too many inputs or outputs, or an out of range stack height.
+    InvalidType(usize),
+}
+
+impl std::fmt::Display for EofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "invalid EOF magic"),
+            Self::InvalidVersion(version) => write!(f, "unsupported EOF version {}", version),
+            Self::IncompleteHeader => write!(f, "incomplete EOF header"),
+            Self::InvalidSectionKind { expected, found } => write!(
+                f,
+                "expected section kind 0x{:02x}, found 0x{:02x}",
+                expected, found
+            ),
+            Self::MissingTerminator => write!(f, "missing EOF header terminator"),
+            Self::InvalidCodeSectionCount(count) => {
+                write!(f, "invalid number of code sections {}", count)
+            }
+            Self::EmptyCodeSection(index) => write!(f, "code section {} is empty", index),
+            Self::InvalidTypeSectionSize(size) => write!(f, "invalid type section size {}", size),
+            Self::InvalidContainerSize { expected, found } => write!(
+                f,
+                "container size {} does not match declared size {}",
+                found, expected
+            ),
+            Self::InvalidFirstSectionType => write!(f, "invalid type of first code section"),
+            Self::InvalidType(index) => write!(f, "invalid type of code section {}", index),
+        }
+    }
+}
+
+impl std::error::Error for EofError {}
+
+/// Type section entry, describing one code section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EofTypes {
+    pub inputs: u8,
+    pub outputs: u8,
+    pub max_stack_height: u16,
+}
+
+/// Parsed EOF container.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EofContainer {
+    pub version: u8,
+    pub types: Vec<EofTypes>,
+    pub code_sections: Vec<Bytes>,
+    pub data_section: Bytes,
+}
+
+/// Returns true if code is meant to be interpreted as an EOF container.
+pub fn is_eof(code: &[u8]) -> bool {
+    code.starts_with(&MAGIC)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, EofError> {
+        let v = *self.data.get(self.pos).ok_or(EofError::IncompleteHeader)?;
+        self.pos += 1;
+        Ok(v)
+    }
+
+    fn u16(&mut self) -> Result<u16, EofError> {
+        Ok(u16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn section_kind(&mut self, expected: u8) -> Result<(), EofError> {
+        let found = self.u8()?;
+        if found != expected {
+            return Err(EofError::InvalidSectionKind { expected, found });
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, size: usize) -> &'a [u8] {
+        let v = &self.data[self.pos..self.pos + size];
+        self.pos += size;
+        v
+    }
+}
+
+impl EofContainer {
+    /// Parse and validate the container header and section layout.
+    ///
+    /// Code sections are not validated.
+    pub fn parse(code: &[u8]) -> Result<Self, EofError> {
+        if !is_eof(code) {
+            return Err(EofError::InvalidMagic);
+        }
+
+        let mut r = Reader { data: code, pos: 2 };
+
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(EofError::InvalidVersion(version));
+        }
+
+        r.section_kind(KIND_TYPES)?;
+        let types_size = r.u16()? as usize;
+
+        r.section_kind(KIND_CODE)?;
+        let num_code_sections = r.u16()? as usize;
+        if num_code_sections == 0 || num_code_sections > MAX_CODE_SECTIONS {
+            return Err(EofError::InvalidCodeSectionCount(num_code_sections));
+        }
+        let mut code_sizes = Vec::with_capacity(num_code_sections);
+        for index in 0..num_code_sections {
+            let size = r.u16()? as usize;
+            if size == 0 {
+                return Err(EofError::EmptyCodeSection(index));
+            }
+            code_sizes.push(size);
+        }
+
+        r.section_kind(KIND_DATA)?;
+        let data_size = r.u16()? as usize;
+
+        if r.u8()? != TERMINATOR {
+            return Err(EofError::MissingTerminator);
+        }
+
+        if types_size != num_code_sections * TYPE_ENTRY_SIZE {
+            return Err(EofError::InvalidTypeSectionSize(types_size));
+        }
+
+        let expected = r.pos + types_size + code_sizes.iter().sum::<usize>() + data_size;
+        if code.len() != expected {
+            return Err(EofError::InvalidContainerSize {
+                expected,
+                found: code.len(),
+            });
+        }
+
+        let types = r
+            .take(types_size)
+            .chunks_exact(TYPE_ENTRY_SIZE)
+            .map(|entry| EofTypes {
+                inputs: entry[0],
+                outputs: entry[1],
+                max_stack_height: u16::from_be_bytes([entry[2], entry[3]]),
+            })
+            .collect::<Vec<_>>();
+
+        if types[0].inputs != 0 || types[0].outputs != NON_RETURNING {
+            return Err(EofError::InvalidFirstSectionType);
+        }
+        for (index, t) in types.iter().enumerate() {
+            if t.inputs > 0x7f
+                || (t.outputs > 0x7f && t.outputs != NON_RETURNING)
+                || t.max_stack_height > MAX_STACK_HEIGHT
+            {
+                return Err(EofError::InvalidType(index));
+            }
+        }
+
+        let code_sections = code_sizes
+            .into_iter()
+            .map(|size| Bytes::copy_from_slice(r.take(size)))
+            .collect();
+        let data_section = Bytes::copy_from_slice(r.take(data_size));
+
+        Ok(Self {
+            version,
+            types,
+            code_sections,
+            data_section,
+        })
+    }
+}
@@ -161,12 +161,15 @@ impl From<StatusCode> for evmc_status_code {
             StatusCode::PrecompileFailure => evmc_status_code::EVMC_PRECOMPILE_FAILURE,
             StatusCode::ArgumentOutOfRange => evmc_status_code::EVMC_ARGUMENT_OUT_OF_RANGE,
             StatusCode::InsufficientBalance => evmc_status_code::EVMC_INSUFFICIENT_BALANCE,
+            StatusCode::LogDataLimitExceeded => evmc_status_code::EVMC_FAILURE,
+            StatusCode::OutputSizeLimitExceeded => evmc_status_code::EVMC_FAILURE,
+            StatusCode::StepLimitExceeded => evmc_status_code::EVMC_FAILURE,
             StatusCode::InternalError(_) => evmc_status_code::EVMC_INTERNAL_ERROR,
         }
     }
 }
 
-impl<'a> Host for ExecutionContext<'a> {
+impl<'a> HostRead for ExecutionContext<'a> {
     fn account_exists(&self, address: Address) -> bool {
         ExecutionContext::account_exists(self, &address.convert())
     }
@@ -177,21 +180,6 @@ impl<'a> Host for ExecutionContext<'a> {
             .into()
     }
 
-    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus {
-        match ExecutionContext::set_storage(
-            self,
-            &address.convert(),
-            &key.convert(),
-            &value.convert(),
-        ) {
-            evmc_storage_status::EVMC_STORAGE_UNCHANGED => StorageStatus::Unchanged,
-            evmc_storage_status::EVMC_STORAGE_MODIFIED => StorageStatus::Modified,
-            evmc_storage_status::EVMC_STORAGE_MODIFIED_AGAIN => StorageStatus::ModifiedAgain,
-            evmc_storage_status::EVMC_STORAGE_ADDED => StorageStatus::Added,
-            evmc_storage_status::EVMC_STORAGE_DELETED => StorageStatus::Deleted,
-        }
-    }
-
     fn get_balance(&self, address: Address) -> U256 {
         ExecutionContext::get_balance(self, &address.convert())
             .bytes
@@ -212,21 +200,24 @@ impl<'a> Host for ExecutionContext<'a> {
         ExecutionContext::copy_code(self, &address.convert(), offset, buffer)
     }
 
-    fn selfdestruct(&mut self, address: Address, beneficiary: Address) {
-        ExecutionContext::selfdestruct(self, &address.convert(), &beneficiary.convert())
-    }
-
     fn call(&mut self, msg: &Message) -> Output {
         let execution_result = ExecutionContext::call(self, &msg.clone().into());
 
         Output {
             status_code: execution_result.status_code().into(),
             gas_left: execution_result.gas_left(),
+            // EVMC 9 results carry no refund.
+            gas_refund: 0,
             output_data: execution_result
                 .output()
                 .map(|v| v.to_vec().into())
                 .unwrap_or_else(Bytes::new),
             create_address: execution_result.create_address().map(|a| a.bytes.into()),
+            failure_location: None,
+            gas_breakdown: None,
+            opcode_trace: None,
+            access_list: None,
+            peak_memory: 0,
         }
     }
 
@@ -252,6 +243,35 @@ impl<'a> Host for ExecutionContext<'a> {
             .into()
     }
 
+    fn access_account(&mut self, address: Address) -> AccessStatus {
+        ExecutionContext::access_account(self, &address.convert()).into()
+    }
+
+    fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus {
+        ExecutionContext::access_storage(self, &address.convert(), &key.convert()).into()
+    }
+}
+
+impl<'a> HostWrite for ExecutionContext<'a> {
+    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus {
+        match ExecutionContext::set_storage(
+            self,
+            &address.convert(),
+            &key.convert(),
+            &value.convert(),
+        ) {
+            evmc_storage_status::EVMC_STORAGE_UNCHANGED => StorageStatus::Unchanged,
+            evmc_storage_status::EVMC_STORAGE_MODIFIED => StorageStatus::Modified,
+            evmc_storage_status::EVMC_STORAGE_MODIFIED_AGAIN => StorageStatus::ModifiedAgain,
+            evmc_storage_status::EVMC_STORAGE_ADDED => StorageStatus::Added,
+            evmc_storage_status::EVMC_STORAGE_DELETED => StorageStatus::Deleted,
+        }
+    }
+
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address) {
+        ExecutionContext::selfdestruct(self, &address.convert(), &beneficiary.convert())
+    }
+
     fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]) {
         ExecutionContext::emit_log(
             self,
@@ -263,14 +283,6 @@ impl<'a> Host for ExecutionContext<'a> {
                 .collect::<ArrayVec<_, 4>>(),
         )
     }
-
-    fn access_account(&mut self, address: Address) -> AccessStatus {
-        ExecutionContext::access_account(self, &address.convert()).into()
-    }
-
-    fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus {
-        ExecutionContext::access_storage(self, &address.convert(), &key.convert()).into()
-    }
 }
 
 impl From<evmc_vm::Revision> for Revision {
@@ -304,7 +316,11 @@ impl From<Revision> for evmc_vm::Revision {
             Revision::Istanbul => evmc_revision::EVMC_ISTANBUL,
             Revision::Berlin => evmc_revision::EVMC_BERLIN,
             Revision::London => evmc_revision::EVMC_LONDON,
+            // EVMC does not distinguish Paris, execution rules are the same as in London.
+            Revision::Paris => evmc_revision::EVMC_LONDON,
             Revision::Shanghai => evmc_revision::EVMC_SHANGHAI,
+            // Not known to EVMC yet.
+            Revision::Cancun => evmc_revision::EVMC_SHANGHAI,
         }
     }
 }
@@ -1,5 +1,10 @@
-use crate::common::{Message, Output};
+use crate::common::{is_mainnet_precompile, Message, Output, Revision, StatusCode};
+use educe::Educe;
 use ethereum_types::{Address, H256, U256};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 /// State access status (EIP-2929).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -14,18 +19,71 @@ impl Default for AccessStatus {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Effect of a storage write, from the value at the start of the transaction, the current value and the new one.
+///
+/// Writes to a slot already modified in the transaction have their own variants where the refund changes
+/// (EIP-2200). Hosts that report [`StorageStatus::ModifiedAgain`] for all of them still get the gas right,
+/// but not [`Output::gas_refund`](crate::Output::gas_refund).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum StorageStatus {
     /// The value of a storage item has been left unchanged: 0 -> 0 and X -> X.
     Unchanged,
     /// The value of a storage item has been modified: X -> Y.
     Modified,
-    /// A storage item has been modified after being modified before: X -> Y -> Z.
+    /// A storage item has been modified after being modified before, without deleting or restoring it:
+    /// X -> Y -> Z and 0 -> Y -> Z.
     ModifiedAgain,
     /// A new storage item has been added: 0 -> X.
     Added,
     /// A storage item has been deleted: X -> 0.
     Deleted,
+    /// A deleted storage item has been added again: X -> 0 -> Z.
+    DeletedAdded,
+    /// A modified storage item has been deleted: X -> Y -> 0.
+    ModifiedDeleted,
+    /// A deleted storage item has been restored to its original value: X -> 0 -> X.
+    DeletedRestored,
+    /// An added storage item has been deleted: 0 -> Y -> 0.
+    AddedDeleted,
+    /// A modified storage item has been restored to its original value: X -> Y -> X.
+    ModifiedRestored,
+}
+
+impl StorageStatus {
+    /// Status of writing `value` to a slot holding `current`, and `original` at the start of the transaction.
+    pub fn new(original: H256, current: H256, value: H256) -> Self {
+        if current == value {
+            return Self::Unchanged;
+        }
+
+        if original == current {
+            return if original.is_zero() {
+                Self::Added
+            } else if value.is_zero() {
+                Self::Deleted
+            } else {
+                Self::Modified
+            };
+        }
+
+        if !original.is_zero() && current.is_zero() {
+            if value == original {
+                Self::DeletedRestored
+            } else {
+                Self::DeletedAdded
+            }
+        } else if !original.is_zero() && value.is_zero() {
+            Self::ModifiedDeleted
+        } else if value == original {
+            if original.is_zero() {
+                Self::AddedDeleted
+            } else {
+                Self::ModifiedRestored
+            }
+        } else {
+            Self::ModifiedAgain
+        }
+    }
 }
 
 /// The transaction and block data for execution.
@@ -44,6 +102,8 @@ pub struct TxContext {
     /// The block gas limit.
     pub block_gas_limit: u64,
     /// The block difficulty.
+    ///
+    /// Since Paris this carries the PREVRANDAO beacon value (EIP-4399), which may be any 256-bit value.
     pub block_difficulty: U256,
     /// The blockchain's ChainID.
     pub chain_id: U256,
@@ -51,16 +111,152 @@ pub struct TxContext {
     pub block_base_fee: U256,
 }
 
+impl TxContext {
+    /// Start building a context with every field zeroed.
+    pub fn builder() -> TxContextBuilder {
+        TxContextBuilder::default()
+    }
+
+    /// Set the ChainID from the common `u64` representation.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
+
+/// Builder for [`TxContext`].
+///
+/// Fields that are not set stay zero, so the base fee only needs to be set from London on.
+///
+/// ```
+/// use evmodin::host::TxContext;
+/// use ethereum_types::{Address, U256};
+///
+/// let london = TxContext::builder()
+///     .origin(Address::from_low_u64_be(0xaa))
+///     .gas_price(30_000_000_000_u64)
+///     .number(12_965_000)
+///     .timestamp(1_628_166_822)
+///     .gas_limit(30_000_000)
+///     .chain_id(1)
+///     .base_fee(1_000_000_000)
+///     .build();
+///
+/// assert_eq!(london.block_base_fee, U256::from(1_000_000_000));
+/// assert_eq!(london.chain_id, U256::one());
+///
+/// let homestead = TxContext::builder()
+///     .number(1_150_000)
+///     .difficulty(17_179_869_184_u64)
+///     .build();
+///
+/// assert!(homestead.block_base_fee.is_zero());
+/// assert!(homestead.tx_origin.is_zero());
+/// ```
+#[derive(Clone, Debug)]
+pub struct TxContextBuilder {
+    context: TxContext,
+}
+
+impl Default for TxContextBuilder {
+    fn default() -> Self {
+        Self {
+            context: TxContext {
+                tx_gas_price: U256::zero(),
+                tx_origin: Address::zero(),
+                block_coinbase: Address::zero(),
+                block_number: 0,
+                block_timestamp: 0,
+                block_gas_limit: 0,
+                block_difficulty: U256::zero(),
+                chain_id: U256::zero(),
+                block_base_fee: U256::zero(),
+            },
+        }
+    }
+}
+
+impl TxContextBuilder {
+    pub fn gas_price(mut self, gas_price: impl Into<U256>) -> Self {
+        self.context.tx_gas_price = gas_price.into();
+        self
+    }
+
+    pub fn origin(mut self, origin: impl Into<Address>) -> Self {
+        self.context.tx_origin = origin.into();
+        self
+    }
+
+    pub fn coinbase(mut self, coinbase: impl Into<Address>) -> Self {
+        self.context.block_coinbase = coinbase.into();
+        self
+    }
+
+    pub fn number(mut self, number: u64) -> Self {
+        self.context.block_number = number;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.context.block_timestamp = timestamp;
+        self
+    }
+
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.context.block_gas_limit = gas_limit;
+        self
+    }
+
+    pub fn difficulty(mut self, difficulty: impl Into<U256>) -> Self {
+        self.context.block_difficulty = difficulty.into();
+        self
+    }
+
+    /// Set the PREVRANDAO value, which replaces the difficulty since Paris.
+    pub fn prev_randao(mut self, prev_randao: H256) -> Self {
+        self.context.block_difficulty = U256::from_big_endian(prev_randao.as_bytes());
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: impl Into<U256>) -> Self {
+        self.context.chain_id = chain_id.into();
+        self
+    }
+
+    pub fn base_fee(mut self, base_fee: impl Into<U256>) -> Self {
+        self.context.block_base_fee = base_fee.into();
+        self
+    }
+
+    pub fn build(self) -> TxContext {
+        self.context
+    }
+}
+
 /// Abstraction that exposes host context to EVM.
-pub trait Host {
+///
+/// Implemented for every host that can both be read from and written to.
+pub trait Host: HostRead + HostWrite {}
+
+impl<T: HostRead + HostWrite> Host for T {}
+
+/// Part of the host that EVM queries without changing the state.
+///
+/// This is enough to run in [dry run mode](crate::Config::dry_run), with two caveats for the methods
+/// that take `&mut self`:
+///
+/// - [`HostRead::call`] hands sub-calls to the host, which must execute them without committing
+///   their changes either, for example with
+///   [`AnalyzedCode::execute_dry_run`](crate::AnalyzedCode::execute_dry_run). Nothing enforces this.
+/// - The `access_*` methods update the set of warm accounts and storage keys (EIP-2929). This set lives
+///   for the transaction only and is not part of the state.
+pub trait HostRead {
     /// Check if an account exists.
     fn account_exists(&self, address: Address) -> bool;
     /// Get value of a storage key.
     ///
     /// Returns `Ok(H256::zero())` if does not exist.
     fn get_storage(&self, address: Address, key: H256) -> H256;
-    /// Set value of a storage key.
-    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus;
     /// Get balance of an account.
     ///
     /// Returns `Ok(0)` if account does not exist.
@@ -77,8 +273,6 @@ pub trait Host {
     ///
     /// Returns `Ok(0)` if offset is invalid.
     fn copy_code(&self, address: Address, offset: usize, buffer: &mut [u8]) -> usize;
-    /// Self-destruct account.
-    fn selfdestruct(&mut self, address: Address, beneficiary: Address);
     /// Call to another account.
     fn call(&mut self, msg: &Message) -> Output;
     /// Retrieve transaction context.
@@ -87,8 +281,6 @@ pub trait Host {
     ///
     /// Returns `Ok(H256::zero())` if block does not exist.
     fn get_block_hash(&self, block_number: u64) -> H256;
-    /// Emit a log.
-    fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]);
     /// Mark account as warm, return previous access status.
     ///
     /// Returns `Ok(AccessStatus::Cold)` if account does not exist.
@@ -97,12 +289,58 @@ pub trait Host {
     ///
     /// Returns `Ok(AccessStatus::Cold)` if account does not exist.
     fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus;
+    /// Mark storage key as warm, return previous access status and value of the key.
+    ///
+    /// Hosts that can answer both in one go should override this.
+    fn access_and_get_storage(&mut self, address: Address, key: H256) -> (AccessStatus, H256) {
+        let status = self.access_storage(address, key);
+        (status, self.get_storage(address, key))
+    }
+    /// Mark account as warm, return previous access status and balance of the account.
+    ///
+    /// Hosts that can answer both in one go should override this.
+    fn access_and_get_balance(&mut self, address: Address) -> (AccessStatus, U256) {
+        let status = self.access_account(address);
+        (status, self.get_balance(address))
+    }
+    /// Access status of the account, without marking it as warm.
+    ///
+    /// Only used to predict gas costs. Hosts that can not tell without side effects return `None`, the default.
+    fn peek_account_access(&self, _address: Address) -> Option<AccessStatus> {
+        None
+    }
+    /// Access status of the storage key, without marking it as warm.
+    ///
+    /// Only used to predict gas costs. Hosts that can not tell without side effects return `None`, the default.
+    fn peek_storage_access(&self, _address: Address, _key: H256) -> Option<AccessStatus> {
+        None
+    }
+    /// Decide whether a call or create may be dispatched.
+    ///
+    /// Denied messages fail without reaching [`HostRead::call`], consuming all gas given to them.
+    fn authorize_call(&mut self, _msg: &Message) -> bool {
+        true
+    }
+    /// Hint that storage slots of the account are about to be read, for hosts that can fetch them in one go.
+    ///
+    /// Only sent with [`Config::prefetch_storage`](crate::Config::prefetch_storage).
+    fn prefetch_storage(&mut self, _address: Address, _keys: &[H256]) {}
+}
+
+/// Part of the host that changes the state.
+pub trait HostWrite {
+    /// Set value of a storage key.
+    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus;
+    /// Self-destruct account.
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address);
+    /// Emit a log.
+    fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]);
 }
 
 /// Host that does not support any ops.
 pub struct DummyHost;
 
-impl Host for DummyHost {
+impl HostRead for DummyHost {
     fn account_exists(&self, _: Address) -> bool {
         todo!()
     }
@@ -111,10 +349,6 @@ impl Host for DummyHost {
         todo!()
     }
 
-    fn set_storage(&mut self, _: Address, _: H256, _: H256) -> StorageStatus {
-        todo!()
-    }
-
     fn get_balance(&self, _: Address) -> U256 {
         todo!()
     }
@@ -131,10 +365,6 @@ impl Host for DummyHost {
         todo!()
     }
 
-    fn selfdestruct(&mut self, _: Address, _: Address) {
-        todo!()
-    }
-
     fn call(&mut self, _: &Message) -> Output {
         todo!()
     }
@@ -147,15 +377,358 @@ impl Host for DummyHost {
         todo!()
     }
 
-    fn emit_log(&mut self, _: Address, _: &[u8], _: &[H256]) {
+    fn access_account(&mut self, _: Address) -> AccessStatus {
         todo!()
     }
 
-    fn access_account(&mut self, _: Address) -> AccessStatus {
+    fn access_storage(&mut self, _: Address, _: H256) -> AccessStatus {
+        todo!()
+    }
+}
+
+impl HostWrite for DummyHost {
+    fn set_storage(&mut self, _: Address, _: H256, _: H256) -> StorageStatus {
         todo!()
     }
 
-    fn access_storage(&mut self, _: Address, _: H256) -> AccessStatus {
+    fn selfdestruct(&mut self, _: Address, _: Address) {
+        todo!()
+    }
+
+    fn emit_log(&mut self, _: Address, _: &[u8], _: &[H256]) {
         todo!()
     }
 }
+
+/// Executes a nested frame on behalf of [`AccessTrackingHost`], which must be the host of the frame.
+pub type FrameExecutor<H> =
+    Arc<dyn Fn(&mut AccessTrackingHost<H>, &Message) -> Output + Send + Sync>;
+
+/// Host wrapper that keeps track of warm accounts and storage keys (EIP-2929) by itself.
+///
+/// Access queries are answered from the internal access set, everything else is delegated to the wrapped host.
+/// The set should live for the duration of one transaction.
+///
+/// Nested frames only share the set if they are executed with the wrapper as their host, see
+/// [`AccessTrackingHost::execute_frames_with`]. Accesses made by a frame that fails or reverts are
+/// forgotten when it returns.
+#[derive(Clone, Educe)]
+#[educe(Debug)]
+pub struct AccessTrackingHost<H> {
+    inner: H,
+    accessed: HashSet<(Address, Option<H256>)>,
+    /// Entries added to `accessed`, in order, so that failed frames can be rolled back.
+    journal: Vec<(Address, Option<H256>)>,
+    #[educe(Debug(false))]
+    executor: Option<FrameExecutor<H>>,
+}
+
+impl<H: HostRead> AccessTrackingHost<H> {
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            accessed: HashSet::new(),
+            journal: Vec::new(),
+            executor: None,
+        }
+    }
+
+    /// Execute nested frames with `executor` instead of handing them to the wrapped host.
+    ///
+    /// The executor gets the wrapper to execute the frame with, so that it shares the access set,
+    /// and is responsible for everything else the wrapped host would do, like value transfers.
+    pub fn execute_frames_with(
+        &mut self,
+        executor: impl Fn(&mut AccessTrackingHost<H>, &Message) -> Output + Send + Sync + 'static,
+    ) {
+        self.executor = Some(Arc::new(executor));
+    }
+
+    /// Warm up the accounts that are accessed before execution starts:
+    /// the sender, the destination, precompiles and, since Shanghai (EIP-3651), the coinbase.
+    pub fn prewarm_transaction(
+        &mut self,
+        revision: Revision,
+        sender: Address,
+        destination: Address,
+    ) {
+        self.warm_account(sender);
+        self.warm_account(destination);
+        for precompile in (1..=0x0a).map(Address::from_low_u64_be) {
+            if is_mainnet_precompile(precompile, revision) {
+                self.warm_account(precompile);
+            }
+        }
+        if revision >= Revision::Shanghai {
+            self.warm_account(self.inner.get_tx_context().block_coinbase);
+        }
+    }
+
+    /// Mark account as warm, e.g. from the transaction access list.
+    pub fn warm_account(&mut self, address: Address) {
+        self.access((address, None));
+    }
+
+    /// Mark storage key as warm, e.g. from the transaction access list.
+    pub fn warm_storage(&mut self, address: Address, key: H256) {
+        self.access((address, Some(key)));
+    }
+
+    /// Forget all accesses, e.g. before the next transaction.
+    pub fn clear_accesses(&mut self) {
+        self.accessed.clear();
+        self.journal.clear();
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+
+    fn status(&self, entry: (Address, Option<H256>)) -> AccessStatus {
+        if self.accessed.contains(&entry) {
+            AccessStatus::Warm
+        } else {
+            AccessStatus::Cold
+        }
+    }
+
+    fn access(&mut self, entry: (Address, Option<H256>)) -> AccessStatus {
+        if self.accessed.insert(entry) {
+            self.journal.push(entry);
+            AccessStatus::Cold
+        } else {
+            AccessStatus::Warm
+        }
+    }
+}
+
+impl<H: HostRead> HostRead for AccessTrackingHost<H> {
+    fn account_exists(&self, address: Address) -> bool {
+        self.inner.account_exists(address)
+    }
+
+    fn get_storage(&self, address: Address, key: H256) -> H256 {
+        self.inner.get_storage(address, key)
+    }
+
+    fn get_balance(&self, address: Address) -> U256 {
+        self.inner.get_balance(address)
+    }
+
+    fn get_code_size(&self, address: Address) -> U256 {
+        self.inner.get_code_size(address)
+    }
+
+    fn get_code_hash(&self, address: Address) -> H256 {
+        self.inner.get_code_hash(address)
+    }
+
+    fn copy_code(&self, address: Address, offset: usize, buffer: &mut [u8]) -> usize {
+        self.inner.copy_code(address, offset, buffer)
+    }
+
+    fn call(&mut self, msg: &Message) -> Output {
+        let checkpoint = self.journal.len();
+        let output = match self.executor.clone() {
+            Some(executor) => executor(self, msg),
+            None => self.inner.call(msg),
+        };
+        if output.status_code != StatusCode::Success {
+            for entry in self.journal.drain(checkpoint..) {
+                self.accessed.remove(&entry);
+            }
+        }
+        output
+    }
+
+    fn authorize_call(&mut self, msg: &Message) -> bool {
+        self.inner.authorize_call(msg)
+    }
+
+    fn prefetch_storage(&mut self, address: Address, keys: &[H256]) {
+        self.inner.prefetch_storage(address, keys)
+    }
+
+    fn get_tx_context(&self) -> TxContext {
+        self.inner.get_tx_context()
+    }
+
+    fn get_block_hash(&self, block_number: u64) -> H256 {
+        self.inner.get_block_hash(block_number)
+    }
+
+    fn access_account(&mut self, address: Address) -> AccessStatus {
+        self.access((address, None))
+    }
+
+    fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus {
+        self.access((address, Some(key)))
+    }
+
+    fn peek_account_access(&self, address: Address) -> Option<AccessStatus> {
+        Some(self.status((address, None)))
+    }
+
+    fn peek_storage_access(&self, address: Address, key: H256) -> Option<AccessStatus> {
+        Some(self.status((address, Some(key))))
+    }
+}
+
+impl<H: HostWrite> HostWrite for AccessTrackingHost<H> {
+    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus {
+        self.inner.set_storage(address, key, value)
+    }
+
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address) {
+        self.inner.selfdestruct(address, beneficiary)
+    }
+
+    fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]) {
+        self.inner.emit_log(address, data, topics)
+    }
+}
+
+/// Precompile implementation. Gets the wrapped host to read or change the state, and the call message.
+pub type Precompile<H> = Arc<dyn Fn(&mut H, &Message) -> Output + Send + Sync>;
+
+/// Host wrapper that executes calls to registered precompiles by itself.
+///
+/// Everything else, including calls to other precompiles, is delegated to the wrapped host.
+/// Registered addresses should also be reported by [`Config::is_precompile`](crate::Config::is_precompile), so that they are warm.
+#[derive(Clone, Educe)]
+#[educe(Debug)]
+pub struct PrecompileHost<H> {
+    inner: H,
+    #[educe(Debug(false))]
+    precompiles: HashMap<Address, Precompile<H>>,
+}
+
+impl<H: HostRead> PrecompileHost<H> {
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            precompiles: HashMap::new(),
+        }
+    }
+
+    /// Execute calls to `address` with `precompile`, replacing the previous one if any.
+    pub fn register(
+        &mut self,
+        address: Address,
+        precompile: impl Fn(&mut H, &Message) -> Output + Send + Sync + 'static,
+    ) {
+        self.precompiles.insert(address, Arc::new(precompile));
+    }
+
+    /// Whether calls to `address` are executed by a registered precompile.
+    pub fn is_registered(&self, address: Address) -> bool {
+        self.precompiles.contains_key(&address)
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H: HostRead> HostRead for PrecompileHost<H> {
+    fn account_exists(&self, address: Address) -> bool {
+        self.inner.account_exists(address)
+    }
+
+    fn get_storage(&self, address: Address, key: H256) -> H256 {
+        self.inner.get_storage(address, key)
+    }
+
+    fn get_balance(&self, address: Address) -> U256 {
+        self.inner.get_balance(address)
+    }
+
+    fn get_code_size(&self, address: Address) -> U256 {
+        self.inner.get_code_size(address)
+    }
+
+    fn get_code_hash(&self, address: Address) -> H256 {
+        self.inner.get_code_hash(address)
+    }
+
+    fn copy_code(&self, address: Address, offset: usize, buffer: &mut [u8]) -> usize {
+        self.inner.copy_code(address, offset, buffer)
+    }
+
+    fn call(&mut self, msg: &Message) -> Output {
+        match self.precompiles.get(&msg.destination) {
+            Some(precompile) => precompile(&mut self.inner, msg),
+            None => self.inner.call(msg),
+        }
+    }
+
+    fn authorize_call(&mut self, msg: &Message) -> bool {
+        self.inner.authorize_call(msg)
+    }
+
+    fn prefetch_storage(&mut self, address: Address, keys: &[H256]) {
+        self.inner.prefetch_storage(address, keys)
+    }
+
+    fn get_tx_context(&self) -> TxContext {
+        self.inner.get_tx_context()
+    }
+
+    fn get_block_hash(&self, block_number: u64) -> H256 {
+        self.inner.get_block_hash(block_number)
+    }
+
+    fn access_account(&mut self, address: Address) -> AccessStatus {
+        self.inner.access_account(address)
+    }
+
+    fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus {
+        self.inner.access_storage(address, key)
+    }
+
+    fn access_and_get_storage(&mut self, address: Address, key: H256) -> (AccessStatus, H256) {
+        self.inner.access_and_get_storage(address, key)
+    }
+
+    fn access_and_get_balance(&mut self, address: Address) -> (AccessStatus, U256) {
+        self.inner.access_and_get_balance(address)
+    }
+
+    fn peek_account_access(&self, address: Address) -> Option<AccessStatus> {
+        self.inner.peek_account_access(address)
+    }
+
+    fn peek_storage_access(&self, address: Address, key: H256) -> Option<AccessStatus> {
+        self.inner.peek_storage_access(address, key)
+    }
+}
+
+impl<H: HostWrite> HostWrite for PrecompileHost<H> {
+    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus {
+        self.inner.set_storage(address, key, value)
+    }
+
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address) {
+        self.inner.selfdestruct(address, beneficiary)
+    }
+
+    fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]) {
+        self.inner.emit_log(address, data, topics)
+    }
+}
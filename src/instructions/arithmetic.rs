@@ -3,10 +3,26 @@ use core::convert::TryInto;
 use ethereum_types::{U256, U512};
 use i256::I256;
 
+/// Value of the lowest limb if the rest are zero.
+#[inline]
+pub(crate) fn as_small(v: &U256) -> Option<u64> {
+    if v.0[1] == 0 && v.0[2] == 0 && v.0[3] == 0 {
+        Some(v.0[0])
+    } else {
+        None
+    }
+}
+
 pub(crate) fn add(stack: &mut Stack) {
     let a = stack.pop();
     let b = stack.pop();
-    stack.push(a.overflowing_add(b).0);
+    stack.push(match (as_small(&a), as_small(&b)) {
+        (Some(a), Some(b)) => {
+            let (v, carry) = a.overflowing_add(b);
+            U256([v, carry as u64, 0, 0])
+        }
+        _ => a.overflowing_add(b).0,
+    });
 }
 
 pub(crate) fn mul(stack: &mut Stack) {
@@ -18,7 +34,10 @@ pub(crate) fn mul(stack: &mut Stack) {
 pub(crate) fn sub(stack: &mut Stack) {
     let a = stack.pop();
     let b = stack.pop();
-    stack.push(a.overflowing_sub(b).0);
+    stack.push(match (as_small(&a), as_small(&b)) {
+        (Some(a), Some(b)) if a >= b => (a - b).into(),
+        _ => a.overflowing_sub(b).0,
+    });
 }
 
 pub(crate) fn div(stack: &mut Stack) {
@@ -104,22 +123,25 @@ fn log2floor(value: U256) -> u64 {
     l
 }
 
-pub(crate) fn exp(state: &mut ExecutionState) -> Result<(), StatusCode> {
+/// Cost of EXP on top of the base cost, by number of bytes in the exponent.
+pub(crate) fn exp_cost(power: U256, revision: Revision) -> i64 {
+    if power.is_zero() {
+        return 0;
+    }
+
+    (if revision >= Revision::Spurious {
+        50
+    } else {
+        10
+    }) * (log2floor(power) / 8 + 1) as i64
+}
+
+pub(crate) fn exp<R: Rules>(state: &mut ExecutionState) -> Result<(), StatusCode> {
     let mut base = state.stack.pop();
     let mut power = state.stack.pop();
 
     if !power.is_zero() {
-        let additional_gas = if state.evm_revision >= Revision::Spurious {
-            50
-        } else {
-            10
-        } * (log2floor(power) / 8 + 1);
-
-        state.gas_left -= additional_gas as i64;
-
-        if state.gas_left < 0 {
-            return Err(StatusCode::OutOfGas);
-        }
+        state.charge(exp_cost(power, R::revision(state)))?;
     }
 
     let mut v = U256::one();
@@ -1,3 +1,4 @@
+use super::arithmetic::as_small;
 use crate::state::*;
 use ethereum_types::U256;
 use i256::I256;
@@ -6,14 +7,24 @@ pub(crate) fn lt(stack: &mut Stack) {
     let a = stack.pop();
     let b = stack.pop();
 
-    stack.push(if a.lt(&b) { U256::one() } else { U256::zero() })
+    let v = match (as_small(&a), as_small(&b)) {
+        (Some(a), Some(b)) => a < b,
+        _ => a.lt(&b),
+    };
+
+    stack.push(if v { U256::one() } else { U256::zero() })
 }
 
 pub(crate) fn gt(stack: &mut Stack) {
     let a = stack.pop();
     let b = stack.pop();
 
-    stack.push(if a.gt(&b) { U256::one() } else { U256::zero() })
+    let v = match (as_small(&a), as_small(&b)) {
+        (Some(a), Some(b)) => a > b,
+        _ => a.gt(&b),
+    };
+
+    stack.push(if v { U256::one() } else { U256::zero() })
 }
 
 pub(crate) fn slt(stack: &mut Stack) {
@@ -1,13 +1,14 @@
 #[doc(hidden)]
 #[macro_export]
 macro_rules! do_call {
-    ($co:expr, $state:expr, $kind:expr, $is_static:expr) => {{
+    ($co:expr, $state:expr, $rules:ty, $kind:expr, $is_static:expr) => {{
         use std::cmp::min;
         use $crate::{
             common::u256_to_address,
             continuation::{interrupt_data::*, resume_data::*},
             host::AccessStatus,
-            instructions::{memory::MemoryRegion, properties::*},
+            instructions::memory::MemoryRegion,
+            state::Rules,
             CallKind, Message,
         };
 
@@ -26,21 +27,21 @@ macro_rules! do_call {
 
         $state.stack.push(U256::zero()); // Assume failure.
 
-        if $state.evm_revision >= Revision::Berlin
+        $state.record_access(dst, None);
+
+        if <$rules as Rules>::eip2929($state)
+            && !($state.config.is_precompile)(dst, <$rules as Rules>::revision($state))
             && ResumeDataVariant::into_access_account_status(
                 $co.yield_(InterruptDataVariant::AccessAccount(AccessAccount {
                     address: dst,
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccessAccount"))?
             .status
                 == AccessStatus::Cold
         {
-            $state.gas_left -= i64::from(ADDITIONAL_COLD_ACCOUNT_ACCESS_COST);
-            if $state.gas_left < 0 {
-                return Err(StatusCode::OutOfGas);
-            }
+            $state.charge($state.spec.access_costs.additional_cold_account_access())?;
         }
 
         let input_region = memory::verify_memory_region($state, input_offset, input_size)
@@ -78,36 +79,38 @@ macro_rules! do_call {
                 return Err(StatusCode::StaticModeViolation);
             }
 
-            if (has_value || $state.evm_revision < Revision::Spurious)
+            // The executing account exists by definition, so calling self never creates an account.
+            if (has_value || <$rules as Rules>::revision($state) < Revision::Spurious)
+                && dst != $state.message.destination
                 && !ResumeDataVariant::into_account_exists_status(
                     $co.yield_(InterruptDataVariant::AccountExists(AccountExists {
                         address: dst,
                     }))
                     .await,
                 )
-                .unwrap()
+                .map_err(|other| other.mismatched("AccountExists"))?
                 .exists
             {
                 cost += 25000;
             }
         }
-        $state.gas_left -= cost;
-        if $state.gas_left < 0 {
-            return Err(StatusCode::OutOfGas);
-        }
+        $state.charge(cost)?;
 
         if gas < msg.gas.into() {
             msg.gas = gas.as_usize() as i64;
         }
 
-        if $state.evm_revision >= Revision::Tangerine {
+        if !$state.config.metering {
+            // The callee gets the gas asked for, up to the gas of this frame.
+            msg.gas = min(msg.gas, $state.gas_left);
+        } else if <$rules as Rules>::revision($state) >= Revision::Tangerine {
             // TODO: Always true for STATICCALL.
             msg.gas = min(msg.gas, $state.gas_left - $state.gas_left / 64);
         } else if msg.gas > $state.gas_left {
             return Err(StatusCode::OutOfGas);
         }
 
-        if has_value {
+        if has_value && $state.config.metering {
             msg.gas += 2300; // Add stipend.
             $state.gas_left += 2300;
         }
@@ -122,17 +125,27 @@ macro_rules! do_call {
                     }))
                     .await,
                 )
-                .unwrap()
+                .map_err(|other| other.mismatched("GetBalance"))?
                 .balance
                     < value)
         {
             let msg_gas = msg.gas;
             let result = ResumeDataVariant::into_call_output(
-                $co.yield_(InterruptDataVariant::Call(Call { message: msg }))
-                    .await,
+                $co.yield_(InterruptDataVariant::Call(Call {
+                    scheme: match $kind {
+                        CallKind::Call if $is_static => CallScheme::StaticCall,
+                        CallKind::Call => CallScheme::Call,
+                        CallKind::CallCode => CallScheme::CallCode,
+                        CallKind::DelegateCall => CallScheme::DelegateCall,
+                        CallKind::Create | CallKind::Create2 { .. } => unreachable!(),
+                    },
+                    message: msg,
+                }))
+                .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("Call"))?
             .output;
+            $state.record_nested_accesses(&result.access_list);
             $state.return_data = result.output_data.clone();
             *$state.stack.get_mut(0) = if matches!(result.status_code, StatusCode::Success) {
                 U256::one()
@@ -148,8 +161,11 @@ macro_rules! do_call {
                 }
             }
 
-            let gas_used = msg_gas - result.gas_left;
-            $state.gas_left -= gas_used;
+            if $state.config.metering {
+                let gas_used = msg_gas - result.gas_left;
+                $state.gas_left -= gas_used;
+                $state.gas_refund += result.gas_refund;
+            }
         }
     }};
 }
@@ -181,10 +197,7 @@ macro_rules! do_create {
 
             if let Some(region) = &region {
                 let salt_cost = memory::num_words(region.size.get()) * 6;
-                $state.gas_left -= salt_cost;
-                if $state.gas_left < 0 {
-                    return Err(StatusCode::OutOfGas);
-                }
+                $state.charge(salt_cost)?;
             }
 
             CallKind::Create2 {
@@ -205,12 +218,12 @@ macro_rules! do_create {
                     }))
                     .await,
                 )
-                .unwrap()
+                .map_err(|other| other.mismatched("GetBalance"))?
                 .balance
                     < endowment)
         {
             let msg = Message {
-                gas: if $state.evm_revision >= Revision::Tangerine {
+                gas: if $state.config.metering && $state.evm_revision >= Revision::Tangerine {
                     $state.gas_left - $state.gas_left / 64
                 } else {
                     $state.gas_left
@@ -234,17 +247,36 @@ macro_rules! do_create {
             };
             let msg_gas = msg.gas;
             let result = ResumeDataVariant::into_call_output(
-                $co.yield_(InterruptDataVariant::Call(Call { message: msg }))
-                    .await,
+                $co.yield_(InterruptDataVariant::Call(Call {
+                    scheme: if $create2 {
+                        CallScheme::Create2
+                    } else {
+                        CallScheme::Create
+                    },
+                    message: msg,
+                }))
+                .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("Call"))?
             .output;
-            $state.gas_left -= msg_gas - result.gas_left;
-
-            $state.return_data = result.output_data;
-            if result.status_code == StatusCode::Success {
-                *$state.stack.get_mut(0) =
-                    address_to_u256(result.create_address.expect("expected create address"));
+            $state.record_nested_accesses(&result.access_list);
+            // On exceptional halt the host reports no gas left, so all the forwarded gas is consumed.
+            if $state.config.metering {
+                $state.gas_left -= msg_gas - result.gas_left;
+                $state.gas_refund += result.gas_refund;
+            }
+            match result.status_code {
+                StatusCode::Success => {
+                    // EIP-211: the output of successful creation is the deployed code,
+                    // so it is not exposed as return data.
+                    *$state.stack.get_mut(0) =
+                        address_to_u256(result.create_address.expect("expected create address"));
+                }
+                StatusCode::Revert => {
+                    $state.return_data = result.output_data;
+                }
+                // Exceptional halt: nothing is returned.
+                _ => {}
             }
         }
     }};
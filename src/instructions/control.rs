@@ -6,6 +6,12 @@ pub(crate) fn ret(state: &mut ExecutionState) -> Result<(), StatusCode> {
     let offset = *state.stack.get(0);
     let size = *state.stack.get(1);
 
+    if let Some(max_output_size) = state.config.max_output_size {
+        if size > U256::from(max_output_size) {
+            return Err(StatusCode::OutputSizeLimitExceeded);
+        }
+    }
+
     if let Some(region) = super::memory::verify_memory_region(state, offset, size)
         .map_err(|_| StatusCode::OutOfGas)?
     {
@@ -1,4 +1,8 @@
-use crate::{common::address_to_u256, host::*, state::ExecutionState};
+use crate::{
+    common::{address_to_u256, AccessCosts, Revision},
+    host::*,
+    state::ExecutionState,
+};
 use ethereum_types::U256;
 
 pub(crate) fn address(state: &mut ExecutionState) {
@@ -16,39 +20,39 @@ pub(crate) fn callvalue(state: &mut ExecutionState) {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! balance {
-    ($co:expr, $state:expr) => {
+    ($co:expr, $state:expr, $rules:ty) => {
         use crate::{
             common::*,
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::properties::*,
+            state::Rules,
         };
 
         let address = u256_to_address($state.stack.pop());
+        $state.record_access(address, None);
 
-        if $state.evm_revision >= Revision::Berlin {
-            let access_status = ResumeDataVariant::into_access_account_status(
-                $co.yield_(InterruptDataVariant::AccessAccount(AccessAccount {
-                    address,
-                }))
+        let balance = if <$rules as Rules>::eip2929($state)
+            && !($state.config.is_precompile)(address, <$rules as Rules>::revision($state))
+        {
+            let AccessedBalance { status, balance } = ResumeDataVariant::into_accessed_balance(
+                $co.yield_(InterruptDataVariant::AccessAndGetBalance(
+                    AccessAndGetBalance { address },
+                ))
                 .await,
             )
-            .unwrap()
-            .status;
-            if access_status == AccessStatus::Cold {
-                $state.gas_left -= i64::from(ADDITIONAL_COLD_ACCOUNT_ACCESS_COST);
-                if $state.gas_left < 0 {
-                    return Err(StatusCode::OutOfGas);
-                }
+            .map_err(|other| other.mismatched("AccessAndGetBalance"))?;
+            if status == AccessStatus::Cold {
+                $state.charge($state.spec.access_costs.additional_cold_account_access())?;
             }
-        }
-
-        let balance = ResumeDataVariant::into_balance(
-            $co.yield_(InterruptDataVariant::GetBalance(GetBalance { address }))
-                .await,
-        )
-        .unwrap()
-        .balance;
+            balance
+        } else {
+            ResumeDataVariant::into_balance(
+                $co.yield_(InterruptDataVariant::GetBalance(GetBalance { address }))
+                    .await,
+            )
+            .map_err(|other| other.mismatched("GetBalance"))?
+            .balance
+        };
 
         $state.stack.push(balance);
     };
@@ -57,30 +61,30 @@ macro_rules! balance {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! extcodesize {
-    ($co:expr, $state:expr) => {
+    ($co:expr, $state:expr, $rules:ty) => {
         use crate::{
             common::*,
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::properties::*,
+            state::Rules,
         };
 
         let address = u256_to_address($state.stack.pop());
+        $state.record_access(address, None);
 
-        if $state.evm_revision >= Revision::Berlin {
+        if <$rules as Rules>::eip2929($state)
+            && !($state.config.is_precompile)(address, <$rules as Rules>::revision($state))
+        {
             let access_account = ResumeDataVariant::into_access_account_status(
                 $co.yield_(InterruptDataVariant::AccessAccount(AccessAccount {
                     address,
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccessAccount"))?
             .status;
             if access_account == AccessStatus::Cold {
-                $state.gas_left -= i64::from(ADDITIONAL_COLD_ACCOUNT_ACCESS_COST);
-                if $state.gas_left < 0 {
-                    return Err(StatusCode::OutOfGas);
-                }
+                $state.charge($state.spec.access_costs.additional_cold_account_access())?;
             }
         }
 
@@ -88,7 +92,7 @@ macro_rules! extcodesize {
             $co.yield_(InterruptDataVariant::GetCodeSize(GetCodeSize { address }))
                 .await,
         )
-        .unwrap()
+        .map_err(|other| other.mismatched("GetCodeSize"))?
         .code_size;
         $state.stack.push(code_size);
     };
@@ -103,7 +107,7 @@ macro_rules! push_txcontext {
         let tx_context = ResumeDataVariant::into_tx_context_data(
             $co.yield_(InterruptDataVariant::GetTxContext).await,
         )
-        .unwrap()
+        .map_err(|other| other.mismatched("GetTxContext"))?
         .context;
 
         $state.stack.push($accessor(tx_context));
@@ -158,7 +162,7 @@ macro_rules! selfbalance {
             }))
             .await,
         )
-        .unwrap()
+        .map_err(|other| other.mismatched("GetBalance"))?
         .balance;
 
         $state.stack.push(balance);
@@ -177,23 +181,43 @@ macro_rules! blockhash {
         let upper_bound = ResumeDataVariant::into_tx_context_data(
             $co.yield_(InterruptDataVariant::GetTxContext).await,
         )
-        .unwrap()
+        .map_err(|other| other.mismatched("GetTxContext"))?
         .context
         .block_number;
-        let lower_bound = upper_bound.saturating_sub(256);
+        // The history contract only keeps the most recent hashes.
+        let window = if $state.spec.eip2935 {
+            core::cmp::min($state.spec.block_hash_window, $crate::HISTORY_SERVE_WINDOW)
+        } else {
+            $state.spec.block_hash_window
+        };
+        let lower_bound = upper_bound.saturating_sub(window);
 
         let mut header = H256::zero();
         if number <= u64::MAX.into() {
             let n = number.as_u64();
             if (lower_bound..upper_bound).contains(&n) {
-                header = ResumeDataVariant::into_block_hash(
-                    $co.yield_(InterruptDataVariant::GetBlockHash(GetBlockHash {
-                        block_number: n,
-                    }))
-                    .await,
-                )
-                .unwrap()
-                .hash;
+                header = if $state.spec.eip2935 {
+                    let key = H256::from_low_u64_be(n % $crate::HISTORY_SERVE_WINDOW);
+                    $state.record_access($crate::HISTORY_STORAGE_ADDRESS, Some(key));
+                    ResumeDataVariant::into_storage_value(
+                        $co.yield_(InterruptDataVariant::GetStorage(GetStorage {
+                            address: $crate::HISTORY_STORAGE_ADDRESS,
+                            key,
+                        }))
+                        .await,
+                    )
+                    .map_err(|other| other.mismatched("GetStorage"))?
+                    .value
+                } else {
+                    ResumeDataVariant::into_block_hash(
+                        $co.yield_(InterruptDataVariant::GetBlockHash(GetBlockHash {
+                            block_number: n,
+                        }))
+                        .await,
+                    )
+                    .map_err(|other| other.mismatched("GetBlockHash"))?
+                    .hash
+                };
             }
         }
 
@@ -206,7 +230,7 @@ macro_rules! blockhash {
 macro_rules! do_log {
     ($co:expr, $state:expr, $num_topics:expr) => {{
         use arrayvec::ArrayVec;
-        use ethereum_types::H256;
+        use ethereum_types::{H256, U256};
         use $crate::continuation::{interrupt_data::*, resume_data::*};
 
         if $state.message.is_static {
@@ -216,15 +240,18 @@ macro_rules! do_log {
         let offset = $state.stack.pop();
         let size = $state.stack.pop();
 
+        if let Some(max_log_data) = $state.config.max_log_data {
+            if size > U256::from(max_log_data) {
+                return Err(StatusCode::LogDataLimitExceeded);
+            }
+        }
+
         let region =
             memory::verify_memory_region($state, offset, size).map_err(|_| StatusCode::OutOfGas)?;
 
         if let Some(region) = &region {
             let cost = region.size.get() as i64 * 8;
-            $state.gas_left -= cost;
-            if cost < 0 {
-                return Err(StatusCode::OutOfGas);
-            }
+            $state.charge(cost)?;
         }
 
         let mut topics = ArrayVec::new();
@@ -242,6 +269,7 @@ macro_rules! do_log {
                 address: $state.message.destination,
                 data: data.to_vec().into(),
                 topics,
+                dry_run: $state.config.dry_run,
             }))
             .await;
 
@@ -252,75 +280,127 @@ macro_rules! do_log {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! sload {
-    ($co:expr, $state:expr) => {{
+    ($co:expr, $state:expr, $rules:ty) => {{
         use ethereum_types::H256;
         use $crate::{
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::properties::{COLD_SLOAD_COST, WARM_STORAGE_READ_COST},
+            state::Rules,
         };
 
         let key = H256($state.stack.pop().into());
+        $state.record_access($state.message.destination, Some(key));
 
-        if $state.evm_revision >= Revision::Berlin {
-            let access_status = ResumeDataVariant::into_access_storage_status(
-                $co.yield_(InterruptDataVariant::AccessStorage(AccessStorage {
+        let mut storage = if <$rules as Rules>::eip2929($state) {
+            let AccessedStorageValue { status, value } =
+                ResumeDataVariant::into_accessed_storage_value(
+                    $co.yield_(InterruptDataVariant::AccessAndGetStorage(
+                        AccessAndGetStorage {
+                            address: $state.message.destination,
+                            key,
+                        },
+                    ))
+                    .await,
+                )
+                .map_err(|other| other.mismatched("AccessAndGetStorage"))?;
+            if status == AccessStatus::Cold {
+                // The warm storage access cost is already applied (from the cost table).
+                // Here we need to apply additional cold storage access cost.
+                $state.charge($state.spec.access_costs.additional_cold_sload())?;
+            }
+            value
+        } else {
+            ResumeDataVariant::into_storage_value(
+                $co.yield_(InterruptDataVariant::GetStorage(GetStorage {
                     address: $state.message.destination,
                     key,
                 }))
                 .await,
             )
-            .unwrap()
-            .status;
-            if access_status == AccessStatus::Cold {
-                // The warm storage access cost is already applied (from the cost table).
-                // Here we need to apply additional cold storage access cost.
-                const ADDITIONAL_COLD_SLOAD_COST: u16 = COLD_SLOAD_COST - WARM_STORAGE_READ_COST;
-                $state.gas_left -= i64::from(ADDITIONAL_COLD_SLOAD_COST);
-                if $state.gas_left < 0 {
-                    return Err(StatusCode::OutOfGas);
-                }
+            .map_err(|other| other.mismatched("GetStorage"))?
+            .value
+        };
+
+        if $state.config.dry_run {
+            if let Some(slot) = $state.dry_run_storage.get(&key) {
+                storage = slot.value;
             }
         }
 
-        let storage = ResumeDataVariant::into_storage_value(
-            $co.yield_(InterruptDataVariant::GetStorage(GetStorage {
-                address: $state.message.destination,
-                key,
-            }))
-            .await,
-        )
-        .unwrap()
-        .value;
-
         $state.stack.push(U256::from_big_endian(storage.as_bytes()));
     }};
 }
 
+/// Change of the gas refund by an SSTORE with net gas metering (EIP-1283, EIP-2200).
+pub(crate) fn sstore_refund(
+    status: StorageStatus,
+    revision: Revision,
+    eip2929: bool,
+    access_costs: &AccessCosts,
+) -> i64 {
+    // EIP-3529 cut the refund for deleting a slot.
+    let clear = if revision >= Revision::London {
+        4800
+    } else {
+        15000
+    };
+    let (set, reset, read) = if eip2929 {
+        (
+            20000,
+            5000 - i64::from(access_costs.cold_sload),
+            i64::from(access_costs.warm_storage_read),
+        )
+    } else if revision >= Revision::Istanbul {
+        (20000, 5000, 800)
+    } else {
+        // Constantinople, EIP-1283.
+        (20000, 5000, 200)
+    };
+
+    match status {
+        StorageStatus::Deleted | StorageStatus::ModifiedDeleted => clear,
+        StorageStatus::DeletedAdded => -clear,
+        // Restoring the original value refunds what the first write cost on top of a read.
+        StorageStatus::DeletedRestored => reset - read - clear,
+        StorageStatus::AddedDeleted => set - read,
+        StorageStatus::ModifiedRestored => reset - read,
+        StorageStatus::Unchanged
+        | StorageStatus::Modified
+        | StorageStatus::ModifiedAgain
+        | StorageStatus::Added => 0,
+    }
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! sstore {
-    ($co:expr, $state:expr) => {{
+    ($co:expr, $state:expr, $rules:ty) => {{
         use ethereum_types::H256;
         use $crate::{
+            common::COLD_SLOAD_COST,
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::properties::{COLD_SLOAD_COST, WARM_STORAGE_READ_COST},
+            state::{DryRunSlot, Rules},
         };
 
         if $state.message.is_static {
             return Err(StatusCode::StaticModeViolation);
         }
 
-        if $state.evm_revision >= Revision::Istanbul && $state.gas_left <= 2300 {
+        if $state.config.metering
+            && <$rules as Rules>::revision($state) >= Revision::Istanbul
+            && $state.gas_left <= 2300
+        {
             return Err(StatusCode::OutOfGas);
         }
 
         let key = H256($state.stack.pop().into());
         let value = H256($state.stack.pop().into());
 
+        $state.record_access($state.message.destination, Some(key));
+
         let mut cost = 0;
-        if $state.evm_revision >= Revision::Berlin {
+        if <$rules as Rules>::eip2929($state) {
             let access_status = ResumeDataVariant::into_access_storage_status(
                 $co.yield_(InterruptDataVariant::AccessStorage(AccessStorage {
                     address: $state.message.destination,
@@ -328,62 +408,148 @@ macro_rules! sstore {
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccessStorage"))?
             .status;
 
             if access_status == AccessStatus::Cold {
-                cost = COLD_SLOAD_COST;
+                cost = $state.spec.access_costs.cold_sload;
             }
         }
 
-        let status = ResumeDataVariant::into_storage_status_info(
+        // Before EIP-1283, and again since Petersburg (until Istanbul),
+        // the cost depends only on the current and the new value.
+        let legacy_pricing = <$rules as Rules>::revision($state) < Revision::Constantinople
+            || <$rules as Rules>::revision($state) == Revision::Petersburg;
+        let current_is_zero = legacy_pricing
+            && match $state.dry_run_storage.get(&key) {
+                Some(slot) => slot.value.is_zero(),
+                None => ResumeDataVariant::into_storage_value(
+                    $co.yield_(InterruptDataVariant::GetStorage(GetStorage {
+                        address: $state.message.destination,
+                        key,
+                    }))
+                    .await,
+                )
+                .map_err(|other| other.mismatched("GetStorage"))?
+                .value
+                .is_zero(),
+            };
+
+        let status = if $state.config.dry_run {
+            let mut slot = match $state.dry_run_storage.get(&key) {
+                Some(slot) => slot.clone(),
+                None => DryRunSlot::new(
+                    ResumeDataVariant::into_storage_value(
+                        $co.yield_(InterruptDataVariant::GetStorage(GetStorage {
+                            address: $state.message.destination,
+                            key,
+                        }))
+                        .await,
+                    )
+                    .map_err(|other| other.mismatched("GetStorage"))?
+                    .value,
+                ),
+            };
+
+            // The host is notified, but the status comes from the frame-local write.
             $co.yield_(InterruptDataVariant::SetStorage(SetStorage {
                 address: $state.message.destination,
                 key,
                 value,
+                dry_run: true,
             }))
-            .await,
-        )
-        .unwrap()
-        .status;
-
-        cost = match status {
-            StorageStatus::Unchanged | StorageStatus::ModifiedAgain => {
-                if $state.evm_revision >= Revision::Berlin {
-                    cost + WARM_STORAGE_READ_COST
-                } else if $state.evm_revision == Revision::Istanbul {
-                    800
-                } else if $state.evm_revision == Revision::Constantinople {
-                    200
-                } else {
-                    5000
-                }
+            .await;
+
+            let status = slot.write(value);
+            $state.dry_run_storage.insert(key, slot);
+            status
+        } else {
+            ResumeDataVariant::into_storage_status_info(
+                $co.yield_(InterruptDataVariant::SetStorage(SetStorage {
+                    address: $state.message.destination,
+                    key,
+                    value,
+                    dry_run: false,
+                }))
+                .await,
+            )
+            .map_err(|other| other.mismatched("SetStorage"))?
+            .status
+        };
+
+        cost = if legacy_pricing {
+            if current_is_zero && !value.is_zero() {
+                20000
+            } else {
+                5000
             }
-            StorageStatus::Modified | StorageStatus::Deleted => {
-                if $state.evm_revision >= Revision::Berlin {
-                    cost + 5000 - COLD_SLOAD_COST
-                } else {
-                    5000
+        } else {
+            match status {
+                // No-op write: priced as a read of the slot.
+                StorageStatus::Unchanged => {
+                    if <$rules as Rules>::eip2929($state) {
+                        cost + $state.spec.access_costs.warm_storage_read
+                    } else if <$rules as Rules>::revision($state) >= Revision::Istanbul {
+                        800
+                    } else {
+                        // Constantinople, EIP-1283.
+                        200
+                    }
                 }
+                // Dirty slot: the first write already paid, the difference goes to the refund.
+                StorageStatus::ModifiedAgain
+                | StorageStatus::DeletedAdded
+                | StorageStatus::ModifiedDeleted
+                | StorageStatus::DeletedRestored
+                | StorageStatus::AddedDeleted
+                | StorageStatus::ModifiedRestored => {
+                    if <$rules as Rules>::eip2929($state) {
+                        cost + $state.spec.access_costs.warm_storage_read
+                    } else if <$rules as Rules>::revision($state) >= Revision::Istanbul {
+                        800
+                    } else {
+                        // Constantinople, EIP-1283.
+                        200
+                    }
+                }
+                StorageStatus::Modified | StorageStatus::Deleted => {
+                    if <$rules as Rules>::eip2929($state) {
+                        cost + 5000 - COLD_SLOAD_COST
+                    } else {
+                        5000
+                    }
+                }
+                StorageStatus::Added => cost + 20000,
             }
-            StorageStatus::Added => cost + 20000,
         };
-        $state.gas_left -= i64::from(cost);
-        if $state.gas_left < 0 {
-            return Err(StatusCode::OutOfGas);
-        }
+        $state.charge(i64::from(cost))?;
+
+        $state.gas_refund += if legacy_pricing {
+            if !current_is_zero && value.is_zero() {
+                15000
+            } else {
+                0
+            }
+        } else {
+            instructions::external::sstore_refund(
+                status,
+                <$rules as Rules>::revision($state),
+                <$rules as Rules>::eip2929($state),
+                &$state.spec.access_costs,
+            )
+        };
     }};
 }
 
 #[doc(hidden)]
 #[macro_export]
 macro_rules! selfdestruct {
-    ($co:expr, $state:expr) => {{
+    ($co:expr, $state:expr, $rules:ty) => {{
         use crate::{
             common::*,
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::properties::*,
+            state::Rules,
         };
 
         if $state.message.is_static {
@@ -391,26 +557,26 @@ macro_rules! selfdestruct {
         }
 
         let beneficiary = u256_to_address($state.stack.pop());
+        $state.record_access(beneficiary, None);
 
-        if $state.evm_revision >= Revision::Berlin {
+        if <$rules as Rules>::eip2929($state)
+            && !($state.config.is_precompile)(beneficiary, <$rules as Rules>::revision($state))
+        {
             let access_status = ResumeDataVariant::into_access_account_status(
                 $co.yield_(InterruptDataVariant::AccessAccount(AccessAccount {
                     address: beneficiary,
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccessAccount"))?
             .status;
             if access_status == AccessStatus::Cold {
-                $state.gas_left -= i64::from(COLD_ACCOUNT_ACCESS_COST);
-                if $state.gas_left < 0 {
-                    return Err(StatusCode::OutOfGas);
-                }
+                $state.charge(i64::from($state.spec.access_costs.cold_account_access))?;
             }
         }
 
-        if $state.evm_revision >= Revision::Tangerine
-            && ($state.evm_revision == Revision::Tangerine
+        if <$rules as Rules>::revision($state) >= Revision::Tangerine
+            && (<$rules as Rules>::revision($state) == Revision::Tangerine
                 || !{
                     ResumeDataVariant::into_balance(
                         $co.yield_(InterruptDataVariant::GetBalance(GetBalance {
@@ -418,7 +584,7 @@ macro_rules! selfdestruct {
                         }))
                         .await,
                     )
-                    .unwrap()
+                    .map_err(|other| other.mismatched("GetBalance"))?
                     .balance
                     .is_zero()
                 })
@@ -431,13 +597,10 @@ macro_rules! selfdestruct {
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccountExists"))?
             .exists
             {
-                $state.gas_left -= 25000;
-                if $state.gas_left < 0 {
-                    return Err(StatusCode::OutOfGas);
-                }
+                $state.charge(25000)?;
             }
         }
 
@@ -445,6 +608,7 @@ macro_rules! selfdestruct {
             $co.yield_(InterruptDataVariant::Selfdestruct(Selfdestruct {
                 address: $state.message.destination,
                 beneficiary,
+                dry_run: $state.config.dry_run,
             }))
             .await,
             ResumeDataVariant::Empty
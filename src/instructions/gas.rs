@@ -0,0 +1,127 @@
+use super::{
+    arithmetic::exp_cost,
+    instruction_table::get_instruction_table,
+    memory::{memory_expansion_cost, num_words},
+};
+use crate::{
+    common::u256_to_address,
+    host::{AccessStatus, HostRead},
+    opcode::OpCode,
+    state::{ExecutionState, Stack},
+    Revision,
+};
+use ethereum_types::{Address, H256, U256};
+
+/// Predict how much gas executing `op` in the given state would charge, without executing it.
+///
+/// Storage and, with EIP-2929, access status are read from the host where the cost depends on them.
+///
+/// Returns `None` if the instruction fails regardless of gas: it is undefined, underflows or overflows
+/// the stack, writes in static mode, or exceeds [`Config::max_output_size`](crate::Config::max_output_size)
+/// or [`Config::max_log_data`](crate::Config::max_log_data). Other failures, e.g. jumps to invalid
+/// destinations, are not predicted. Also returns `None` if the cost can not be known without side effects:
+/// access status the host does not report through [`HostRead::peek_account_access`] and
+/// [`HostRead::peek_storage_access`], original storage values since Istanbul, and gas consumed by
+/// nested calls and creates.
+pub fn peek_gas_cost<H: HostRead>(op: OpCode, state: &ExecutionState, host: &H) -> Option<i64> {
+    let revision = state.evm_revision;
+    let eip2929 = state.spec.eip2929;
+    let metrics = get_instruction_table(&state.spec)[op.to_usize()]?;
+
+    let stack = &state.stack;
+    if stack.len() == Stack::limit() {
+        if metrics.can_overflow_stack {
+            return None;
+        }
+    } else if stack.len() < metrics.stack_height_required.into() {
+        return None;
+    }
+
+    let memory = |offset: U256, size: U256| memory_expansion_cost(state.memory.len(), offset, size);
+    // Only called once the region is known to fit, so the size fits too.
+    let words = |size: &U256| num_words(size.as_usize());
+    // On top of the warm access cost from the instruction table.
+    let account_access = |address: Address| {
+        if (state.config.is_precompile)(address, revision) {
+            return Some(0);
+        }
+        Some(match host.peek_account_access(address)? {
+            AccessStatus::Cold => state.spec.access_costs.additional_cold_account_access(),
+            AccessStatus::Warm => 0,
+        })
+    };
+    let exceeds = |limit: Option<usize>, size: &U256| match limit {
+        Some(limit) => *size > U256::from(limit),
+        None => false,
+    };
+
+    let dynamic = match op {
+        OpCode::MLOAD | OpCode::MSTORE => memory(*stack.get(0), 32.into())?,
+        OpCode::MSTORE8 => memory(*stack.get(0), 1.into())?,
+        OpCode::KECCAK256 => memory(*stack.get(0), *stack.get(1))? + words(stack.get(1)) * 6,
+        OpCode::CALLDATACOPY | OpCode::CODECOPY | OpCode::RETURNDATACOPY => {
+            memory(*stack.get(0), *stack.get(2))? + words(stack.get(2)) * 3
+        }
+        OpCode::EXTCODECOPY => {
+            let access = if eip2929 {
+                account_access(u256_to_address(*stack.get(0)))?
+            } else {
+                0
+            };
+            memory(*stack.get(1), *stack.get(3))? + words(stack.get(3)) * 3 + access
+        }
+        OpCode::RETURN | OpCode::REVERT => {
+            if exceeds(state.config.max_output_size, stack.get(1)) {
+                return None;
+            }
+            memory(*stack.get(0), *stack.get(1))?
+        }
+        OpCode::LOG0 | OpCode::LOG1 | OpCode::LOG2 | OpCode::LOG3 | OpCode::LOG4 => {
+            if state.message.is_static || exceeds(state.config.max_log_data, stack.get(1)) {
+                return None;
+            }
+            memory(*stack.get(0), *stack.get(1))? + stack.get(1).as_u64() as i64 * 8
+        }
+        OpCode::EXP => exp_cost(*stack.get(1), revision),
+        OpCode::SSTORE
+            if revision < Revision::Constantinople || revision == Revision::Petersburg =>
+        {
+            if state.message.is_static {
+                return None;
+            }
+
+            let key = H256((*stack.get(0)).into());
+            let current = match state.dry_run_storage.get(&key) {
+                Some(slot) => slot.value,
+                None => host.get_storage(state.message.destination, key),
+            };
+
+            if current.is_zero() && !stack.get(1).is_zero() {
+                20000
+            } else {
+                5000
+            }
+        }
+        OpCode::SLOAD if eip2929 => {
+            let key = H256((*stack.get(0)).into());
+            match host.peek_storage_access(state.message.destination, key)? {
+                AccessStatus::Cold => state.spec.access_costs.additional_cold_sload(),
+                AccessStatus::Warm => 0,
+            }
+        }
+        OpCode::BALANCE | OpCode::EXTCODESIZE | OpCode::EXTCODEHASH if eip2929 => {
+            account_access(u256_to_address(*stack.get(0)))?
+        }
+        OpCode::SSTORE
+        | OpCode::CALL
+        | OpCode::CALLCODE
+        | OpCode::DELEGATECALL
+        | OpCode::STATICCALL
+        | OpCode::CREATE
+        | OpCode::CREATE2
+        | OpCode::SELFDESTRUCT => return None,
+        _ => 0,
+    };
+
+    Some(i64::from(metrics.gas_cost) + dynamic)
+}
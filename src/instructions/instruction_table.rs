@@ -1,5 +1,10 @@
-use crate::{instructions::properties, Revision};
+use crate::{
+    common::{AccessCosts, Spec},
+    instructions::properties,
+    Revision,
+};
 use once_cell::sync::Lazy;
+use std::{collections::BTreeMap, sync::Mutex};
 
 #[derive(Clone, Copy, Debug)]
 pub struct InstructionTableEntry {
@@ -9,34 +14,82 @@ pub struct InstructionTableEntry {
 }
 
 pub type InstructionTable = [Option<InstructionTableEntry>; 256];
-pub type InstructionTables = [InstructionTable; Revision::len()];
+/// Tables for every revision, with EIP-2929 and EIP-3855 switched off and on.
+pub type InstructionTables = [[[InstructionTable; 2]; 2]; Revision::len()];
+
+fn instruction_table(gas_costs: &[Option<u16>; 256]) -> InstructionTable {
+    let mut table = [None; 256];
+
+    for (opcode, &cost) in gas_costs.iter().enumerate() {
+        if let Some(cost) = cost {
+            let stack_height_required = properties::PROPERTIES[opcode]
+                .unwrap()
+                .stack_height_required;
+
+            // Because any instruction can increase stack height at most of 1,
+            // stack overflow can only happen if stack height is already at the limit.
+            assert!(properties::PROPERTIES[opcode].unwrap().stack_height_change <= 1);
+
+            table[opcode] = Some(InstructionTableEntry {
+                gas_cost: cost,
+                stack_height_required,
+                can_overflow_stack: properties::PROPERTIES[opcode].unwrap().stack_height_change > 0,
+            });
+        }
+    }
+    table
+}
 
 pub static INSTRUCTION_TABLES: Lazy<InstructionTables> = Lazy::new(|| {
-    let mut table = [[None; 256]; Revision::len()];
+    let mut tables = [[[[None; 256]; 2]; 2]; Revision::len()];
 
     for revision in Revision::iter() {
-        for (opcode, &cost) in properties::gas_costs(revision).iter().enumerate() {
-            if let Some(cost) = cost {
-                let stack_height_required = properties::PROPERTIES[opcode]
-                    .unwrap()
-                    .stack_height_required;
-
-                // Because any instruction can increase stack height at most of 1,
-                // stack overflow can only happen if stack height is already at the limit.
-                assert!(properties::PROPERTIES[opcode].unwrap().stack_height_change <= 1);
-
-                table[revision as usize][opcode] = Some(InstructionTableEntry {
-                    gas_cost: cost,
-                    stack_height_required,
-                    can_overflow_stack: properties::PROPERTIES[opcode].unwrap().stack_height_change
-                        > 0,
-                });
+        for eip2929 in [false, true] {
+            for eip3855 in [false, true] {
+                let spec = Spec {
+                    eip2929,
+                    eip3855,
+                    ..Spec::mainnet(revision)
+                };
+                tables[revision as usize][eip2929 as usize][eip3855 as usize] =
+                    instruction_table(&properties::spec_gas_costs(&spec));
             }
         }
     }
-    table
+    tables
 });
 
+/// Tables with EIP-2929 and a warm access cost other than mainnet, by revision, EIP-3855 and the cost.
+///
+/// Built on first use and kept for the lifetime of the process, chains only use a few costs.
+static CUSTOM_INSTRUCTION_TABLES: Lazy<
+    Mutex<BTreeMap<(Revision, bool, u16), &'static InstructionTable>>,
+> = Lazy::new(Default::default);
+
+/// Instruction table for the spec.
+pub fn get_instruction_table(spec: &Spec) -> &'static InstructionTable {
+    if spec.eip2929 && spec.access_costs.warm_storage_read != AccessCosts::MAINNET.warm_storage_read
+    {
+        let key = (
+            spec.revision,
+            spec.eip3855,
+            spec.access_costs.warm_storage_read,
+        );
+        return *CUSTOM_INSTRUCTION_TABLES
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| {
+                Box::leak(Box::new(instruction_table(&properties::spec_gas_costs(
+                    spec,
+                ))))
+            });
+    }
+
+    &INSTRUCTION_TABLES[spec.revision as usize][spec.eip2929 as usize][spec.eip3855 as usize]
+}
+
 pub fn get_baseline_instruction_table(revision: Revision) -> &'static InstructionTable {
-    &INSTRUCTION_TABLES[revision as usize]
+    let spec = Spec::mainnet(revision);
+    &INSTRUCTION_TABLES[revision as usize][spec.eip2929 as usize][spec.eip3855 as usize]
 }
@@ -14,6 +14,41 @@ pub(crate) fn num_words(size_in_bytes: usize) -> i64 {
     ((size_in_bytes as i64) + (WORD_SIZE - 1)) / WORD_SIZE
 }
 
+/// Total cost of memory of the given size in words, saturating at `i64::MAX`.
+fn memory_cost(words: i64) -> i64 {
+    words
+        .saturating_mul(3)
+        .saturating_add(words.saturating_mul(words) / 512)
+}
+
+/// Cost of growing memory from `current_size` to `new_size` bytes.
+fn expansion_cost(current_size: u64, new_size: u64) -> i64 {
+    let new_words = (new_size.saturating_add(WORD_SIZE as u64 - 1) / WORD_SIZE as u64) as i64;
+    let current_words = (current_size / WORD_SIZE as u64) as i64;
+    memory_cost(new_words) - memory_cost(current_words)
+}
+
+/// Cost of expanding memory of `current_size` bytes to cover the region.
+///
+/// Returns `None` if the region is too large to ever be allocated.
+pub(crate) fn memory_expansion_cost(current_size: usize, offset: U256, size: U256) -> Option<i64> {
+    if size.is_zero() {
+        return Some(0);
+    }
+
+    if offset > U256::from(MAX_BUFFER_SIZE) || size > U256::from(MAX_BUFFER_SIZE) {
+        return None;
+    }
+
+    let new_size = offset.as_u64() + size.as_u64();
+    let current_size = current_size as u64;
+    Some(if new_size > current_size {
+        expansion_cost(current_size, new_size)
+    } else {
+        0
+    })
+}
+
 pub(crate) fn mload(state: &mut ExecutionState) -> Result<(), StatusCode> {
     let index = state.stack.pop();
 
@@ -72,18 +107,24 @@ pub(crate) fn verify_memory_region_u64(
         return Err(());
     }
 
-    let new_size = offset.as_usize() + size.get();
-    let current_size = state.memory.len();
+    // Compute in u64, so that the size cannot wrap on 32-bit targets.
+    let new_size = offset.as_u64().saturating_add(size.get() as u64);
+    let current_size = state.memory.len() as u64;
     if new_size > current_size {
-        let new_words = num_words(new_size);
-        let current_words = (current_size / 32) as i64;
-        let new_cost = 3 * new_words + new_words * new_words / 512;
-        let current_cost = 3 * current_words + current_words * current_words / 512;
-        let cost = new_cost - current_cost;
+        let new_words = (new_size.saturating_add(WORD_SIZE as u64 - 1) / WORD_SIZE as u64) as i64;
 
-        state.gas_left -= cost;
+        if state.config.metering {
+            let cost = expansion_cost(current_size, new_size);
+            state.gas_left = state.gas_left.saturating_sub(cost);
+            if let Some(breakdown) = &mut state.gas_breakdown {
+                breakdown.memory = breakdown.memory.saturating_add(cost);
+            }
 
-        if state.gas_left < 0 {
+            if state.gas_left < 0 {
+                return Err(());
+            }
+        } else if memory_cost(new_words) > state.message.gas {
+            // Without metering, memory is still capped to what the gas of the frame would pay for.
             return Err(());
         }
 
@@ -128,10 +169,7 @@ pub(crate) fn calldatacopy(state: &mut ExecutionState) -> Result<(), StatusCode>
 
     if let Some(region) = &region {
         let copy_cost = num_words(region.size.get()) * 3;
-        state.gas_left -= copy_cost;
-        if state.gas_left < 0 {
-            return Err(StatusCode::OutOfGas);
-        }
+        state.charge(copy_cost)?;
 
         let input_len = state.message.input_data.len().into();
 
@@ -161,10 +199,7 @@ pub(crate) fn keccak256(state: &mut ExecutionState) -> Result<(), StatusCode> {
         if let Some(region) = region {
             let w = num_words(region.size.get());
             let cost = w * 6;
-            state.gas_left -= cost;
-            if state.gas_left < 0 {
-                return Err(StatusCode::OutOfGas);
-            }
+            state.charge(cost)?;
 
             &state.memory[region.offset..region.offset + region.size.get()]
         } else {
@@ -193,10 +228,7 @@ pub(crate) fn codecopy(state: &mut ExecutionState, code: &[u8]) -> Result<(), St
         let copy_size = min(region.size.get(), code.len() - src);
 
         let copy_cost = num_words(region.size.get()) * 3;
-        state.gas_left -= copy_cost;
-        if state.gas_left < 0 {
-            return Err(StatusCode::OutOfGas);
-        }
+        state.charge(copy_cost)?;
 
         // TODO: Add unit tests for each combination of conditions.
         if copy_size > 0 {
@@ -215,12 +247,13 @@ pub(crate) fn codecopy(state: &mut ExecutionState, code: &[u8]) -> Result<(), St
 #[doc(hidden)]
 #[macro_export]
 macro_rules! extcodecopy {
-    ($co:expr, $state:expr) => {
+    ($co:expr, $state:expr, $rules:ty) => {
         use crate::{
             common::*,
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::{memory::*, properties::*},
+            instructions::memory::*,
+            state::Rules,
         };
         use core::cmp::min;
 
@@ -234,27 +267,24 @@ macro_rules! extcodecopy {
 
         if let Some(region) = &region {
             let copy_cost = num_words(region.size.get()) * 3;
-            $state.gas_left -= copy_cost;
-            if $state.gas_left < 0 {
-                return Err(StatusCode::OutOfGas);
-            }
+            $state.charge(copy_cost)?;
         }
 
-        if $state.evm_revision >= Revision::Berlin
+        $state.record_access(addr, None);
+
+        if <$rules as Rules>::eip2929($state)
+            && !($state.config.is_precompile)(addr, <$rules as Rules>::revision($state))
             && ResumeDataVariant::into_access_account_status(
                 $co.yield_(InterruptDataVariant::AccessAccount(AccessAccount {
                     address: addr,
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccessAccount"))?
             .status
                 == AccessStatus::Cold
         {
-            $state.gas_left -= i64::from(ADDITIONAL_COLD_ACCOUNT_ACCESS_COST);
-            if $state.gas_left < 0 {
-                return Err(StatusCode::OutOfGas);
-            }
+            $state.charge($state.spec.access_costs.additional_cold_account_access())?;
         }
 
         if let Some(region) = region {
@@ -269,7 +299,7 @@ macro_rules! extcodecopy {
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("CopyCode"))?
             .code;
 
             r[..code.len()].copy_from_slice(&code);
@@ -303,10 +333,7 @@ pub(crate) fn returndatacopy(state: &mut ExecutionState) -> Result<(), StatusCod
 
     if let Some(region) = region {
         let copy_cost = num_words(region.size.get()) * 3;
-        state.gas_left -= copy_cost;
-        if state.gas_left < 0 {
-            return Err(StatusCode::OutOfGas);
-        }
+        state.charge(copy_cost)?;
 
         state.memory[region.offset..region.offset + region.size.get()]
             .copy_from_slice(&state.return_data[src..src + region.size.get()]);
@@ -318,43 +345,45 @@ pub(crate) fn returndatacopy(state: &mut ExecutionState) -> Result<(), StatusCod
 #[doc(hidden)]
 #[macro_export]
 macro_rules! extcodehash {
-    ($co:expr, $state:expr) => {
+    ($co:expr, $state:expr, $rules:ty) => {
         use crate::{
             common::*,
             continuation::{interrupt_data::*, resume_data::*},
             host::*,
-            instructions::properties::*,
+            state::Rules,
         };
 
         let addr = u256_to_address($state.stack.pop());
+        $state.record_access(addr, None);
 
-        if $state.evm_revision >= Revision::Berlin
+        if <$rules as Rules>::eip2929($state)
+            && !($state.config.is_precompile)(addr, <$rules as Rules>::revision($state))
             && ResumeDataVariant::into_access_account_status(
                 $co.yield_(InterruptDataVariant::AccessAccount(AccessAccount {
                     address: addr,
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("AccessAccount"))?
             .status
                 == AccessStatus::Cold
         {
-            $state.gas_left -= i64::from(ADDITIONAL_COLD_ACCOUNT_ACCESS_COST);
-            if $state.gas_left < 0 {
-                return Err(StatusCode::OutOfGas);
-            }
+            $state.charge($state.spec.access_costs.additional_cold_account_access())?;
         }
 
-        $state.stack.push(U256::from_big_endian(
+        // The account being created exists, but gets its code only when the init code returns.
+        let hash = if $state.is_create() && addr == $state.message.destination {
+            EMPTY_CODE_HASH
+        } else {
             ResumeDataVariant::into_code_hash(
                 $co.yield_(InterruptDataVariant::GetCodeHash(GetCodeHash {
                     address: addr,
                 }))
                 .await,
             )
-            .unwrap()
+            .map_err(|other| other.mismatched("GetCodeHash"))?
             .hash
-            .as_bytes(),
-        ));
+        };
+        $state.stack.push(U256::from_big_endian(hash.as_bytes()));
     };
 }
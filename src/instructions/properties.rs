@@ -1,17 +1,9 @@
 use once_cell::sync::Lazy;
 
-use crate::{common::Revision, opcode::*};
-
-pub(crate) const COLD_SLOAD_COST: u16 = 2100;
-pub(crate) const COLD_ACCOUNT_ACCESS_COST: u16 = 2600;
-pub(crate) const WARM_STORAGE_READ_COST: u16 = 100;
-
-/// Additional cold account access cost.
-///
-/// The warm access cost is unconditionally applied for every account access instruction.
-/// If the access turns out to be cold, this cost must be applied additionally.
-pub(crate) const ADDITIONAL_COLD_ACCOUNT_ACCESS_COST: u16 =
-    COLD_ACCOUNT_ACCESS_COST - WARM_STORAGE_READ_COST;
+use crate::{
+    common::{Revision, Spec, WARM_STORAGE_READ_COST},
+    opcode::*,
+};
 
 /// EVM instruction properties
 #[derive(Clone, Copy, Debug)]
@@ -107,6 +99,8 @@ pub static PROPERTIES: Lazy<[Option<Properties>; 256]> = Lazy::new(|| {
     table[OpCode::GAS.to_usize()] = Some(Properties::new("GAS", 0, 1));
     table[OpCode::JUMPDEST.to_usize()] = Some(Properties::new("JUMPDEST", 0, 0));
 
+    table[OpCode::PUSH0.to_usize()] = Some(Properties::new("PUSH0", 0, 1));
+
     table[OpCode::PUSH1.to_usize()] = Some(Properties::new("PUSH1", 0, 1));
     table[OpCode::PUSH2.to_usize()] = Some(Properties::new("PUSH2", 0, 1));
     table[OpCode::PUSH3.to_usize()] = Some(Properties::new("PUSH3", 0, 1));
@@ -356,7 +350,15 @@ static LONDON_GAS_COSTS: Lazy<[Option<u16>; 256]> = Lazy::new(|| {
     table
 });
 
-static SHANGHAI_GAS_COSTS: Lazy<[Option<u16>; 256]> = Lazy::new(|| *LONDON_GAS_COSTS);
+static PARIS_GAS_COSTS: Lazy<[Option<u16>; 256]> = Lazy::new(|| *LONDON_GAS_COSTS);
+
+static SHANGHAI_GAS_COSTS: Lazy<[Option<u16>; 256]> = Lazy::new(|| {
+    let mut table = *PARIS_GAS_COSTS;
+    table[OpCode::PUSH0.to_usize()] = Some(2);
+    table
+});
+
+static CANCUN_GAS_COSTS: Lazy<[Option<u16>; 256]> = Lazy::new(|| *SHANGHAI_GAS_COSTS);
 
 pub fn gas_costs(revision: Revision) -> &'static [Option<u16>; 256] {
     match revision {
@@ -370,6 +372,84 @@ pub fn gas_costs(revision: Revision) -> &'static [Option<u16>; 256] {
         Revision::Istanbul => &ISTANBUL_GAS_COSTS,
         Revision::Berlin => &BERLIN_GAS_COSTS,
         Revision::London => &LONDON_GAS_COSTS,
+        Revision::Paris => &PARIS_GAS_COSTS,
         Revision::Shanghai => &SHANGHAI_GAS_COSTS,
+        Revision::Cancun => &CANCUN_GAS_COSTS,
+    }
+}
+
+/// Instructions with the warm access cost of EIP-2929 as their static cost.
+const STATE_ACCESS_OPCODES: [OpCode; 9] = [
+    OpCode::SLOAD,
+    OpCode::BALANCE,
+    OpCode::EXTCODESIZE,
+    OpCode::EXTCODECOPY,
+    OpCode::EXTCODEHASH,
+    OpCode::CALL,
+    OpCode::CALLCODE,
+    OpCode::DELEGATECALL,
+    OpCode::STATICCALL,
+];
+
+/// Gas costs of the spec revision, with the EIPs switched on or off as the spec says.
+pub fn spec_gas_costs(spec: &Spec) -> [Option<u16>; 256] {
+    let mut table = *gas_costs(spec.revision);
+    let mainnet = Spec::mainnet(spec.revision);
+
+    if spec.eip2929 != mainnet.eip2929 {
+        let access_costs = if spec.eip2929 {
+            &BERLIN_GAS_COSTS
+        } else {
+            &ISTANBUL_GAS_COSTS
+        };
+        for op in STATE_ACCESS_OPCODES {
+            if table[op.to_usize()].is_some() {
+                table[op.to_usize()] = access_costs[op.to_usize()];
+            }
+        }
+    }
+
+    if spec.eip2929 {
+        for op in STATE_ACCESS_OPCODES {
+            if table[op.to_usize()].is_some() {
+                table[op.to_usize()] = Some(spec.access_costs.warm_storage_read);
+            }
+        }
+    }
+
+    if spec.eip3855 != mainnet.eip3855 {
+        table[OpCode::PUSH0.to_usize()] = spec.eip3855.then(|| 2);
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_cover_every_byte() {
+        for byte in 0..=u8::MAX {
+            let op = OpCode(byte);
+            let defined = op.name() != "UNDEFINED";
+
+            match PROPERTIES[op.to_usize()] {
+                Some(properties) => assert_eq!(properties.name, op.name(), "{}", op),
+                None => assert!(!defined, "{} has no properties", op),
+            }
+
+            for revision in Revision::iter() {
+                if gas_costs(revision)[op.to_usize()].is_some() {
+                    assert!(defined, "{} has a gas cost in {}", op, revision);
+                }
+            }
+            assert_eq!(
+                gas_costs(Revision::latest())[op.to_usize()].is_some(),
+                defined,
+                "{}",
+                op
+            );
+        }
     }
 }
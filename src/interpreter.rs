@@ -2,14 +2,60 @@ use self::instruction_table::*;
 use crate::{
     common::*,
     continuation::{interrupt::*, interrupt_data::*, resume_data::*, *},
+    host::StorageStatus,
     instructions::{control::*, stack_manip::*, *},
     state::*,
-    tracing::Tracer,
+    tracing::{NoopTracer, Tracer},
     *,
 };
-use ethereum_types::{Address, U256};
+use ethereum_types::{Address, H256, U256};
 use genawaiter::sync::*;
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
+
+fn find_undefined_instructions(code: &[u8], revision: Revision) -> Vec<(usize, OpCode)> {
+    let instruction_table = get_baseline_instruction_table(revision);
+
+    let mut undefined = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = OpCode(code[pc]);
+        if instruction_table[op.to_usize()].is_none() {
+            undefined.push((pc, op));
+        }
+        pc += 1 + op.push_size().map(usize::from).unwrap_or(0);
+    }
+
+    undefined
+}
+
+/// Check contract code against the deployment rules of the given revision, without executing it.
+///
+/// Enforces the EIP-170 size limit (Spurious Dragon and later) and rejects the EIP-3541 0xEF prefix (London and later).
+pub fn validate_code(code: &[u8], revision: Revision) -> Result<(), CodeError> {
+    if revision >= Revision::Spurious && code.len() > MAX_CODE_SIZE {
+        return Err(CodeError::TooLarge { size: code.len() });
+    }
+
+    if revision >= Revision::London && code.first() == Some(&0xef) {
+        return Err(CodeError::ReservedPrefix);
+    }
+
+    Ok(())
+}
+
+/// Same as [validate_code], but additionally rejects code containing instructions undefined in the given revision.
+pub fn validate_code_strict(code: &[u8], revision: Revision) -> Result<(), CodeError> {
+    validate_code(code, revision)?;
+
+    if let Some((pc, op)) = find_undefined_instructions(code, revision)
+        .into_iter()
+        .next()
+    {
+        return Err(CodeError::UndefinedInstruction { pc, opcode: op.0 });
+    }
+
+    Ok(())
+}
 
 fn check_requirements(
     instruction_table: &InstructionTable,
@@ -18,10 +64,7 @@ fn check_requirements(
 ) -> Result<(), StatusCode> {
     let metrics = &instruction_table[op.to_usize()].ok_or(StatusCode::UndefinedInstruction)?;
 
-    state.gas_left -= metrics.gas_cost as i64;
-    if state.gas_left < 0 {
-        return Err(StatusCode::OutOfGas);
-    }
+    state.charge(metrics.gas_cost as i64)?;
 
     let stack_size = state.stack.len();
     if stack_size == Stack::limit() {
@@ -50,6 +93,10 @@ pub struct AnalyzedCode {
     jumpdest_map: JumpdestMap,
     code: Bytes,
     padded_code: Bytes,
+    /// Keys of `PUSHn key SLOAD` sequences, see [`AnalyzedCode::constant_storage_keys`].
+    constant_storage_keys: Vec<H256>,
+    /// Same, but also with `PUSH0 SLOAD` sequences, which only push a key since Shanghai.
+    constant_storage_keys_push0: Vec<H256>,
 }
 
 impl AnalyzedCode {
@@ -57,11 +104,15 @@ impl AnalyzedCode {
     pub fn analyze(code: impl Into<Vec<u8>>) -> Self {
         let code = code.into();
         let mut jumpdest_map = vec![false; code.len()];
+        let mut constant_storage_keys = Vec::new();
+        let mut constant_storage_keys_push0 = Vec::new();
+        let mut seen_keys = HashSet::new();
+        let mut seen_keys_push0 = HashSet::new();
 
         let mut i = 0;
         while i < code.len() {
             let opcode = OpCode(code[i]);
-            i += match opcode {
+            let size = match opcode {
                 OpCode::JUMPDEST => {
                     jumpdest_map[i] = true;
                     1
@@ -99,7 +150,21 @@ impl AnalyzedCode {
                 | OpCode::PUSH31
                 | OpCode::PUSH32 => opcode.to_usize() - OpCode::PUSH1.to_usize() + 2,
                 _ => 1,
+            };
+
+            let is_push = opcode == OpCode::PUSH0 || opcode.push_size().is_some();
+            if is_push && code.get(i + size) == Some(&OpCode::SLOAD.to_u8()) {
+                let mut key = H256::zero();
+                key.0[33 - size..].copy_from_slice(&code[i + 1..i + size]);
+                if seen_keys_push0.insert(key) {
+                    constant_storage_keys_push0.push(key);
+                }
+                if opcode != OpCode::PUSH0 && seen_keys.insert(key) {
+                    constant_storage_keys.push(key);
+                }
             }
+
+            i += size;
         }
 
         let code_len = code.len();
@@ -117,6 +182,27 @@ impl AnalyzedCode {
             jumpdest_map,
             code,
             padded_code,
+            constant_storage_keys,
+            constant_storage_keys_push0,
+        }
+    }
+
+    /// Find instructions that are undefined in the given revision.
+    ///
+    /// Returns the position and opcode of every offending instruction. Push data is skipped.
+    pub fn undefined_instructions(&self, revision: Revision) -> Vec<(usize, OpCode)> {
+        find_undefined_instructions(&self.code, revision)
+    }
+
+    /// Storage keys read by `PUSHn key SLOAD` sequences, and `PUSH0 SLOAD` since Shanghai, in order of appearance
+    /// and without duplicates.
+    ///
+    /// Keys computed at runtime are not included.
+    pub fn constant_storage_keys(&self, revision: Revision) -> &[H256] {
+        if revision >= Revision::Shanghai {
+            &self.constant_storage_keys_push0
+        } else {
+            &self.constant_storage_keys
         }
     }
 
@@ -129,31 +215,230 @@ impl AnalyzedCode {
         message: Message,
         revision: Revision,
     ) -> Output {
+        self.execute_with_config(
+            host,
+            tracer,
+            state_modifier,
+            message,
+            revision,
+            Config::default(),
+        )
+    }
+
+    /// Same as [`AnalyzedCode::execute`], but with execution limits and modes from `config`.
+    pub fn execute_with_config<H: Host, T: Tracer>(
+        &self,
+        host: &mut H,
+        tracer: &mut T,
+        state_modifier: StateModifier,
+        message: Message,
+        revision: Revision,
+        config: Config,
+    ) -> Output {
+        self.execute_answering(
+            tracer,
+            state_modifier,
+            message,
+            revision,
+            config,
+            Self::execute_resumable_with_config,
+            |interrupt, tracer| answer_host(interrupt, host, tracer),
+        )
+    }
+
+    /// Same as [`AnalyzedCode::execute_with_config`], but with an interpreter specialized to the revision at
+    /// compile time.
+    ///
+    /// Only the revision and the [EIP-2929](Spec::eip2929) checks fold away; the rest of the spec is still read
+    /// at runtime. [`AnalyzedCode::execute_with_config`] already picks the specialized interpreter for the
+    /// revision with mainnet rules.
+    ///
+    /// # Panics
+    ///
+    /// If the spec of `config` for the revision is not [`Spec::mainnet`].
+    pub fn execute_specialized<S: RevisionSpec, H: Host, T: Tracer>(
+        &self,
+        host: &mut H,
+        tracer: &mut T,
+        state_modifier: StateModifier,
+        message: Message,
+        config: Config,
+    ) -> Output {
+        assert!(
+            (config.spec)(S::REVISION) == Spec::mainnet(S::REVISION),
+            "specialized interpreter requires mainnet rules"
+        );
+        self.execute_answering(
+            tracer,
+            state_modifier,
+            message,
+            S::REVISION,
+            config,
+            Self::start::<Specialized<S>>,
+            |interrupt, tracer| answer_host(interrupt, host, tracer),
+        )
+    }
+
+    /// Same as [`AnalyzedCode::execute_with_config`], but always in [dry run mode](Config::dry_run).
+    ///
+    /// The host is never asked to change the state, so it only needs to implement [`HostRead`].
+    /// See there for what the host still has to take care of for calls.
+    ///
+    /// ```
+    /// # use evmodin::{host::*, tracing::NoopTracer, *};
+    /// fn execute<H: HostRead>(code: &AnalyzedCode, host: &mut H, message: Message) -> Output {
+    ///     let config = Config::default();
+    ///     code.execute_dry_run(host, &mut NoopTracer, None, message, Revision::latest(), config)
+    /// }
+    /// ```
+    ///
+    /// Outside of dry run the same host lacks [`HostWrite`](crate::HostWrite):
+    ///
+    /// ```compile_fail,E0277
+    /// # use evmodin::{host::*, tracing::NoopTracer, *};
+    /// fn execute<H: HostRead>(code: &AnalyzedCode, host: &mut H, message: Message) -> Output {
+    ///     code.execute(host, &mut NoopTracer, None, message, Revision::latest())
+    /// }
+    /// ```
+    pub fn execute_dry_run<H: HostRead, T: Tracer>(
+        &self,
+        host: &mut H,
+        tracer: &mut T,
+        state_modifier: StateModifier,
+        message: Message,
+        revision: Revision,
+        config: Config,
+    ) -> Output {
+        self.execute_answering(
+            tracer,
+            state_modifier,
+            message,
+            revision,
+            Config {
+                dry_run: true,
+                ..config
+            },
+            Self::execute_resumable_with_config,
+            |interrupt, tracer| answer_host_read(interrupt, host, tracer),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_answering<T: Tracer>(
+        &self,
+        tracer: &mut T,
+        state_modifier: StateModifier,
+        message: Message,
+        revision: Revision,
+        config: Config,
+        start: fn(&Self, bool, Message, Revision, Config) -> ExecutionStartInterrupt,
+        answer: impl FnMut(InterruptVariant, &mut T) -> Result<InterruptVariant, Output>,
+    ) -> Output {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!(
+            "frame",
+            depth = message.depth,
+            destination = ?message.destination,
+            gas = message.gas,
+            %revision,
+        )
+        .entered();
+
         if !T::DUMMY {
             tracer.notify_execution_start(revision, message.clone(), self.code.clone());
         }
 
-        let output = self
-            .execute_resumable(!T::DUMMY || state_modifier.is_some(), message, revision)
-            .run_to_completion_with_host(host, tracer, state_modifier);
+        let run = || {
+            start(
+                self,
+                !T::DUMMY || state_modifier.is_some(),
+                message,
+                revision,
+                config,
+            )
+            .run_to_completion(tracer, state_modifier, answer)
+        };
+
+        #[cfg(feature = "catch-panic")]
+        let output = std::panic::catch_unwind(std::panic::AssertUnwindSafe(run))
+            .unwrap_or_else(panic_output);
+        #[cfg(not(feature = "catch-panic"))]
+        let output = run();
 
         if !T::DUMMY {
             tracer.notify_execution_end(&output);
         }
 
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(
+            status = %output.status_code,
+            gas_left = output.gas_left,
+            "frame finished"
+        );
+
         output
     }
 
     /// Execute in resumable EVM.
+    ///
+    /// Execution runs in a stackless, heap-allocated generator. Nested calls are left to the caller,
+    /// so driving them from a loop instead of recursing keeps native stack usage flat at any call depth.
     pub fn execute_resumable(
         &self,
         trace: bool,
         message: Message,
         revision: Revision,
+    ) -> ExecutionStartInterrupt {
+        self.execute_resumable_with_config(trace, message, revision, Config::default())
+    }
+
+    /// Execute in resumable EVM with execution limits and modes from `config`.
+    ///
+    /// With the mainnet rules of the revision, execution runs in an interpreter specialized to the revision.
+    pub fn execute_resumable_with_config(
+        &self,
+        trace: bool,
+        message: Message,
+        revision: Revision,
+        config: Config,
+    ) -> ExecutionStartInterrupt {
+        if (config.spec)(revision) != Spec::mainnet(revision) {
+            return self.start::<Dynamic>(trace, message, revision, config);
+        }
+
+        let start = match revision {
+            Revision::Frontier => Self::start::<Specialized<FrontierSpec>>,
+            Revision::Homestead => Self::start::<Specialized<HomesteadSpec>>,
+            Revision::Tangerine => Self::start::<Specialized<TangerineSpec>>,
+            Revision::Spurious => Self::start::<Specialized<SpuriousSpec>>,
+            Revision::Byzantium => Self::start::<Specialized<ByzantiumSpec>>,
+            Revision::Constantinople => Self::start::<Specialized<ConstantinopleSpec>>,
+            Revision::Petersburg => Self::start::<Specialized<PetersburgSpec>>,
+            Revision::Istanbul => Self::start::<Specialized<IstanbulSpec>>,
+            Revision::Berlin => Self::start::<Specialized<BerlinSpec>>,
+            Revision::London => Self::start::<Specialized<LondonSpec>>,
+            Revision::Paris => Self::start::<Specialized<ParisSpec>>,
+            Revision::Shanghai => Self::start::<Specialized<ShanghaiSpec>>,
+            Revision::Cancun => Self::start::<Specialized<CancunSpec>>,
+        };
+        start(self, trace, message, revision, config)
+    }
+
+    fn start<R: Rules>(
+        &self,
+        trace: bool,
+        message: Message,
+        revision: Revision,
+        config: Config,
     ) -> ExecutionStartInterrupt {
         let code = self.clone();
         let inner = Box::pin(Gen::new(move |co| {
-            interpreter_producer(co, code, ExecutionState::new(message, revision), trace)
+            interpreter_producer::<R>(
+                co,
+                code,
+                ExecutionState::with_config(message, revision, config),
+                trace,
+            )
         }));
 
         ExecutionStartInterrupt { inner, data: () }
@@ -166,6 +451,17 @@ impl ExecutionStartInterrupt {
         host: &mut H,
         tracer: &mut T,
         state_modifier: StateModifier,
+    ) -> Output {
+        self.run_to_completion(tracer, state_modifier, |interrupt, tracer| {
+            answer_host(interrupt, host, tracer)
+        })
+    }
+
+    fn run_to_completion<T: Tracer>(
+        self,
+        tracer: &mut T,
+        state_modifier: StateModifier,
+        mut answer: impl FnMut(InterruptVariant, &mut T) -> Result<InterruptVariant, Output>,
     ) -> Output {
         let mut interrupt = self.resume(());
 
@@ -175,136 +471,441 @@ impl ExecutionStartInterrupt {
                     tracer.notify_instruction_start(i.data().pc, i.data().opcode, &i.data().state);
                     i.resume(state_modifier.clone())
                 }
-                InterruptVariant::AccountExists(i) => {
-                    let exists = host.account_exists(i.data().address);
-                    i.resume(AccountExistsStatus { exists })
-                }
-                InterruptVariant::GetBalance(i) => {
-                    let balance = host.get_balance(i.data().address);
-                    i.resume(Balance { balance })
-                }
-                InterruptVariant::GetCodeSize(i) => {
-                    let code_size = host.get_code_size(i.data().address);
-                    i.resume(CodeSize { code_size })
-                }
-                InterruptVariant::GetStorage(i) => {
-                    let value = host.get_storage(i.data().address, i.data().key);
-                    i.resume(StorageValue { value })
-                }
-                InterruptVariant::SetStorage(i) => {
-                    let status = host.set_storage(i.data().address, i.data().key, i.data().value);
-                    i.resume(StorageStatusInfo { status })
-                }
-                InterruptVariant::GetCodeHash(i) => {
-                    let hash = host.get_code_hash(i.data().address);
-                    i.resume(CodeHash { hash })
-                }
-                InterruptVariant::CopyCode(i) => {
-                    let mut code = vec![0; i.data().max_size];
-                    let copied = host.copy_code(i.data().address, i.data().offset, &mut code[..]);
-                    if copied > code.len() {
-                        return Output {
-                            status_code: StatusCode::InternalError(format!(
-                                "copy code: copied {} > max size {}",
-                                copied,
-                                code.len()
-                            )),
-                            gas_left: 0,
-                            output_data: Bytes::new(),
-                            create_address: None,
-                        };
+                InterruptVariant::Complete(res) => return complete_output(res),
+                other => match answer(other, tracer) {
+                    Ok(interrupt) => interrupt,
+                    Err(output) => return output,
+                },
+            };
+        }
+    }
+}
+
+impl InterruptVariant {
+    /// Resume execution and run at most `n` instructions.
+    ///
+    /// Stops at the first interrupt that requires host input. Instruction start interrupts must be enabled.
+    pub fn run_n_steps(self, n: usize) -> RunResult {
+        self.run_steps(steps(n), |interrupt| {
+            Err(RunResult::NeedHostInput(interrupt))
+        })
+    }
+
+    /// Resume execution and run at most `n` instructions, answering all host interrupts with provided `Host`.
+    ///
+    /// Instruction start interrupts must be enabled.
+    pub fn run_n_steps_with_host<H: Host>(self, host: &mut H, n: usize) -> RunResult {
+        self.run_steps(steps(n), |interrupt| {
+            answer_host(interrupt, host, &mut NoopTracer).map_err(RunResult::Halted)
+        })
+    }
+
+    /// Resume execution, answering host interrupts with provided `Host`, and pause before the first log is emitted.
+    ///
+    /// The log is returned as [`RunResult::NeedHostInput`] and reaches the host when this interrupt is answered.
+    pub fn run_until_log_with_host<H: Host>(self, host: &mut H) -> RunResult {
+        self.run_steps(steps(usize::MAX), |interrupt| match interrupt {
+            InterruptVariant::EmitLog(_) => Err(RunResult::NeedHostInput(interrupt)),
+            other => answer_host(other, host, &mut NoopTracer).map_err(RunResult::Halted),
+        })
+    }
+
+    /// Resume execution, answering host interrupts with provided `Host`, and pause before the instruction at `pc`.
+    ///
+    /// If this interrupt is already paused at `pc`, e.g. at the previous hit, its instruction runs first, so that
+    /// calling this again pauses at the next hit. Only the frame being stepped pauses: nested calls are executed
+    /// by the host to completion. Instruction start interrupts must be enabled.
+    pub fn run_until_breakpoint_with_host<H: Host>(self, host: &mut H, pc: usize) -> RunResult {
+        let mut resuming =
+            matches!(&self, InterruptVariant::InstructionStart(i) if i.data().pc == pc);
+        self.run_steps(
+            |i| !std::mem::replace(&mut resuming, false) && i.data().pc == pc,
+            |interrupt| answer_host(interrupt, host, &mut NoopTracer).map_err(RunResult::Halted),
+        )
+    }
+
+    /// Run until `pause` returns true for an instruction about to be executed.
+    fn run_steps(
+        self,
+        mut pause: impl FnMut(&InstructionStartInterrupt) -> bool,
+        mut answer: impl FnMut(InterruptVariant) -> Result<InterruptVariant, RunResult>,
+    ) -> RunResult {
+        let mut interrupt = self;
+
+        loop {
+            interrupt = match interrupt {
+                InterruptVariant::InstructionStart(i) => {
+                    if pause(&i) {
+                        return RunResult::Paused(i);
                     }
-                    code.truncate(copied);
-                    let code = code.into();
-                    i.resume(Code { code })
-                }
-                InterruptVariant::Selfdestruct(i) => {
-                    host.selfdestruct(i.data().address, i.data().beneficiary);
-                    i.resume(())
-                }
-                InterruptVariant::Call(i) => {
-                    let output = host.call(&i.data().message);
-                    i.resume(CallOutput { output })
-                }
-                InterruptVariant::GetTxContext(i) => {
-                    let context = host.get_tx_context();
-                    i.resume(TxContextData { context })
-                }
-                InterruptVariant::GetBlockHash(i) => {
-                    let hash = host.get_block_hash(i.data().block_number);
-                    i.resume(BlockHash { hash })
-                }
-                InterruptVariant::EmitLog(i) => {
-                    host.emit_log(
-                        i.data().address,
-                        &*i.data().data,
-                        i.data().topics.as_slice(),
-                    );
-                    i.resume(())
-                }
-                InterruptVariant::AccessAccount(i) => {
-                    let status = host.access_account(i.data().address);
-                    i.resume(AccessAccountStatus { status })
-                }
-                InterruptVariant::AccessStorage(i) => {
-                    let status = host.access_storage(i.data().address, i.data().key);
-                    i.resume(AccessStorageStatus { status })
-                }
-                InterruptVariant::Complete(i) => {
-                    let output = match i {
-                        Ok(output) => output.into(),
-                        Err(status_code) => Output {
-                            status_code,
-                            gas_left: 0,
-                            output_data: Bytes::new(),
-                            create_address: None,
-                        },
-                    };
-
-                    return output;
+                    i.resume(None)
                 }
+                InterruptVariant::Complete(res) => return RunResult::Halted(complete_output(res)),
+                other => match answer(other) {
+                    Ok(interrupt) => interrupt,
+                    Err(res) => return res,
+                },
             };
         }
     }
 }
 
-async fn interpreter_producer(
+/// Pause before the instruction following the first `n`.
+fn steps(n: usize) -> impl FnMut(&InstructionStartInterrupt) -> bool {
+    let mut steps = 0;
+    move |_| {
+        if steps == n {
+            return true;
+        }
+        steps += 1;
+        false
+    }
+}
+
+#[cfg(feature = "catch-panic")]
+fn panic_output(payload: Box<dyn std::any::Any + Send>) -> Output {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+
+    Output {
+        status_code: StatusCode::InternalError(format!("panic: {}", message)),
+        gas_left: 0,
+        gas_refund: 0,
+        output_data: Bytes::new(),
+        create_address: None,
+        failure_location: None,
+        gas_breakdown: None,
+        opcode_trace: None,
+        access_list: None,
+        peak_memory: 0,
+    }
+}
+
+pub(crate) fn complete_output(res: Result<SuccessfulOutput, FailedOutput>) -> Output {
+    match res {
+        Ok(output) => output.into(),
+        Err(output) => output.into(),
+    }
+}
+
+/// Answer interrupt that queries the host. Other interrupts are returned as is.
+fn answer_host<H: Host, T: Tracer>(
+    interrupt: InterruptVariant,
+    host: &mut H,
+    tracer: &mut T,
+) -> Result<InterruptVariant, Output> {
+    #[cfg(feature = "tracing")]
+    ::tracing::trace!(kind = <&'static str>::from(&interrupt), "host interrupt");
+
+    Ok(match interrupt {
+        InterruptVariant::SetStorage(i) if !i.data().dry_run => {
+            let status = host.set_storage(i.data().address, i.data().key, i.data().value);
+            i.resume(StorageStatusInfo { status })
+        }
+        InterruptVariant::Selfdestruct(i) if !i.data().dry_run => {
+            host.selfdestruct(i.data().address, i.data().beneficiary);
+            i.resume(())
+        }
+        InterruptVariant::EmitLog(i) if !i.data().dry_run => {
+            host.emit_log(
+                i.data().address,
+                &*i.data().data,
+                i.data().topics.as_slice(),
+            );
+            i.resume(())
+        }
+        other => return answer_reads(other, host, tracer),
+    })
+}
+
+/// Same as [`answer_host`], for dry runs.
+fn answer_host_read<H: HostRead, T: Tracer>(
+    interrupt: InterruptVariant,
+    host: &mut H,
+    tracer: &mut T,
+) -> Result<InterruptVariant, Output> {
+    #[cfg(feature = "tracing")]
+    ::tracing::trace!(kind = <&'static str>::from(&interrupt), "host interrupt");
+
+    answer_reads(interrupt, host, tracer)
+}
+
+/// Answer interrupt that does not change the state. State changes are only reached in dry run, where they are advisory.
+fn answer_reads<H: HostRead, T: Tracer>(
+    interrupt: InterruptVariant,
+    host: &mut H,
+    tracer: &mut T,
+) -> Result<InterruptVariant, Output> {
+    Ok(match interrupt {
+        InterruptVariant::AccountExists(i) => {
+            let exists = host.account_exists(i.data().address);
+            i.resume(AccountExistsStatus { exists })
+        }
+        InterruptVariant::GetBalance(i) => {
+            let balance = host.get_balance(i.data().address);
+            i.resume(Balance { balance })
+        }
+        InterruptVariant::GetCodeSize(i) => {
+            let code_size = host.get_code_size(i.data().address);
+            i.resume(CodeSize { code_size })
+        }
+        InterruptVariant::GetStorage(i) => {
+            let value = host.get_storage(i.data().address, i.data().key);
+            i.resume(StorageValue { value })
+        }
+        InterruptVariant::SetStorage(i) => i.resume(StorageStatusInfo {
+            status: StorageStatus::Unchanged,
+        }),
+        InterruptVariant::GetCodeHash(i) => {
+            let hash = host.get_code_hash(i.data().address);
+            i.resume(CodeHash { hash })
+        }
+        InterruptVariant::CopyCode(i) => {
+            let mut code = vec![0; i.data().max_size];
+            let copied = host.copy_code(i.data().address, i.data().offset, &mut code[..]);
+            if copied > code.len() {
+                return Err(Output {
+                    status_code: StatusCode::InternalError(format!(
+                        "copy code: copied {} > max size {}",
+                        copied,
+                        code.len()
+                    )),
+                    gas_left: 0,
+                    gas_refund: 0,
+                    output_data: Bytes::new(),
+                    create_address: None,
+                    failure_location: None,
+                    gas_breakdown: None,
+                    opcode_trace: None,
+                    access_list: None,
+                    peak_memory: 0,
+                });
+            }
+            code.truncate(copied);
+            let code = code.into();
+            i.resume(Code { code })
+        }
+        InterruptVariant::Selfdestruct(i) => i.resume(()),
+        InterruptVariant::Call(i) => {
+            let message = &i.data().message;
+            tracer.notify_call_enter(message.depth, message.gas);
+            let output = if host.authorize_call(message) {
+                host.call(message)
+            } else {
+                Output {
+                    status_code: StatusCode::Failure,
+                    gas_left: 0,
+                    gas_refund: 0,
+                    output_data: Bytes::new(),
+                    create_address: None,
+                    failure_location: None,
+                    gas_breakdown: None,
+                    opcode_trace: None,
+                    access_list: None,
+                    peak_memory: 0,
+                }
+            };
+            tracer.notify_call_exit(message.depth, output.gas_left, &output.status_code);
+            i.resume(CallOutput { output })
+        }
+        InterruptVariant::GetTxContext(i) => {
+            let context = host.get_tx_context();
+            i.resume(TxContextData { context })
+        }
+        InterruptVariant::GetBlockHash(i) => {
+            let hash = host.get_block_hash(i.data().block_number);
+            i.resume(BlockHash { hash })
+        }
+        InterruptVariant::EmitLog(i) => i.resume(()),
+        InterruptVariant::AccessAccount(i) => {
+            let status = host.access_account(i.data().address);
+            i.resume(AccessAccountStatus { status })
+        }
+        InterruptVariant::AccessStorage(i) => {
+            let status = host.access_storage(i.data().address, i.data().key);
+            i.resume(AccessStorageStatus { status })
+        }
+        InterruptVariant::AccessAndGetStorage(i) => {
+            let (status, value) = host.access_and_get_storage(i.data().address, i.data().key);
+            i.resume(AccessedStorageValue { status, value })
+        }
+        InterruptVariant::AccessAndGetBalance(i) => {
+            let (status, balance) = host.access_and_get_balance(i.data().address);
+            i.resume(AccessedBalance { status, balance })
+        }
+        InterruptVariant::PrefetchStorage(i) => {
+            host.prefetch_storage(i.data().address, &i.data().keys);
+            i.resume(())
+        }
+        other => other,
+    })
+}
+
+async fn interpreter_producer<R: Rules>(
     mut co: Co<InterruptDataVariant, ResumeDataVariant>,
     s: AnalyzedCode,
     mut state: ExecutionState,
     trace: bool,
-) -> Result<SuccessfulOutput, StatusCode> {
-    let state = &mut state;
+) -> Result<SuccessfulOutput, FailedOutput> {
+    let mut pc = 0;
+    let mut opcode_trace = state.config.opcode_trace.then(Vec::new);
+    let res =
+        execute_instructions::<R>(&mut co, &s, &mut state, trace, &mut pc, &mut opcode_trace).await;
 
-    let instruction_table = get_baseline_instruction_table(state.evm_revision);
+    // Hand memory buffer over to the next frame.
+    if state.config.reuse_memory {
+        release_memory(std::mem::take(&mut state.memory));
+    }
+
+    // Failed instructions do not advance the program counter.
+    res.map_err(|status_code| FailedOutput {
+        status_code,
+        location: FailureLocation {
+            pc,
+            opcode: OpCode(s.padded_code[pc]),
+        },
+        opcode_trace,
+        access_list: state.access_list.take(),
+    })
+}
+
+/// Attribute gas charged by `op` on top of its static cost and memory expansion.
+fn account_dynamic_gas(state: &mut ExecutionState, op: OpCode, gas_left: i64, memory: i64) {
+    if let Some(breakdown) = &mut state.gas_breakdown {
+        let cost = gas_left - state.gas_left - (breakdown.memory - memory);
+        *match op {
+            OpCode::SLOAD | OpCode::SSTORE => &mut breakdown.storage,
+            OpCode::CALL
+            | OpCode::CALLCODE
+            | OpCode::DELEGATECALL
+            | OpCode::STATICCALL
+            | OpCode::CREATE
+            | OpCode::CREATE2 => &mut breakdown.calls,
+            _ => &mut breakdown.other,
+        } += cost;
+    }
+}
+
+async fn execute_instructions<R: Rules>(
+    co: &mut Co<InterruptDataVariant, ResumeDataVariant>,
+    s: &AnalyzedCode,
+    state: &mut ExecutionState,
+    trace: bool,
+    pc: &mut usize,
+    opcode_trace: &mut Option<Vec<(usize, OpCode)>>,
+) -> Result<SuccessfulOutput, StatusCode> {
+    let instruction_table = get_instruction_table(&state.spec);
 
     let mut reverted = false;
+    let mut steps = 0;
+    // Previous instruction, with gas left and memory expansion cost after its static cost was charged.
+    let mut charged = None;
 
-    let mut pc = 0;
+    if state.config.prefetch_storage {
+        let keys = s.constant_storage_keys(state.evm_revision);
+        if !keys.is_empty() {
+            co.yield_(InterruptDataVariant::PrefetchStorage(PrefetchStorage {
+                address: state.message.destination,
+                keys: keys.to_vec(),
+            }))
+            .await;
+        }
+    }
 
     loop {
-        let op = OpCode(s.padded_code[pc]);
+        if let Some((op, gas_left, memory)) = charged.take() {
+            account_dynamic_gas(state, op, gas_left, memory);
+        }
+
+        let op = OpCode(s.padded_code[*pc]);
+
+        // Outputs of an instruction mocked with `StepAction::Override`.
+        let mut overridden = None;
 
         // Do not print stop on the final STOP
-        if trace && pc < s.code.len() {
-            if let Some(modifier) = co
+        if trace && *pc < s.code.len() {
+            let modifier = match co
                 .yield_(InterruptDataVariant::InstructionStart(Box::new(
                     InstructionStart {
-                        pc,
+                        pc: *pc,
                         opcode: op,
                         state: state.clone(),
                     },
                 )))
                 .await
-                .as_state_modifier()
-                .unwrap()
             {
+                ResumeDataVariant::StateModifier(modifier)
+                | ResumeDataVariant::StepAction(StepAction::Continue(modifier)) => modifier,
+                ResumeDataVariant::StepAction(StepAction::Override { push }) => {
+                    overridden = Some(push);
+                    None
+                }
+                ResumeDataVariant::StepAction(StepAction::Halt {
+                    status_code,
+                    output_data,
+                }) => match status_code {
+                    StatusCode::Success | StatusCode::Revert => {
+                        state.output_data = output_data;
+                        reverted = status_code == StatusCode::Revert;
+                        break;
+                    }
+                    other => return Err(other),
+                },
+                ResumeDataVariant::StepAction(StepAction::Restore(snapshot)) => {
+                    *pc = snapshot.pc;
+                    state.restore(snapshot);
+                    continue;
+                }
+                other => return Err(other.mismatched("InstructionStart")),
+            };
+            if let Some(modifier) = modifier {
                 (modifier)(state)
             }
         }
 
+        // The STOP past the end of code is not counted.
+        if let (Some(max_steps), true) = (state.config.max_steps, *pc < s.code.len()) {
+            if steps == max_steps {
+                return Err(StatusCode::StepLimitExceeded);
+            }
+            steps += 1;
+        }
+
+        // Recorded before the checks, so that the trace of a failed frame ends with the instruction
+        // it failed at.
+        if let (Some(opcode_trace), true) = (opcode_trace.as_mut(), *pc < s.code.len()) {
+            opcode_trace.push((*pc, op));
+        }
+
         check_requirements(instruction_table, state, op)?;
 
+        if let Some(breakdown) = &mut state.gas_breakdown {
+            breakdown.base += i64::from(instruction_table[op.to_usize()].unwrap().gas_cost);
+            charged = Some((op, state.gas_left, breakdown.memory));
+        }
+
+        if let Some(push) = overridden {
+            let inputs = instruction_table[op.to_usize()]
+                .unwrap()
+                .stack_height_required
+                .into();
+            if state.stack.len() - inputs + push.len() > Stack::limit() {
+                return Err(StatusCode::StackOverflow);
+            }
+            for _ in 0..inputs {
+                state.stack.pop();
+            }
+            for value in push {
+                state.stack.push(value);
+            }
+
+            *pc += 1 + op.push_size().map(usize::from).unwrap_or(0);
+            continue;
+        }
+
         match op {
             OpCode::STOP => {
                 break;
@@ -337,7 +938,7 @@ async fn interpreter_producer(
                 arithmetic::mulmod(&mut state.stack);
             }
             OpCode::EXP => {
-                arithmetic::exp(state)?;
+                arithmetic::exp::<R>(state)?;
             }
             OpCode::SIGNEXTEND => {
                 arithmetic::signextend(&mut state.stack);
@@ -392,7 +993,7 @@ async fn interpreter_producer(
                 external::address(state);
             }
             OpCode::BALANCE => {
-                balance!(co, state);
+                balance!(co, state, R);
             }
             OpCode::CALLER => {
                 external::caller(state);
@@ -416,10 +1017,10 @@ async fn interpreter_producer(
                 memory::codecopy(state, &s.code[..])?;
             }
             OpCode::EXTCODESIZE => {
-                extcodesize!(co, state);
+                extcodesize!(co, state, R);
             }
             OpCode::EXTCODECOPY => {
-                extcodecopy!(co, state);
+                extcodecopy!(co, state, R);
             }
             OpCode::RETURNDATASIZE => {
                 memory::returndatasize(state);
@@ -428,7 +1029,7 @@ async fn interpreter_producer(
                 memory::returndatacopy(state)?;
             }
             OpCode::EXTCODEHASH => {
-                extcodehash!(co, state);
+                extcodehash!(co, state, R);
             }
             OpCode::BLOCKHASH => {
                 blockhash!(co, state);
@@ -475,13 +1076,13 @@ async fn interpreter_producer(
                 memory::mstore8(state)?;
             }
             OpCode::JUMP => {
-                pc = op_jump(state, &s.jumpdest_map)?;
+                *pc = op_jump(state, &s.jumpdest_map)?;
 
                 continue;
             }
             OpCode::JUMPI => {
                 if !state.stack.get(1).is_zero() {
-                    pc = op_jump(state, &s.jumpdest_map)?;
+                    *pc = op_jump(state, &s.jumpdest_map)?;
                     state.stack.pop();
 
                     continue;
@@ -490,16 +1091,17 @@ async fn interpreter_producer(
                     state.stack.pop();
                 }
             }
-            OpCode::PC => state.stack.push(pc.into()),
+            OpCode::PC => state.stack.push((*pc).into()),
             OpCode::MSIZE => memory::msize(state),
             OpCode::SLOAD => {
-                sload!(co, state);
+                sload!(co, state, R);
             }
             OpCode::SSTORE => {
-                sstore!(co, state);
+                sstore!(co, state, R);
             }
             OpCode::GAS => state.stack.push(state.gas_left.into()),
             OpCode::JUMPDEST => {}
+            OpCode::PUSH0 => state.stack.push(U256::zero()),
             OpCode::PUSH1
             | OpCode::PUSH2
             | OpCode::PUSH3
@@ -532,9 +1134,9 @@ async fn interpreter_producer(
             | OpCode::PUSH30
             | OpCode::PUSH31
             | OpCode::PUSH32 => {
-                pc += load_push(
+                *pc += load_push(
                     &mut state.stack,
-                    &s.padded_code[pc + 1..],
+                    &s.padded_code[*pc + 1..],
                     op.to_usize() - OpCode::PUSH1.to_usize() + 1,
                 )
             }
@@ -591,6 +1193,7 @@ async fn interpreter_producer(
                 do_call!(
                     co,
                     state,
+                    R,
                     match op {
                         OpCode::CALL | OpCode::STATICCALL => CallKind::Call,
                         OpCode::CALLCODE => CallKind::CallCode,
@@ -609,7 +1212,7 @@ async fn interpreter_producer(
                 return Err(StatusCode::InvalidInstruction);
             }
             OpCode::SELFDESTRUCT => {
-                selfdestruct!(co, state);
+                selfdestruct!(co, state, R);
                 break;
             }
             other => {
@@ -617,13 +1220,22 @@ async fn interpreter_producer(
             }
         }
 
-        pc += 1;
+        *pc += 1;
+    }
+
+    if let Some((op, gas_left, memory)) = charged {
+        account_dynamic_gas(state, op, gas_left, memory);
     }
 
     let output = SuccessfulOutput {
         reverted,
         gas_left: state.gas_left,
+        gas_refund: state.gas_refund,
         output_data: state.output_data.clone(),
+        gas_breakdown: state.gas_breakdown,
+        opcode_trace: opcode_trace.take(),
+        access_list: state.access_list.take(),
+        peak_memory: state.memory.len(),
     };
 
     Ok(output)
@@ -1,15 +1,24 @@
 #![doc = include_str!("../README.md")]
 use bytes::Bytes;
-pub use common::{CallKind, Message, Output, Revision, StatusCode, SuccessfulOutput};
-pub use host::Host;
-pub use interpreter::AnalyzedCode;
+pub use common::{
+    create2_address, create_address, decode_revert_reason, is_mainnet_precompile, AccessCosts,
+    AccessList, BerlinSpec, ByzantiumSpec, CallKind, CancunSpec, CodeError, Config,
+    ConstantinopleSpec, FailedOutput, FailureLocation, FrontierSpec, GasBreakdown, HomesteadSpec,
+    IstanbulSpec, LondonSpec, Message, MessageBuilder, Output, ParisSpec, PetersburgSpec, Revision,
+    RevisionSpec, ShanghaiSpec, Spec, SpuriousSpec, StatusCode, SuccessfulOutput, TangerineSpec,
+    EMPTY_CODE_HASH, HISTORY_SERVE_WINDOW, HISTORY_STORAGE_ADDRESS,
+};
+pub use host::{Host, HostRead, HostWrite};
+pub use instructions::gas::peek_gas_cost;
+pub use interpreter::{validate_code, validate_code_strict, AnalyzedCode};
 pub use opcode::OpCode;
-pub use state::{ExecutionState, Stack};
+pub use state::{ExecutionState, Stack, StateSnapshot};
 
 /// Maximum allowed EVM bytecode size.
 pub const MAX_CODE_SIZE: usize = 0x6000;
 
 mod common;
+pub mod eof;
 pub mod host;
 #[doc(hidden)]
 pub mod instructions;
@@ -24,3 +33,6 @@ pub mod util;
 
 #[cfg(feature = "evmc")]
 pub mod evmc;
+
+#[cfg(feature = "rpc")]
+pub mod rpc;
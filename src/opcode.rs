@@ -66,6 +66,8 @@ impl OpCode {
     pub const TIMESTAMP: OpCode = OpCode(0x42);
     pub const NUMBER: OpCode = OpCode(0x43);
     pub const DIFFICULTY: OpCode = OpCode(0x44);
+    /// Since Paris, DIFFICULTY returns the PREVRANDAO value ([EIP-4399](https://eips.ethereum.org/EIPS/eip-4399)).
+    pub const PREVRANDAO: OpCode = OpCode::DIFFICULTY;
     pub const GASLIMIT: OpCode = OpCode(0x45);
     pub const CHAINID: OpCode = OpCode(0x46);
     pub const SELFBALANCE: OpCode = OpCode(0x47);
@@ -84,6 +86,8 @@ impl OpCode {
     pub const GAS: OpCode = OpCode(0x5a);
     pub const JUMPDEST: OpCode = OpCode(0x5b);
 
+    pub const PUSH0: OpCode = OpCode(0x5f);
+
     pub const PUSH1: OpCode = OpCode(0x60);
     pub const PUSH2: OpCode = OpCode(0x61);
     pub const PUSH3: OpCode = OpCode(0x62);
@@ -235,6 +239,7 @@ impl OpCode {
             OpCode::MSIZE => "MSIZE",
             OpCode::GAS => "GAS",
             OpCode::JUMPDEST => "JUMPDEST",
+            OpCode::PUSH0 => "PUSH0",
             OpCode::PUSH1 => "PUSH1",
             OpCode::PUSH2 => "PUSH2",
             OpCode::PUSH3 => "PUSH3",
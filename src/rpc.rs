@@ -0,0 +1,528 @@
+//! Host reading the state of a remote chain over JSON-RPC, for executing against a fork of it.
+use crate::{
+    common::{is_mainnet_precompile, CallKind, Message, Output, Revision, StatusCode},
+    host::{AccessStatus, HostRead, HostWrite, StorageStatus, TxContext},
+    state::DryRunSlot,
+    tracing::NoopTracer,
+    AnalyzedCode,
+};
+use bytes::Bytes;
+use ethereum_types::{Address, H256, U256};
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::min,
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+/// Sends JSON-RPC requests to an endpoint, e.g. over HTTP.
+pub trait RpcTransport {
+    /// Send the request and return its `result`, or a description of the failure.
+    fn request(&self, method: &str, params: Value) -> Result<Value, String>;
+}
+
+impl<T: RpcTransport + ?Sized> RpcTransport for &T {
+    fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        (**self).request(method, params)
+    }
+}
+
+/// Minimal JSON-RPC over plain HTTP, one connection per request.
+///
+/// Only `http://` endpoints are supported, put a local proxy in front of TLS endpoints.
+pub struct HttpTransport {
+    host: String,
+    port: u16,
+    path: String,
+    id: Cell<u64>,
+}
+
+impl HttpTransport {
+    /// Endpoint at the URL, e.g. `http://localhost:8545`.
+    pub fn new(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("unsupported URL {}, expected http://", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid port in URL {}", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("no host in URL {}", url));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            id: Cell::new(0),
+        })
+    }
+
+    fn post(&self, body: &[u8]) -> Result<Vec<u8>, String> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|e| format!("connect to {}:{}: {}", self.host, self.port, e))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        )
+        .and_then(|_| stream.write_all(body))
+        .map_err(|e| format!("send request: {}", e))?;
+
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .map_err(|e| format!("read response: {}", e))?;
+
+        let header_end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or("malformed HTTP response")?;
+        let header = String::from_utf8_lossy(&response[..header_end]).to_ascii_lowercase();
+        let status = header.split_whitespace().nth(1).unwrap_or_default();
+        if status != "200" {
+            return Err(format!("HTTP status {}", status));
+        }
+        if header.contains("transfer-encoding: chunked") {
+            return Err("chunked HTTP responses are not supported".to_string());
+        }
+
+        Ok(response[header_end + 4..].to_vec())
+    }
+}
+
+impl RpcTransport for HttpTransport {
+    fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.id.get() + 1;
+        self.id.set(id);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let body = self.post(request.to_string().as_bytes())?;
+        let mut response: Value = serde_json::from_slice(&body)
+            .map_err(|e| format!("malformed JSON-RPC response: {}", e))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("JSON-RPC error {}", error));
+        }
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err("JSON-RPC response without result".to_string()),
+        }
+    }
+}
+
+/// Log emitted during execution on top of the remote state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcLog {
+    pub address: Address,
+    pub data: Bytes,
+    pub topics: Vec<H256>,
+}
+
+#[derive(Clone, Debug)]
+struct RemoteAccount {
+    nonce: u64,
+    balance: U256,
+    code: Bytes,
+    code_hash: H256,
+}
+
+/// Local changes at the start of a nested frame, to roll back to if it fails.
+struct Checkpoint {
+    overlay: HashMap<(Address, H256), DryRunSlot>,
+    balances: HashMap<Address, U256>,
+    accessed: HashSet<(Address, Option<H256>)>,
+    selfdestructs: usize,
+    logs: usize,
+}
+
+/// Host answering reads from the state of a remote chain at a fixed block, keeping writes in a local overlay.
+///
+/// Every account is fetched once with `eth_getBalance`, `eth_getTransactionCount` and `eth_getCode`,
+/// every storage slot once with `eth_getStorageAt`, and then served from the cache.
+/// Storage writes, value transfers, self-destructs and logs never reach the endpoint.
+///
+/// Nested calls are executed by the host on top of the overlay, and their changes are rolled back
+/// if they fail or revert. Contract creation and precompiles are not supported and panic.
+/// DELEGATECALL needs the frame it is made from, so start execution with [`RpcHost::execute`].
+/// Access status is tracked by the host for all frames and rolled back with failed calls, so it must not be
+/// wrapped into [`AccessTrackingHost`](crate::host::AccessTrackingHost); prewarm with [`RpcHost::warm_account`]
+/// and [`RpcHost::warm_storage`] instead.
+///
+/// Panics if the endpoint fails or answers with malformed data.
+pub struct RpcHost<T> {
+    transport: T,
+    block: Value,
+    revision: Revision,
+    tx_context: TxContext,
+    accounts: RefCell<HashMap<Address, RemoteAccount>>,
+    storage: RefCell<HashMap<(Address, H256), H256>>,
+    block_hashes: RefCell<HashMap<u64, H256>>,
+    overlay: HashMap<(Address, H256), DryRunSlot>,
+    balances: HashMap<Address, U256>,
+    accessed: HashSet<(Address, Option<H256>)>,
+    selfdestructs: Vec<(Address, Address)>,
+    logs: Vec<RpcLog>,
+    /// Account whose storage each frame executed by the host runs on, innermost last.
+    frames: Vec<Address>,
+}
+
+impl<T: RpcTransport> RpcHost<T> {
+    /// Fork the remote state as of the end of block `block_number`, executing nested calls in `revision`.
+    pub fn new(transport: T, block_number: u64, revision: Revision, tx_context: TxContext) -> Self {
+        Self {
+            transport,
+            block: quantity(block_number),
+            revision,
+            tx_context,
+            accounts: Default::default(),
+            storage: Default::default(),
+            block_hashes: Default::default(),
+            overlay: Default::default(),
+            balances: Default::default(),
+            accessed: Default::default(),
+            selfdestructs: Default::default(),
+            logs: Default::default(),
+            frames: Default::default(),
+        }
+    }
+
+    /// Execute the message with the code of its destination, as a transaction would.
+    ///
+    /// Value is transferred from the sender without checking its balance, and no intrinsic gas is charged.
+    pub fn execute(&mut self, message: &Message) -> Output {
+        self.call(message)
+    }
+
+    /// Mark account as warm, e.g. the sender and destination of the transaction.
+    pub fn warm_account(&mut self, address: Address) {
+        self.accessed.insert((address, None));
+    }
+
+    /// Mark storage key as warm, e.g. from the transaction access list.
+    pub fn warm_storage(&mut self, address: Address, key: H256) {
+        self.accessed.insert((address, Some(key)));
+    }
+
+    /// Code of the account, e.g. to execute a transaction to it.
+    pub fn code(&self, address: Address) -> Bytes {
+        self.with_account(address, |account| account.code.clone())
+    }
+
+    /// Storage written during execution, with the value each slot was last set to.
+    pub fn storage_changes(&self) -> impl Iterator<Item = (Address, H256, H256)> + '_ {
+        self.overlay
+            .iter()
+            .map(|(&(address, key), slot)| (address, key, slot.value))
+    }
+
+    /// Self-destructed accounts with their beneficiaries, in order.
+    pub fn selfdestructs(&self) -> &[(Address, Address)] {
+        &self.selfdestructs
+    }
+
+    /// Logs emitted so far, in order.
+    pub fn logs(&self) -> &[RpcLog] {
+        &self.logs
+    }
+
+    fn request(&self, method: &str, params: Value) -> Value {
+        self.transport
+            .request(method, params)
+            .unwrap_or_else(|e| panic!("{} failed: {}", method, e))
+    }
+
+    fn request_hex(&self, method: &str, params: Value) -> String {
+        match self.request(method, params) {
+            Value::String(s) => s
+                .strip_prefix("0x")
+                .unwrap_or_else(|| panic!("{} returned {} without 0x prefix", method, s))
+                .to_string(),
+            other => panic!("{} returned {}, expected a hex string", method, other),
+        }
+    }
+
+    fn request_quantity(&self, method: &str, params: Value) -> U256 {
+        let hex = self.request_hex(method, params);
+        if hex.is_empty() {
+            return U256::zero();
+        }
+        U256::from_str_radix(&hex, 16)
+            .unwrap_or_else(|_| panic!("{} returned invalid quantity 0x{}", method, hex))
+    }
+
+    fn with_account<R>(&self, address: Address, f: impl FnOnce(&RemoteAccount) -> R) -> R {
+        if let Some(account) = self.accounts.borrow().get(&address) {
+            return f(account);
+        }
+
+        let params = || json!([data(address.as_bytes()), self.block]);
+        let nonce = self.request_quantity("eth_getTransactionCount", params());
+        let balance = self.request_quantity("eth_getBalance", params());
+        let code: Bytes = hex::decode(self.request_hex("eth_getCode", params()))
+            .unwrap_or_else(|e| panic!("eth_getCode returned invalid code: {}", e))
+            .into();
+        let account = RemoteAccount {
+            nonce: nonce.as_u64(),
+            balance,
+            code_hash: H256::from_slice(&Keccak256::digest(&code)),
+            code,
+        };
+
+        let r = f(&account);
+        self.accounts.borrow_mut().insert(address, account);
+        r
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            overlay: self.overlay.clone(),
+            balances: self.balances.clone(),
+            accessed: self.accessed.clone(),
+            selfdestructs: self.selfdestructs.len(),
+            logs: self.logs.len(),
+        }
+    }
+
+    fn roll_back(&mut self, checkpoint: Checkpoint) {
+        self.overlay = checkpoint.overlay;
+        self.balances = checkpoint.balances;
+        self.accessed = checkpoint.accessed;
+        self.selfdestructs.truncate(checkpoint.selfdestructs);
+        self.logs.truncate(checkpoint.logs);
+    }
+
+    fn transfer(&mut self, from: Address, to: Address, value: U256) {
+        let from_balance = self.get_balance(from);
+        self.balances
+            .insert(from, from_balance.saturating_sub(value));
+        let to_balance = self.get_balance(to);
+        self.balances.insert(to, to_balance.saturating_add(value));
+    }
+
+    fn remote_storage(&self, address: Address, key: H256) -> H256 {
+        if let Some(value) = self.storage.borrow().get(&(address, key)) {
+            return *value;
+        }
+
+        let value = H256(
+            self.request_quantity(
+                "eth_getStorageAt",
+                json!([
+                    data(address.as_bytes()),
+                    quantity(U256::from_big_endian(&key.0)),
+                    self.block
+                ]),
+            )
+            .into(),
+        );
+        self.storage.borrow_mut().insert((address, key), value);
+        value
+    }
+}
+
+/// JSON-RPC encoding of an integer, hex without leading zeros.
+fn quantity(v: impl Into<U256>) -> Value {
+    Value::String(format!("{:#x}", v.into()))
+}
+
+/// JSON-RPC encoding of a byte string, hex with all leading zeros.
+fn data(bytes: &[u8]) -> Value {
+    Value::String(format!("0x{}", hex::encode(bytes)))
+}
+
+impl<T: RpcTransport> HostRead for RpcHost<T> {
+    fn account_exists(&self, address: Address) -> bool {
+        // EIP-161: empty accounts are treated as non-existent.
+        !self.get_balance(address).is_zero()
+            || self.with_account(address, |account| {
+                account.nonce != 0 || !account.code.is_empty()
+            })
+    }
+
+    fn get_storage(&self, address: Address, key: H256) -> H256 {
+        match self.overlay.get(&(address, key)) {
+            Some(slot) => slot.value,
+            None => self.remote_storage(address, key),
+        }
+    }
+
+    fn get_balance(&self, address: Address) -> U256 {
+        match self.balances.get(&address) {
+            Some(balance) => *balance,
+            None => self.with_account(address, |account| account.balance),
+        }
+    }
+
+    fn get_code_size(&self, address: Address) -> U256 {
+        self.with_account(address, |account| account.code.len().into())
+    }
+
+    fn get_code_hash(&self, address: Address) -> H256 {
+        if self.account_exists(address) {
+            self.with_account(address, |account| account.code_hash)
+        } else {
+            H256::zero()
+        }
+    }
+
+    fn copy_code(&self, address: Address, offset: usize, buffer: &mut [u8]) -> usize {
+        self.with_account(address, |account| {
+            let code = &account.code;
+            if offset >= code.len() {
+                return 0;
+            }
+
+            let n = min(buffer.len(), code.len() - offset);
+            buffer[..n].copy_from_slice(&code[offset..offset + n]);
+            n
+        })
+    }
+
+    fn call(&mut self, msg: &Message) -> Output {
+        // The sender of CALLCODE is the frame it is made from. That of DELEGATECALL is passed through,
+        // so the frame is only known if the host executed it.
+        let context = match msg.kind {
+            CallKind::Call => msg.destination,
+            CallKind::CallCode => msg.sender,
+            CallKind::DelegateCall => *self
+                .frames
+                .last()
+                .expect("DELEGATECALL from a frame not executed by RpcHost"),
+            CallKind::Create | CallKind::Create2 { .. } => {
+                panic!("RpcHost does not support contract creation")
+            }
+        };
+        if is_mainnet_precompile(msg.destination, self.revision) {
+            panic!("RpcHost does not support precompile {:?}", msg.destination);
+        }
+
+        let checkpoint = self.checkpoint();
+        if msg.kind == CallKind::Call && !msg.value.is_zero() {
+            self.transfer(msg.sender, msg.destination, msg.value);
+        }
+
+        let code = AnalyzedCode::analyze(self.code(msg.destination).to_vec());
+        let revision = self.revision;
+        self.frames.push(context);
+        let output = code.execute(
+            self,
+            &mut NoopTracer,
+            None,
+            Message {
+                destination: context,
+                ..msg.clone()
+            },
+            revision,
+        );
+        self.frames.pop();
+
+        if output.status_code != StatusCode::Success {
+            self.roll_back(checkpoint);
+        }
+        output
+    }
+
+    fn get_tx_context(&self) -> TxContext {
+        self.tx_context.clone()
+    }
+
+    fn get_block_hash(&self, block_number: u64) -> H256 {
+        if let Some(hash) = self.block_hashes.borrow().get(&block_number) {
+            return *hash;
+        }
+
+        let block = self.request(
+            "eth_getBlockByNumber",
+            json!([quantity(block_number), false]),
+        );
+        let hash = match &block["hash"] {
+            // Unknown block.
+            Value::Null => H256::zero(),
+            Value::String(hash) => hash
+                .strip_prefix("0x")
+                .and_then(|hash| hex::decode(hash).ok())
+                .filter(|hash| hash.len() == 32)
+                .map(|hash| H256::from_slice(&hash))
+                .unwrap_or_else(|| panic!("eth_getBlockByNumber returned invalid hash {}", hash)),
+            other => panic!("eth_getBlockByNumber returned invalid hash {}", other),
+        };
+        self.block_hashes.borrow_mut().insert(block_number, hash);
+        hash
+    }
+
+    fn access_account(&mut self, address: Address) -> AccessStatus {
+        if self.accessed.insert((address, None)) {
+            AccessStatus::Cold
+        } else {
+            AccessStatus::Warm
+        }
+    }
+
+    fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus {
+        if self.accessed.insert((address, Some(key))) {
+            AccessStatus::Cold
+        } else {
+            AccessStatus::Warm
+        }
+    }
+
+    fn peek_account_access(&self, address: Address) -> Option<AccessStatus> {
+        Some(if self.accessed.contains(&(address, None)) {
+            AccessStatus::Warm
+        } else {
+            AccessStatus::Cold
+        })
+    }
+
+    fn peek_storage_access(&self, address: Address, key: H256) -> Option<AccessStatus> {
+        Some(if self.accessed.contains(&(address, Some(key))) {
+            AccessStatus::Warm
+        } else {
+            AccessStatus::Cold
+        })
+    }
+}
+
+impl<T: RpcTransport> HostWrite for RpcHost<T> {
+    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus {
+        if !self.overlay.contains_key(&(address, key)) {
+            let original = self.remote_storage(address, key);
+            self.overlay
+                .insert((address, key), DryRunSlot::new(original));
+        }
+        self.overlay.get_mut(&(address, key)).unwrap().write(value)
+    }
+
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address) {
+        self.selfdestructs.push((address, beneficiary));
+    }
+
+    fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]) {
+        self.logs.push(RpcLog {
+            address,
+            data: data.to_vec().into(),
+            topics: topics.to_vec(),
+        });
+    }
+}
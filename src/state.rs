@@ -1,9 +1,16 @@
-use crate::common::{Message, Revision};
+use crate::{
+    common::{
+        AccessList, CallKind, Config, GasBreakdown, Message, Revision, RevisionSpec, Spec,
+        StatusCode,
+    },
+    host::StorageStatus,
+};
 use arrayvec::ArrayVec;
 use bytes::Bytes;
-use ethereum_types::U256;
+use ethereum_types::{Address, H256, U256};
 use getset::{Getters, MutGetters};
 use serde::Serialize;
+use std::{cell::RefCell, collections::HashMap, marker::PhantomData};
 
 const SIZE: usize = 1024;
 
@@ -37,6 +44,11 @@ impl Stack {
         self.len() == 0
     }
 
+    /// Stack items, from the bottom to the top.
+    pub fn as_slice(&self) -> &[U256] {
+        &self.0
+    }
+
     pub fn push(&mut self, v: U256) {
         unsafe { self.0.push_unchecked(v) }
     }
@@ -54,34 +66,269 @@ impl Stack {
 
 pub type Memory = Vec<u8>;
 
+/// Maximum number of memory buffers kept for reuse, one per possible call frame.
+const MEMORY_POOL_SIZE: usize = 1024;
+
+/// Buffers larger than this are not kept for reuse.
+const MAX_POOLED_MEMORY_CAPACITY: usize = 1024 * 1024;
+
+/// Maximum total capacity of the buffers kept for reuse.
+const MAX_POOLED_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Default)]
+struct MemoryPool {
+    buffers: Vec<Memory>,
+    /// Total capacity of `buffers`.
+    bytes: usize,
+}
+
+thread_local! {
+    static MEMORY_POOL: RefCell<MemoryPool> = RefCell::new(MemoryPool::default());
+}
+
+/// Take a memory buffer for the new frame, reusing previously released ones when possible.
+fn checkout_memory() -> Memory {
+    MEMORY_POOL
+        .with(|pool| {
+            let mut pool = pool.borrow_mut();
+            let memory = pool.buffers.pop()?;
+            pool.bytes -= memory.capacity();
+            Some(memory)
+        })
+        .unwrap_or_else(|| Memory::with_capacity(4 * 1024))
+}
+
+/// Return memory buffer of the finished frame for reuse.
+///
+/// The buffer is cleared, so that the next frame sees its memory as zeroed.
+pub(crate) fn release_memory(mut memory: Memory) {
+    if memory.capacity() > MAX_POOLED_MEMORY_CAPACITY {
+        return;
+    }
+
+    memory.clear();
+    MEMORY_POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.buffers.len() < MEMORY_POOL_SIZE
+            && pool.bytes + memory.capacity() <= MAX_POOLED_MEMORY_BYTES
+        {
+            pool.bytes += memory.capacity();
+            pool.buffers.push(memory);
+        }
+    });
+}
+
+/// Storage slot written in dry run mode.
+#[derive(Clone, Debug)]
+pub(crate) struct DryRunSlot {
+    pub value: H256,
+    original: H256,
+}
+
+impl DryRunSlot {
+    pub fn new(value: H256) -> Self {
+        Self {
+            value,
+            original: value,
+        }
+    }
+
+    /// Write the value and return the status the host would have reported (EIP-2200).
+    pub fn write(&mut self, value: H256) -> StorageStatus {
+        let status = StorageStatus::new(self.original, self.value, value);
+        self.value = value;
+        status
+    }
+}
+
+/// Where the interpreter takes the revision and the EIPs following from it from.
+pub(crate) trait Rules: 'static {
+    fn revision(state: &ExecutionState) -> Revision;
+    fn eip2929(state: &ExecutionState) -> bool;
+}
+
+/// Rules of the execution state, known at runtime.
+pub(crate) struct Dynamic;
+
+impl Rules for Dynamic {
+    fn revision(state: &ExecutionState) -> Revision {
+        state.evm_revision
+    }
+
+    fn eip2929(state: &ExecutionState) -> bool {
+        state.spec.eip2929
+    }
+}
+
+/// Mainnet rules of a revision known at compile time, so that checks against it fold away.
+///
+/// Only valid if the spec of the execution state is [`Spec::mainnet`] for the revision.
+pub(crate) struct Specialized<S>(PhantomData<S>);
+
+impl<S: RevisionSpec> Rules for Specialized<S> {
+    #[inline]
+    fn revision(_: &ExecutionState) -> Revision {
+        S::REVISION
+    }
+
+    #[inline]
+    fn eip2929(_: &ExecutionState) -> bool {
+        S::REVISION >= Revision::Berlin
+    }
+}
+
 /// EVM execution state.
 #[derive(Clone, Debug, Getters, MutGetters)]
 pub struct ExecutionState {
     #[getset(get = "pub", get_mut = "pub")]
     pub(crate) gas_left: i64,
+    /// Gas refund accumulated so far, see [`Output::gas_refund`](crate::Output::gas_refund).
+    #[getset(get = "pub")]
+    pub(crate) gas_refund: i64,
     #[getset(get = "pub", get_mut = "pub")]
     pub(crate) stack: Stack,
     #[getset(get = "pub", get_mut = "pub")]
     pub(crate) memory: Memory,
     pub(crate) message: Message,
     pub(crate) evm_revision: Revision,
+    /// EIPs active in this execution, from [`Config::spec`].
+    #[getset(get = "pub")]
+    pub(crate) spec: Spec,
     #[getset(get = "pub", get_mut = "pub")]
     pub(crate) return_data: Bytes,
     pub(crate) output_data: Bytes,
+    #[getset(get = "pub")]
+    pub(crate) config: Config,
+    pub(crate) dry_run_storage: HashMap<H256, DryRunSlot>,
+    /// Gas used so far by category, if [enabled](Config::gas_breakdown).
+    #[getset(get = "pub")]
+    pub(crate) gas_breakdown: Option<GasBreakdown>,
+    /// Accounts and storage keys accessed so far, if [enabled](Config::access_list).
+    #[getset(get = "pub")]
+    pub(crate) access_list: Option<AccessList>,
 }
 
 impl ExecutionState {
     pub fn new(message: Message, evm_revision: Revision) -> Self {
+        Self::with_config(message, evm_revision, Config::default())
+    }
+
+    pub fn with_config(message: Message, evm_revision: Revision, config: Config) -> Self {
         Self {
             gas_left: message.gas,
+            gas_refund: 0,
             stack: Default::default(),
-            memory: Memory::with_capacity(4 * 1024),
+            memory: if config.reuse_memory {
+                checkout_memory()
+            } else {
+                Memory::with_capacity(4 * 1024)
+            },
             message,
             evm_revision,
+            spec: (config.spec)(evm_revision),
             return_data: Default::default(),
             output_data: Bytes::new(),
+            gas_breakdown: config.gas_breakdown.then(GasBreakdown::default),
+            access_list: config.access_list.then(AccessList::new),
+            config,
+            dry_run_storage: HashMap::new(),
+        }
+    }
+
+    /// Whether this frame runs init code of CREATE or CREATE2.
+    ///
+    /// The code being executed is then not the code of `message.destination`, which has none until
+    /// the frame returns. EXTCODEHASH of it gives the empty code hash; EXTCODESIZE is up to the host.
+    pub fn is_create(&self) -> bool {
+        matches!(
+            self.message.kind,
+            CallKind::Create | CallKind::Create2 { .. }
+        )
+    }
+
+    /// Deduct the cost from the gas left, failing if it runs out.
+    ///
+    /// Does nothing if [metering](Config::metering) is disabled.
+    #[inline]
+    pub(crate) fn charge(&mut self, cost: i64) -> Result<(), StatusCode> {
+        if self.config.metering {
+            self.gas_left -= cost;
+            if self.gas_left < 0 {
+                return Err(StatusCode::OutOfGas);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the account, and the storage key if given, to the access list if it is collected.
+    pub(crate) fn record_access(&mut self, address: Address, key: Option<H256>) {
+        if let Some(access_list) = &mut self.access_list {
+            let keys = access_list.entry(address).or_default();
+            if let Some(key) = key {
+                keys.insert(key);
+            }
         }
     }
+
+    /// Merge the accesses a nested frame reported in its output into the access list if it is collected.
+    pub(crate) fn record_nested_accesses(&mut self, nested: &Option<AccessList>) {
+        if let (Some(access_list), Some(nested)) = (&mut self.access_list, nested) {
+            for (address, keys) in nested {
+                access_list
+                    .entry(*address)
+                    .or_default()
+                    .extend(keys.iter().copied());
+            }
+        }
+    }
+
+    /// Capture the frame-local state before the instruction at `pc`.
+    pub(crate) fn snapshot(&self, pc: usize) -> StateSnapshot {
+        StateSnapshot {
+            pc,
+            gas_left: self.gas_left,
+            gas_refund: self.gas_refund,
+            stack: self.stack.clone(),
+            memory: Bytes::copy_from_slice(&self.memory),
+            return_data: self.return_data.clone(),
+            output_data: self.output_data.clone(),
+            dry_run_storage: self.dry_run_storage.clone(),
+            gas_breakdown: self.gas_breakdown,
+        }
+    }
+
+    /// Roll back to the snapshot taken earlier in the same frame. Rewinding the pc is up to the caller.
+    pub(crate) fn restore(&mut self, snapshot: StateSnapshot) {
+        self.gas_left = snapshot.gas_left;
+        self.gas_refund = snapshot.gas_refund;
+        self.stack = snapshot.stack;
+        // Keep the allocated buffer.
+        self.memory.clear();
+        self.memory.extend_from_slice(&snapshot.memory);
+        self.return_data = snapshot.return_data;
+        self.output_data = snapshot.output_data;
+        self.dry_run_storage = snapshot.dry_run_storage;
+        self.gas_breakdown = snapshot.gas_breakdown;
+    }
+}
+
+/// Frame-local part of [`ExecutionState`] and the pc, captured by [`InstructionStart::snapshot`].
+///
+/// The snapshot is cheap to clone, so it can be restored more than once.
+///
+/// [`InstructionStart::snapshot`]: crate::continuation::interrupt_data::InstructionStart::snapshot
+#[derive(Clone, Debug)]
+pub struct StateSnapshot {
+    pub(crate) pc: usize,
+    gas_left: i64,
+    gas_refund: i64,
+    stack: Stack,
+    memory: Bytes,
+    return_data: Bytes,
+    output_data: Bytes,
+    dry_run_storage: HashMap<H256, DryRunSlot>,
+    gas_breakdown: Option<GasBreakdown>,
 }
 
 #[cfg(test)]
@@ -105,4 +352,41 @@ mod tests {
 
         assert_eq!(*stack.get(2), 0xde.into());
     }
+
+    #[test]
+    fn stack_as_slice() {
+        let mut stack = Stack::default();
+        assert!(stack.as_slice().is_empty());
+
+        stack.push(1.into());
+        stack.push(2.into());
+        stack.push(3.into());
+        assert_eq!(
+            stack.as_slice(),
+            &[U256::from(1), U256::from(2), U256::from(3)]
+        );
+
+        stack.pop();
+        stack.swap_top(1);
+        assert_eq!(stack.as_slice(), &[U256::from(2), U256::from(1)]);
+    }
+
+    #[test]
+    fn memory_pool_capped() {
+        for _ in 0..MEMORY_POOL_SIZE {
+            release_memory(Memory::with_capacity(MAX_POOLED_MEMORY_CAPACITY));
+        }
+        MEMORY_POOL.with(|pool| {
+            let pool = pool.borrow();
+            assert!(pool.bytes <= MAX_POOLED_MEMORY_BYTES);
+            assert_eq!(pool.bytes, pool.buffers.iter().map(Memory::capacity).sum());
+        });
+
+        let memory = checkout_memory();
+        assert!(memory.is_empty());
+        MEMORY_POOL.with(|pool| {
+            let pool = pool.borrow();
+            assert_eq!(pool.bytes, pool.buffers.iter().map(Memory::capacity).sum());
+        });
+    }
 }
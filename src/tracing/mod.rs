@@ -1,5 +1,6 @@
 use super::*;
 use crate::state::*;
+use ethereum_types::U256;
 use serde::Serialize;
 
 /// Passed into execution context to collect metrics.
@@ -13,9 +14,20 @@ pub trait Tracer {
     fn notify_instruction_start(&mut self, pc: usize, opcode: OpCode, state: &ExecutionState);
     /// Called when execution ends.
     fn notify_execution_end(&mut self, output: &Output);
+    /// Called before a sub-call or create of depth `depth` is handed to the host.
+    ///
+    /// Only fires when evmodin answers the call interrupt itself, as in [`AnalyzedCode::execute`] and
+    /// `run_to_completion_with_host`. Embedders answering [`CallInterrupt`](crate::continuation::interrupt::CallInterrupt)
+    /// through the resumable API get no call notifications.
+    fn notify_call_enter(&mut self, _depth: i32, _gas: i64) {}
+    /// Called when a sub-call or create of depth `depth` returns from the host.
+    ///
+    /// Fires in the same cases as [`Self::notify_call_enter`].
+    fn notify_call_exit(&mut self, _depth: i32, _gas_left: i64, _status: &StatusCode) {}
 }
 
 /// Tracer which does nothing.
+#[derive(Clone, Copy, Debug)]
 pub struct NoopTracer;
 
 impl Tracer for NoopTracer {
@@ -28,6 +40,52 @@ impl Tracer for NoopTracer {
     fn notify_execution_end(&mut self, _: &Output) {}
 }
 
+/// Tracer which counts executed opcodes, accumulating over all executions it is used for.
+#[derive(Clone, Debug)]
+pub struct OpcodeCoverageTracer {
+    counts: [u64; 256],
+}
+
+impl Default for OpcodeCoverageTracer {
+    fn default() -> Self {
+        Self { counts: [0; 256] }
+    }
+}
+
+impl OpcodeCoverageTracer {
+    /// How many times the opcode has been executed.
+    pub fn count(&self, opcode: OpCode) -> u64 {
+        self.counts[opcode.to_usize()]
+    }
+
+    /// Whether the opcode has been executed at least once.
+    pub fn is_covered(&self, opcode: OpCode) -> bool {
+        self.count(opcode) > 0
+    }
+
+    /// Opcodes defined in the revision that have never been executed.
+    pub fn uncovered(&self, revision: Revision) -> Vec<OpCode> {
+        let instruction_table =
+            crate::instructions::instruction_table::get_baseline_instruction_table(revision);
+        (0..=u8::MAX)
+            .map(OpCode)
+            .filter(|&opcode| {
+                instruction_table[opcode.to_usize()].is_some() && !self.is_covered(opcode)
+            })
+            .collect()
+    }
+}
+
+impl Tracer for OpcodeCoverageTracer {
+    fn notify_execution_start(&mut self, _: Revision, _: Message, _: Bytes) {}
+
+    fn notify_instruction_start(&mut self, _: usize, opcode: OpCode, _: &ExecutionState) {
+        self.counts[opcode.to_usize()] += 1;
+    }
+
+    fn notify_execution_end(&mut self, _: &Output) {}
+}
+
 #[derive(Serialize)]
 struct ExecutionStart {
     pub depth: i32,
@@ -38,13 +96,15 @@ struct ExecutionStart {
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct InstructionStart {
+pub(crate) struct InstructionStart<'a> {
     pub pc: usize,
     pub op: u8,
     pub op_name: &'static str,
     pub gas: i64,
-    pub stack: Stack,
+    pub stack: &'a [U256],
     pub memory_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -59,12 +119,87 @@ pub(crate) struct ExecutionEnd {
 struct TracerContext {
     message: Message,
     code: Bytes,
+    /// Whether the previous instruction could have written to memory.
+    memory_written: bool,
+    /// Memory size seen at the previous instruction.
+    memory_size: usize,
+}
+
+/// Which instruction traces include memory contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryTraceMode {
+    /// Never include memory.
+    Never,
+    /// Include memory on every instruction.
+    Full,
+    /// Include memory only if the previous instruction changed it.
+    Changed,
+}
+
+impl Default for MemoryTraceMode {
+    fn default() -> Self {
+        Self::Never
+    }
+}
+
+/// Returns true if executing the instruction may modify memory contents.
+fn writes_memory(opcode: OpCode) -> bool {
+    matches!(
+        opcode,
+        OpCode::MSTORE
+            | OpCode::MSTORE8
+            | OpCode::CALLDATACOPY
+            | OpCode::CODECOPY
+            | OpCode::EXTCODECOPY
+            | OpCode::RETURNDATACOPY
+            | OpCode::CALL
+            | OpCode::CALLCODE
+            | OpCode::DELEGATECALL
+            | OpCode::STATICCALL
+    )
 }
 
 /// Tracer which prints to stdout.
 #[derive(Default)]
 pub struct StdoutTracer {
     execution_stack: Vec<TracerContext>,
+    memory: MemoryTraceMode,
+}
+
+impl StdoutTracer {
+    /// Create tracer which includes memory contents according to `memory`.
+    pub fn new(memory: MemoryTraceMode) -> Self {
+        Self {
+            execution_stack: Vec::new(),
+            memory,
+        }
+    }
+
+    fn instruction_start_json(&mut self, pc: usize, state: &ExecutionState) -> String {
+        let context = self.execution_stack.last_mut().unwrap();
+        let opcode = OpCode(context.code[pc]);
+
+        let memory_changed = context.memory_written || context.memory_size != state.memory.len();
+        context.memory_written = writes_memory(opcode);
+        context.memory_size = state.memory.len();
+
+        let memory = match self.memory {
+            MemoryTraceMode::Never => None,
+            MemoryTraceMode::Full => Some(hex::encode(&state.memory)),
+            MemoryTraceMode::Changed => memory_changed.then(|| hex::encode(&state.memory)),
+        };
+
+        serde_json::to_string(&InstructionStart {
+            pc,
+            op: opcode.0,
+            op_name: opcode.name(),
+            gas: state.gas_left,
+            stack: state.stack.as_slice(),
+            memory_size: state.memory.len(),
+            memory,
+        })
+        .unwrap()
+    }
 }
 
 impl Tracer for StdoutTracer {
@@ -78,24 +213,16 @@ impl Tracer for StdoutTracer {
             })
             .unwrap()
         );
-        self.execution_stack.push(TracerContext { message, code });
+        self.execution_stack.push(TracerContext {
+            message,
+            code,
+            memory_written: false,
+            memory_size: 0,
+        });
     }
 
     fn notify_instruction_start(&mut self, pc: usize, _: OpCode, state: &ExecutionState) {
-        let context = self.execution_stack.last().unwrap();
-        let opcode = OpCode(context.code[pc]);
-        println!(
-            "{}",
-            serde_json::to_string(&InstructionStart {
-                pc,
-                op: opcode.0,
-                op_name: opcode.name(),
-                gas: state.gas_left,
-                stack: state.stack.clone(),
-                memory_size: state.memory.len()
-            })
-            .unwrap()
-        )
+        println!("{}", self.instruction_start_json(pc, state))
     }
 
     fn notify_execution_end(&mut self, output: &Output) {
@@ -122,3 +249,72 @@ impl Tracer for StdoutTracer {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcode_coverage() {
+        // PUSH1 1, PUSH1 2, ADD
+        let code = AnalyzedCode::analyze(vec![0x60, 0x01, 0x60, 0x02, 0x01]);
+
+        let mut tracer = OpcodeCoverageTracer::default();
+        for _ in 0..2 {
+            let output = code.execute(
+                &mut crate::host::DummyHost,
+                &mut tracer,
+                None,
+                Message::builder().build(),
+                Revision::Istanbul,
+            );
+            assert_eq!(output.status_code, StatusCode::Success);
+        }
+
+        assert!(tracer.is_covered(OpCode::ADD));
+        assert_eq!(tracer.count(OpCode::ADD), 2);
+        assert_eq!(tracer.count(OpCode::PUSH1), 4);
+        assert!(!tracer.is_covered(OpCode::MUL));
+
+        let uncovered = tracer.uncovered(Revision::Istanbul);
+        assert!(!uncovered.contains(&OpCode::ADD));
+        assert!(uncovered.contains(&OpCode::MUL));
+        assert!(!uncovered.contains(&OpCode::BASEFEE));
+    }
+
+    #[test]
+    fn memory_trace_changed() {
+        // PUSH1 1, PUSH1 0, MSTORE, PUSH1 2, PUSH1 3, ADD
+        let code =
+            Bytes::from_static(&[0x60, 0x01, 0x60, 0x00, 0x52, 0x60, 0x02, 0x60, 0x03, 0x01]);
+        let message = Message::builder().build();
+
+        let mut tracer = StdoutTracer::new(MemoryTraceMode::Changed);
+        tracer.notify_execution_start(Revision::latest(), message.clone(), code);
+
+        let mut state = ExecutionState::new(message, Revision::latest());
+        assert!(!tracer
+            .instruction_start_json(0, &state)
+            .contains("memory\""));
+        assert!(!tracer
+            .instruction_start_json(2, &state)
+            .contains("memory\""));
+        assert!(!tracer
+            .instruction_start_json(4, &state)
+            .contains("memory\""));
+
+        // MSTORE wrote a word.
+        state.memory.resize(32, 0);
+        state.memory[31] = 1;
+        let trace = tracer.instruction_start_json(5, &state);
+        assert!(trace.contains(&format!("\"memory\":\"{}\"", hex::encode(&state.memory))));
+
+        // Pure stack manipulation and arithmetic.
+        assert!(!tracer
+            .instruction_start_json(7, &state)
+            .contains("memory\""));
+        assert!(!tracer
+            .instruction_start_json(9, &state)
+            .contains("memory\""));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::opcode::*;
+use ethereum_types::U256;
+
+/// Assemble whitespace separated mnemonics into bytecode.
+///
+/// `PUSHn` takes an immediate value, decimal or `0x`-prefixed hex, that must fit into `n` bytes.
+/// Panics on malformed input.
+///
+/// ```
+/// use evmodin::util::*;
+///
+/// let code = asm("PUSH1 1 PUSH1 2 ADD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN");
+/// assert_eq!(code, [0x60, 1, 0x60, 2, 0x01, 0x60, 0, 0x52, 0x60, 0x20, 0x60, 0, 0xf3]);
+///
+/// EvmTester::new().code(code).output_value(3).check();
+/// ```
+pub fn asm(source: &str) -> Vec<u8> {
+    let mut code = Vec::new();
+    let mut tokens = source.split_whitespace();
+    while let Some(mnemonic) = tokens.next() {
+        let op = (0..=u8::MAX)
+            .map(OpCode)
+            .find(|op| op.name() != "UNDEFINED" && op.name().eq_ignore_ascii_case(mnemonic))
+            .unwrap_or_else(|| panic!("unknown mnemonic {}", mnemonic));
+        code.push(op.to_u8());
+
+        if let Some(push_size) = op.push_size().map(usize::from) {
+            let immediate = tokens
+                .next()
+                .unwrap_or_else(|| panic!("{} without immediate value", mnemonic));
+            let value = if let Some(hex) = immediate.strip_prefix("0x") {
+                U256::from_str_radix(hex, 16).ok()
+            } else {
+                U256::from_dec_str(immediate).ok()
+            }
+            .unwrap_or_else(|| panic!("invalid immediate value {}", immediate));
+            assert!(
+                value.bits() <= push_size * 8,
+                "{} does not fit into {}",
+                immediate,
+                mnemonic
+            );
+
+            let mut buf = [0; 32];
+            value.to_big_endian(&mut buf);
+            code.extend_from_slice(&buf[32 - push_size..]);
+        }
+    }
+    code
+}
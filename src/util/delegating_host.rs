@@ -0,0 +1,233 @@
+use crate::{
+    host::*,
+    tracing::{NoopTracer, Tracer},
+    *,
+};
+use ethereum_types::{Address, H256, U256};
+
+/// Host wrapper for tests that delegates everything to the wrapped host, unless configured to answer
+/// some queries by itself.
+#[derive(Clone, Debug)]
+pub struct DelegatingHost<H, T = NoopTracer> {
+    inner: H,
+    execute_calls: Option<Revision>,
+    config: Config,
+    callee: Option<AnalyzedCode>,
+    tracer: T,
+    batch_accesses: bool,
+    read_only: bool,
+    results: Vec<(i32, StatusCode)>,
+    batched: usize,
+}
+
+impl<H: Host> DelegatingHost<H> {
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            execute_calls: None,
+            config: Config::default(),
+            callee: None,
+            tracer: NoopTracer,
+            batch_accesses: false,
+            read_only: false,
+            results: vec![],
+            batched: 0,
+        }
+    }
+}
+
+impl<H: Host, T: Tracer + Clone> DelegatingHost<H, T> {
+    /// Execute calls in `revision` with the code of their destination, instead of delegating them.
+    pub fn execute_calls(mut self, revision: Revision) -> Self {
+        self.execute_calls = Some(revision);
+        self
+    }
+
+    /// Execute calls with `config` instead of the default one.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Execute calls with `code`, whatever their destination. Implies [`Self::execute_calls`] in `revision`.
+    pub fn callee(mut self, code: AnalyzedCode, revision: Revision) -> Self {
+        self.callee = Some(code);
+        self.execute_calls(revision)
+    }
+
+    /// Trace executed calls with clones of `tracer`.
+    pub fn tracer<U: Tracer + Clone>(self, tracer: U) -> DelegatingHost<H, U> {
+        DelegatingHost {
+            inner: self.inner,
+            execute_calls: self.execute_calls,
+            config: self.config,
+            callee: self.callee,
+            tracer,
+            batch_accesses: self.batch_accesses,
+            read_only: self.read_only,
+            results: self.results,
+            batched: self.batched,
+        }
+    }
+
+    /// Answer storage and balance accesses only together with the value, as in
+    /// [`HostRead::access_and_get_storage`] and [`HostRead::access_and_get_balance`].
+    ///
+    /// Separate [`HostRead::access_storage`] and [`HostRead::access_account`] queries panic.
+    pub fn batch_accesses(mut self) -> Self {
+        self.batch_accesses = true;
+        self
+    }
+
+    /// Panic on every write, e.g. to check that a dry run leaves the state alone.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Depth and status of every executed call, in the order they completed.
+    pub fn results(&self) -> &[(i32, StatusCode)] {
+        &self.results
+    }
+
+    /// Number of accesses answered together with the value.
+    pub fn batched(&self) -> usize {
+        self.batched
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H: Host, T: Tracer + Clone> HostRead for DelegatingHost<H, T> {
+    fn account_exists(&self, address: Address) -> bool {
+        self.inner.account_exists(address)
+    }
+
+    fn get_storage(&self, address: Address, key: H256) -> H256 {
+        self.inner.get_storage(address, key)
+    }
+
+    fn get_balance(&self, address: Address) -> U256 {
+        self.inner.get_balance(address)
+    }
+
+    fn get_code_size(&self, address: Address) -> U256 {
+        self.inner.get_code_size(address)
+    }
+
+    fn get_code_hash(&self, address: Address) -> H256 {
+        self.inner.get_code_hash(address)
+    }
+
+    fn copy_code(&self, address: Address, offset: usize, buffer: &mut [u8]) -> usize {
+        self.inner.copy_code(address, offset, buffer)
+    }
+
+    fn call(&mut self, msg: &Message) -> Output {
+        let revision = match self.execute_calls {
+            Some(revision) => revision,
+            None => return self.inner.call(msg),
+        };
+
+        let code = self.callee.clone().unwrap_or_else(|| {
+            let mut code = vec![0; self.inner.get_code_size(msg.destination).as_usize()];
+            self.inner.copy_code(msg.destination, 0, &mut code);
+            AnalyzedCode::analyze(code)
+        });
+        let mut tracer = self.tracer.clone();
+        let config = self.config.clone();
+        let output =
+            code.execute_with_config(self, &mut tracer, None, msg.clone(), revision, config);
+        self.results.push((msg.depth, output.status_code.clone()));
+        output
+    }
+
+    fn authorize_call(&mut self, msg: &Message) -> bool {
+        self.inner.authorize_call(msg)
+    }
+
+    fn prefetch_storage(&mut self, address: Address, keys: &[H256]) {
+        self.inner.prefetch_storage(address, keys)
+    }
+
+    fn get_tx_context(&self) -> TxContext {
+        self.inner.get_tx_context()
+    }
+
+    fn get_block_hash(&self, block_number: u64) -> H256 {
+        self.inner.get_block_hash(block_number)
+    }
+
+    fn access_account(&mut self, address: Address) -> AccessStatus {
+        assert!(
+            !self.batch_accesses,
+            "must be answered with access_and_get_balance"
+        );
+        self.inner.access_account(address)
+    }
+
+    fn access_storage(&mut self, address: Address, key: H256) -> AccessStatus {
+        assert!(
+            !self.batch_accesses,
+            "must be answered with access_and_get_storage"
+        );
+        self.inner.access_storage(address, key)
+    }
+
+    fn access_and_get_storage(&mut self, address: Address, key: H256) -> (AccessStatus, H256) {
+        if !self.batch_accesses {
+            return self.inner.access_and_get_storage(address, key);
+        }
+        self.batched += 1;
+        (
+            self.inner.access_storage(address, key),
+            self.inner.get_storage(address, key),
+        )
+    }
+
+    fn access_and_get_balance(&mut self, address: Address) -> (AccessStatus, U256) {
+        if !self.batch_accesses {
+            return self.inner.access_and_get_balance(address);
+        }
+        self.batched += 1;
+        (
+            self.inner.access_account(address),
+            self.inner.get_balance(address),
+        )
+    }
+
+    fn peek_account_access(&self, address: Address) -> Option<AccessStatus> {
+        self.inner.peek_account_access(address)
+    }
+
+    fn peek_storage_access(&self, address: Address, key: H256) -> Option<AccessStatus> {
+        self.inner.peek_storage_access(address, key)
+    }
+}
+
+impl<H: Host, T: Tracer + Clone> HostWrite for DelegatingHost<H, T> {
+    fn set_storage(&mut self, address: Address, key: H256, value: H256) -> StorageStatus {
+        assert!(!self.read_only, "set_storage on read-only host");
+        self.inner.set_storage(address, key, value)
+    }
+
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address) {
+        assert!(!self.read_only, "selfdestruct on read-only host");
+        self.inner.selfdestruct(address, beneficiary)
+    }
+
+    fn emit_log(&mut self, address: Address, data: &[u8], topics: &[H256]) {
+        assert!(!self.read_only, "emit_log on read-only host");
+        self.inner.emit_log(address, data, topics)
+    }
+}
@@ -3,7 +3,10 @@ use bytes::Bytes;
 use ethereum_types::{Address, H256, U256};
 use hex_literal::hex;
 use parking_lot::Mutex;
-use std::{cmp::min, collections::HashMap};
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+};
 
 /// LOG record.
 #[derive(Clone, Debug, PartialEq)]
@@ -30,6 +33,9 @@ pub struct SelfdestructRecord {
 #[derive(Clone, Debug, Default)]
 pub struct StorageValue {
     pub value: H256,
+    /// Value at the start of the transaction, if `dirty`. Otherwise it is `value`.
+    pub original: H256,
+    /// Whether the value has been written in the transaction.
     pub dirty: bool,
     pub access_status: AccessStatus,
 }
@@ -69,6 +75,8 @@ pub struct MockedHost {
     pub tx_context: TxContext,
     pub block_hash: H256,
     pub call_result: Output,
+    /// Destinations [`HostRead::authorize_call`] denies calls to.
+    pub denied_calls: HashSet<Address>,
     pub recorded: Mutex<Records>,
 }
 
@@ -79,6 +87,7 @@ impl Clone for MockedHost {
             tx_context: self.tx_context.clone(),
             block_hash: self.block_hash,
             call_result: self.call_result.clone(),
+            denied_calls: self.denied_calls.clone(),
             recorded: Mutex::new(self.recorded.lock().clone()),
         }
     }
@@ -88,29 +97,54 @@ impl Default for MockedHost {
     fn default() -> Self {
         Self {
             accounts: Default::default(),
-            tx_context: TxContext {
-                tx_gas_price: U256::zero(),
-                tx_origin: Address::zero(),
-                block_coinbase: Address::zero(),
-                block_number: 0,
-                block_timestamp: 0,
-                block_gas_limit: 0,
-                block_difficulty: U256::zero(),
-                chain_id: U256::zero(),
-                block_base_fee: U256::zero(),
-            },
+            tx_context: TxContext::builder().build(),
             block_hash: H256::zero(),
             call_result: Output {
                 status_code: StatusCode::Success,
                 gas_left: 0,
+                gas_refund: 0,
                 output_data: Bytes::new(),
                 create_address: Some(Address::zero()),
+                failure_location: None,
+                gas_breakdown: None,
+                opcode_trace: None,
+                access_list: None,
+                peak_memory: 0,
             },
+            denied_calls: Default::default(),
             recorded: Default::default(),
         }
     }
 }
 
+impl MockedHost {
+    /// Logs emitted so far, in order.
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.recorded.lock().logs.clone()
+    }
+
+    fn account_access_status(&self, address: Address) -> AccessStatus {
+        if address.0 >= hex!("0000000000000000000000000000000000000001")
+            && address.0 <= hex!("0000000000000000000000000000000000000009")
+        {
+            return AccessStatus::Warm;
+        }
+
+        // Check if the address have been already accessed.
+        if self
+            .recorded
+            .lock()
+            .account_accesses
+            .iter()
+            .any(|&a| a == address)
+        {
+            AccessStatus::Warm
+        } else {
+            AccessStatus::Cold
+        }
+    }
+}
+
 impl Records {
     fn record_account_access(&mut self, address: Address) {
         if self.account_accesses.len() < MAX_RECORDED_ACCOUNT_ACCESSES {
@@ -119,7 +153,7 @@ impl Records {
     }
 }
 
-impl crate::Host for MockedHost {
+impl HostRead for MockedHost {
     fn account_exists(&self, address: ethereum_types::Address) -> bool {
         self.recorded.lock().record_account_access(address);
         self.accounts.contains_key(&address)
@@ -134,51 +168,6 @@ impl crate::Host for MockedHost {
             .unwrap_or_else(H256::zero)
     }
 
-    fn set_storage(
-        &mut self,
-        address: ethereum_types::Address,
-        key: H256,
-        value: H256,
-    ) -> StorageStatus {
-        self.recorded.lock().record_account_access(address);
-
-        // Get the reference to the old value.
-        // This will create the account in case it was not present.
-        // This is convenient for unit testing and standalone EVM execution to preserve the
-        // storage values after the execution terminates.
-        let old = self
-            .accounts
-            .entry(address)
-            .or_default()
-            .storage
-            .entry(key)
-            .or_default();
-
-        // Follow https://eips.ethereum.org/EIPS/eip-1283 specification.
-        // WARNING! This is not complete implementation as refund is not handled here.
-
-        if old.value == value {
-            return StorageStatus::Unchanged;
-        }
-
-        let status = if !old.dirty {
-            old.dirty = true;
-            if old.value.is_zero() {
-                StorageStatus::Added
-            } else if !value.is_zero() {
-                StorageStatus::Modified
-            } else {
-                StorageStatus::Deleted
-            }
-        } else {
-            StorageStatus::ModifiedAgain
-        };
-
-        old.value = value;
-
-        status
-    }
-
     fn get_balance(&self, address: ethereum_types::Address) -> ethereum_types::U256 {
         self.recorded.lock().record_account_access(address);
 
@@ -227,20 +216,6 @@ impl crate::Host for MockedHost {
             .unwrap_or(0)
     }
 
-    fn selfdestruct(
-        &mut self,
-        address: ethereum_types::Address,
-        beneficiary: ethereum_types::Address,
-    ) {
-        let mut r = self.recorded.lock();
-
-        r.record_account_access(address);
-        r.selfdestructs.push(SelfdestructRecord {
-            selfdestructed: address,
-            beneficiary,
-        });
-    }
-
     fn call(&mut self, msg: &Message) -> Output {
         let mut r = self.recorded.lock();
 
@@ -265,33 +240,10 @@ impl crate::Host for MockedHost {
         self.block_hash
     }
 
-    fn emit_log(&mut self, address: ethereum_types::Address, data: &[u8], topics: &[H256]) {
-        self.recorded.lock().logs.push(LogRecord {
-            creator: address,
-            data: data.to_vec().into(),
-            topics: topics.to_vec(),
-        });
-    }
-
     fn access_account(&mut self, address: ethereum_types::Address) -> AccessStatus {
-        let mut r = self.recorded.lock();
-
-        // Check if the address have been already accessed.
-        let already_accessed = r.account_accesses.iter().any(|&a| a == address);
-
-        r.record_account_access(address);
-
-        if address.0 >= hex!("0000000000000000000000000000000000000001")
-            && address.0 <= hex!("0000000000000000000000000000000000000009")
-        {
-            return AccessStatus::Warm;
-        }
-
-        if already_accessed {
-            AccessStatus::Warm
-        } else {
-            AccessStatus::Cold
-        }
+        let status = self.account_access_status(address);
+        self.recorded.lock().record_account_access(address);
+        status
     }
 
     fn access_storage(&mut self, address: ethereum_types::Address, key: H256) -> AccessStatus {
@@ -306,4 +258,80 @@ impl crate::Host for MockedHost {
         value.access_status = AccessStatus::Warm;
         access_status
     }
+
+    fn authorize_call(&mut self, msg: &Message) -> bool {
+        !self.denied_calls.contains(&msg.destination)
+    }
+
+    fn peek_account_access(&self, address: Address) -> Option<AccessStatus> {
+        Some(self.account_access_status(address))
+    }
+
+    fn peek_storage_access(&self, address: Address, key: H256) -> Option<AccessStatus> {
+        Some(
+            self.accounts
+                .get(&address)
+                .and_then(|account| account.storage.get(&key))
+                .map(|value| value.access_status)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+impl HostWrite for MockedHost {
+    fn set_storage(
+        &mut self,
+        address: ethereum_types::Address,
+        key: H256,
+        value: H256,
+    ) -> StorageStatus {
+        self.recorded.lock().record_account_access(address);
+
+        // Get the reference to the old value.
+        // This will create the account in case it was not present.
+        // This is convenient for unit testing and standalone EVM execution to preserve the
+        // storage values after the execution terminates.
+        let old = self
+            .accounts
+            .entry(address)
+            .or_default()
+            .storage
+            .entry(key)
+            .or_default();
+
+        // Follow https://eips.ethereum.org/EIPS/eip-2200 specification.
+        if !old.dirty {
+            old.original = old.value;
+        }
+        let status = StorageStatus::new(old.original, old.value, value);
+
+        if status != StorageStatus::Unchanged {
+            old.dirty = true;
+        }
+        old.value = value;
+
+        status
+    }
+
+    fn selfdestruct(
+        &mut self,
+        address: ethereum_types::Address,
+        beneficiary: ethereum_types::Address,
+    ) {
+        let mut r = self.recorded.lock();
+
+        r.record_account_access(address);
+        r.selfdestructs.push(SelfdestructRecord {
+            selfdestructed: address,
+            beneficiary,
+        });
+    }
+
+    fn emit_log(&mut self, address: ethereum_types::Address, data: &[u8], topics: &[H256]) {
+        self.recorded.lock().logs.push(LogRecord {
+            creator: address,
+            data: data.to_vec().into(),
+            topics: topics.to_vec(),
+        });
+    }
 }
@@ -1,6 +1,12 @@
+mod asm;
 mod bytecode;
+mod delegating_host;
 pub mod mocked_host;
+mod script;
 mod tester;
 
+pub use asm::*;
 pub use bytecode::*;
+pub use delegating_host::*;
+pub use script::*;
 pub use tester::*;
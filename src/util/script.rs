@@ -0,0 +1,134 @@
+use crate::{
+    continuation::{interrupt::*, Interrupt},
+    interpreter::complete_output,
+    Output,
+};
+
+/// Interrupt that can be answered by a [`ScriptStep`].
+pub trait ScriptedInterrupt: Interrupt + Sized {
+    /// Name of the interrupt in [`InterruptVariant`].
+    const NAME: &'static str;
+
+    fn from_variant(interrupt: InterruptVariant) -> Option<Self>;
+}
+
+macro_rules! scripted_interrupts {
+    ($($variant:ident => $interrupt:ident),* $(,)?) => {
+        $(
+            impl ScriptedInterrupt for $interrupt {
+                const NAME: &'static str = stringify!($variant);
+
+                fn from_variant(interrupt: InterruptVariant) -> Option<Self> {
+                    match interrupt {
+                        InterruptVariant::$variant(i) => Some(i),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+scripted_interrupts! {
+    AccountExists => AccountExistsInterrupt,
+    GetStorage => GetStorageInterrupt,
+    SetStorage => SetStorageInterrupt,
+    GetBalance => GetBalanceInterrupt,
+    GetCodeSize => GetCodeSizeInterrupt,
+    GetCodeHash => GetCodeHashInterrupt,
+    CopyCode => CopyCodeInterrupt,
+    Selfdestruct => SelfdestructInterrupt,
+    Call => CallInterrupt,
+    GetTxContext => GetTxContextInterrupt,
+    GetBlockHash => GetBlockHashInterrupt,
+    EmitLog => EmitLogInterrupt,
+    AccessAccount => AccessAccountInterrupt,
+    AccessStorage => AccessStorageInterrupt,
+    AccessAndGetStorage => AccessAndGetStorageInterrupt,
+    AccessAndGetBalance => AccessAndGetBalanceInterrupt,
+    PrefetchStorage => PrefetchStorageInterrupt,
+}
+
+/// Interrupt expected next by [`run_with_script`], with the data to resume it with.
+pub struct ScriptStep {
+    expected: &'static str,
+    resume: Box<dyn FnOnce(InterruptVariant) -> Option<InterruptVariant>>,
+}
+
+impl ScriptStep {
+    /// Expect interrupt `I` and resume it with `resume_data`.
+    pub fn answer<I: ScriptedInterrupt>(resume_data: I::ResumeData) -> Self
+    where
+        I::ResumeData: 'static,
+    {
+        Self {
+            expected: I::NAME,
+            resume: Box::new(move |interrupt| {
+                I::from_variant(interrupt).map(|i| i.resume(resume_data))
+            }),
+        }
+    }
+}
+
+/// Run execution to completion, answering host interrupts from `script` in order.
+///
+/// Instruction start interrupts are resumed without modification and are not part of the script.
+/// Panics with the expected and the actual interrupts if they diverge, or if execution completes
+/// before the script does.
+///
+/// ```
+/// use ethereum_types::{H256, U256};
+/// use evmodin::{
+///     continuation::{interrupt::*, resume_data::*},
+///     util::*,
+///     *,
+/// };
+///
+/// let code = AnalyzedCode::analyze(Bytecode::new().sload(0).ret_top().build());
+/// let output = run_with_script(
+///     code.execute_resumable(false, Message::builder().build(), Revision::Istanbul),
+///     vec![ScriptStep::answer::<GetStorageInterrupt>(StorageValue {
+///         value: H256::from_low_u64_be(42),
+///     })],
+/// );
+///
+/// assert_eq!(U256::from_big_endian(&output.output_data), 42.into());
+/// ```
+pub fn run_with_script(
+    start: ExecutionStartInterrupt,
+    script: impl IntoIterator<Item = ScriptStep>,
+) -> Output {
+    let script = script.into_iter().collect::<Vec<_>>();
+    let expected = script.iter().map(|step| step.expected).collect::<Vec<_>>();
+    let mut actual = Vec::new();
+
+    let mut steps = script.into_iter();
+    let mut interrupt = start.resume(());
+    loop {
+        interrupt = match interrupt {
+            InterruptVariant::InstructionStart(i) => i.resume(None),
+            InterruptVariant::Complete(res) => {
+                if actual.len() < expected.len() {
+                    diverged(&expected, &actual);
+                }
+                return complete_output(res);
+            }
+            other => {
+                actual.push(<&'static str>::from(&other));
+                match steps.next() {
+                    Some(step) if step.expected == actual[actual.len() - 1] => {
+                        (step.resume)(other).unwrap()
+                    }
+                    _ => diverged(&expected, &actual),
+                }
+            }
+        };
+    }
+}
+
+fn diverged(expected: &[&str], actual: &[&str]) -> ! {
+    panic!(
+        "interrupts diverged from the script\n  expected: {:?}\n    actual: {:?}",
+        expected, actual
+    )
+}
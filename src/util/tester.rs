@@ -13,6 +13,7 @@ fn exec(
     revision: Revision,
     message: Message,
     code: Vec<u8>,
+    config: Config,
     collect_traces: bool,
 ) -> Output {
     // Add EIP-2929 tweak.
@@ -23,9 +24,16 @@ fn exec(
     let code = AnalyzedCode::analyze(code);
 
     if collect_traces {
-        code.execute(host, &mut StdoutTracer::default(), None, message, revision)
+        code.execute_with_config(
+            host,
+            &mut StdoutTracer::default(),
+            None,
+            message,
+            revision,
+            config,
+        )
     } else {
-        code.execute(host, &mut NoopTracer, None, message, revision)
+        code.execute_with_config(host, &mut NoopTracer, None, message, revision, config)
     }
 }
 
@@ -52,9 +60,11 @@ pub struct EvmTester {
     revision: Revision,
     message: Message,
     code: Vec<u8>,
+    config: Config,
     gas_check: Option<GasCheck>,
     expected_status_codes: Option<Vec<StatusCode>>,
     expected_output_data: Option<Vec<u8>>,
+    expected_logs: Option<Vec<LogRecord>>,
     collect_traces: bool,
 }
 
@@ -85,9 +95,11 @@ impl EvmTester {
                 value: 0.into(),
             },
             code: Vec::new(),
+            config: Config::default(),
             gas_check: None,
             expected_status_codes: None,
             expected_output_data: None,
+            expected_logs: None,
             collect_traces: false,
         }
     }
@@ -110,6 +122,12 @@ impl EvmTester {
         self
     }
 
+    /// Set execution limits and modes.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Set message depth.
     pub fn depth(mut self, depth: u16) -> Self {
         self.message.depth = depth.into();
@@ -190,6 +208,12 @@ impl EvmTester {
         self
     }
 
+    /// Check emitted logs to be equal to provided ones.
+    pub fn logs(mut self, expected_logs: impl Into<Vec<LogRecord>>) -> Self {
+        self.expected_logs = Some(expected_logs.into());
+        self
+    }
+
     /// Inspect output with provided function.
     pub fn inspect_output(mut self, inspect_output_fn: impl Fn(&[u8]) + 'static) -> Self {
         self.inspect_output_fn = Arc::new(inspect_output_fn);
@@ -227,6 +251,7 @@ impl EvmTester {
             self.revision,
             self.message.clone(),
             self.code,
+            self.config,
             self.collect_traces,
         );
 
@@ -250,6 +275,10 @@ impl EvmTester {
             assert_eq!(&*output.output_data, expected_data);
         }
 
+        if let Some(expected_logs) = &self.expected_logs {
+            assert_eq!(&host.logs(), expected_logs);
+        }
+
         (self.inspect_output_fn)(&*output.output_data);
         (self.inspect_host_fn)(&host, &self.message);
         (self.inspect_fn)(&host, &self.message, &*output.output_data);
@@ -1,8 +1,15 @@
 use bytes::Bytes;
 use core::iter::repeat_with;
 use ethereum_types::{Address, H256, U256};
-use evmodin::{opcode::*, util::*, *};
+use evmodin::{
+    host::*,
+    opcode::*,
+    tracing::{NoopTracer, Tracer},
+    util::*,
+    *,
+};
 use hex_literal::hex;
+use std::{cell::RefCell, rc::Rc};
 
 #[test]
 fn delegatecall() {
@@ -58,6 +65,51 @@ fn delegatecall_static() {
         .check()
 }
 
+/// CALLCODE and DELEGATECALL both run the callee code in the caller's storage (the host does that based on the kind),
+/// but only CALLCODE starts a fresh message from the caller.
+#[test]
+fn callcode_and_delegatecall_context() {
+    let origin = Address::from_low_u64_be(0xaa);
+    let caller = Address::from_low_u64_be(0xbb);
+    let callee = Address::from_low_u64_be(0xcc);
+
+    let t = EvmTester::new()
+        .sender(origin)
+        .destination(caller)
+        .value(7)
+        .apply_host_fn(move |host, _| {
+            host.accounts.entry(caller).or_default().balance = 10.into();
+        })
+        .status(StatusCode::Success);
+
+    t.clone()
+        .code(Bytecode::new().append_bc(CallInstruction::callcode(0xcc).value(3).gas(1)))
+        .inspect_host(move |host, _| {
+            let r = host.recorded.lock();
+            assert_eq!(r.calls.len(), 1);
+            let msg = &r.calls[0];
+            assert_eq!(msg.kind, CallKind::CallCode);
+            assert_eq!(msg.destination, callee);
+            assert_eq!(msg.sender, caller);
+            assert_eq!(msg.value, 3.into());
+            assert_eq!(msg.gas, 1 + 2300);
+        })
+        .check();
+
+    t.code(Bytecode::new().append_bc(CallInstruction::delegatecall(0xcc).gas(1)))
+        .inspect_host(move |host, _| {
+            let r = host.recorded.lock();
+            assert_eq!(r.calls.len(), 1);
+            let msg = &r.calls[0];
+            assert_eq!(msg.kind, CallKind::DelegateCall);
+            assert_eq!(msg.destination, callee);
+            assert_eq!(msg.sender, origin);
+            assert_eq!(msg.value, 7.into());
+            assert_eq!(msg.gas, 1);
+        })
+        .check();
+}
+
 #[test]
 fn delegatecall_oog_depth_limit() {
     let t = EvmTester::new()
@@ -109,6 +161,60 @@ fn create() {
         .check()
 }
 
+#[test]
+fn create_empty_init_code() {
+    let created = Address::from_low_u64_be(0xce);
+
+    for op in [OpCode::CREATE, OpCode::CREATE2] {
+        // create(0, 0, 0) or create2(0, 0, 0, 0), then return the address and the return data size.
+        let code = if op == OpCode::CREATE {
+            Bytecode::new()
+        } else {
+            Bytecode::new().pushv(0)
+        }
+        .pushv(0)
+        .opcode(OpCode::DUP1)
+        .opcode(OpCode::DUP1)
+        .opcode(op)
+        .pushv(0)
+        .opcode(OpCode::MSTORE)
+        .opcode(OpCode::RETURNDATASIZE)
+        .pushv(0x20)
+        .opcode(OpCode::MSTORE)
+        .ret(0, 0x40);
+
+        EvmTester::new()
+            .revision(Revision::Constantinople)
+            .apply_host_fn(move |host, _| {
+                host.call_result.create_address = Some(created);
+            })
+            .code(code)
+            .status(StatusCode::Success)
+            .output_data(
+                [
+                    H256::from(created).to_fixed_bytes(),
+                    H256::zero().to_fixed_bytes(),
+                ]
+                .concat(),
+            )
+            .inspect_host(move |host, _| {
+                let r = host.recorded.lock();
+                assert_eq!(r.calls.len(), 1);
+                let msg = r.calls.last().unwrap();
+                assert_eq!(
+                    msg.kind,
+                    if op == OpCode::CREATE {
+                        CallKind::Create
+                    } else {
+                        CallKind::Create2 { salt: H256::zero() }
+                    }
+                );
+                assert!(msg.input_data.is_empty());
+            })
+            .check()
+    }
+}
+
 #[test]
 fn create_gas() {
     for rev in [Revision::Homestead, Revision::Tangerine] {
@@ -326,6 +432,161 @@ fn create_failure() {
     }
 }
 
+#[test]
+fn create_return_data() {
+    for op in [OpCode::CREATE, OpCode::CREATE2] {
+        let t = EvmTester::new()
+            .revision(Revision::Constantinople)
+            .apply_host_fn(|host, _| {
+                host.call_result.output_data = (&hex!("0a0b0c") as &[u8]).into();
+            })
+            .code(
+                Bytecode::new()
+                    .pushv(0)
+                    .opcode(OpCode::DUP1)
+                    .opcode(OpCode::DUP1)
+                    .opcode(OpCode::DUP1)
+                    .opcode(op)
+                    .opcode(OpCode::POP)
+                    .opcode(OpCode::RETURNDATASIZE)
+                    .pushv(0)
+                    .pushv(0)
+                    .opcode(OpCode::RETURNDATACOPY)
+                    .opcode(OpCode::RETURNDATASIZE)
+                    .pushv(0)
+                    .opcode(OpCode::RETURN),
+            );
+
+        t.clone()
+            .apply_host_fn(|host, _| {
+                host.call_result.status_code = StatusCode::Revert;
+            })
+            .status(StatusCode::Success)
+            .output_data(hex!("0a0b0c"))
+            .check();
+
+        t.apply_host_fn(|host, _| {
+            host.call_result.status_code = StatusCode::Success;
+        })
+        .status(StatusCode::Success)
+        .output_data(vec![])
+        .check();
+    }
+}
+
+#[test]
+fn create_revert_and_exceptional_halt() {
+    for op in [OpCode::CREATE, OpCode::CREATE2] {
+        let t = EvmTester::new()
+            .revision(Revision::Constantinople)
+            .gas(100_000)
+            .apply_host_fn(|host, _| {
+                host.call_result.gas_left = 50_000;
+                host.call_result.output_data = (&hex!("0a0b0c") as &[u8]).into();
+            })
+            .code(
+                Bytecode::new()
+                    .pushv(0)
+                    .opcode(OpCode::DUP1)
+                    .opcode(OpCode::DUP1)
+                    .opcode(OpCode::DUP1)
+                    .opcode(op)
+                    .opcode(OpCode::POP)
+                    .opcode(OpCode::RETURNDATASIZE)
+                    .ret_top(),
+            )
+            .status(StatusCode::Success);
+
+        // 32012 up to the create, which forwards 67988 - 67988 / 64 = 66926, and 19 after it.
+        t.clone()
+            .apply_host_fn(|host, _| {
+                host.call_result.status_code = StatusCode::Revert;
+            })
+            .gas_used(32012 + (66926 - 50_000) + 19)
+            .output_value(3)
+            .check();
+
+        // An exceptional halt returns no data, and the gas the host reports as left.
+        t.clone()
+            .apply_host_fn(|host, _| {
+                host.call_result.status_code = StatusCode::InvalidInstruction;
+            })
+            .gas_used(32012 + (66926 - 50_000) + 19)
+            .output_value(0)
+            .check();
+
+        // Init code halting in the interpreter leaves no gas.
+        t.apply_host_fn(|host, _| {
+            host.call_result.status_code = StatusCode::InvalidInstruction;
+            host.call_result.gas_left = 0;
+        })
+        .gas_used(32012 + 66926 + 19)
+        .output_value(0)
+        .check();
+    }
+}
+
+#[test]
+fn create_failure_gas_of_executed_init_code() {
+    // 32012 up to the create, which forwards 67988 - 67988 / 64 = 66926 and keeps 1062.
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .pushv(0)
+            .opcode(OpCode::DUP1)
+            .opcode(OpCode::DUP1)
+            .opcode(OpCode::DUP1)
+            .opcode(OpCode::CREATE)
+            .build(),
+    );
+
+    for (init_code, gas_left) in [
+        // Reverting init code returns what it did not use.
+        (asm("PUSH1 0 DUP1 REVERT"), 1062 + 66926 - 6),
+        (asm("INVALID"), 1062),
+    ] {
+        let mut host = DelegatingHost::new(mocked_host::MockedHost::default())
+            .callee(AnalyzedCode::analyze(init_code), Revision::Berlin);
+        let output = code.execute(
+            &mut host,
+            &mut NoopTracer,
+            None,
+            Message::builder().gas(100_000).build(),
+            Revision::Berlin,
+        );
+        assert_eq!(output.status_code, StatusCode::Success);
+        assert_eq!(output.gas_left, gas_left);
+    }
+}
+
+#[test]
+fn create_extcodehash_of_self() {
+    // Reverts with the code hash of the account being created, so the caller can return it.
+    let init_code = asm("ADDRESS EXTCODEHASH PUSH1 0 MSTORE PUSH1 32 PUSH1 0 REVERT");
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .pushv(0)
+            .opcode(OpCode::DUP1)
+            .opcode(OpCode::DUP1)
+            .opcode(OpCode::CREATE)
+            .append(asm(
+                "RETURNDATASIZE PUSH1 0 PUSH1 0 RETURNDATACOPY PUSH1 32 PUSH1 0 RETURN",
+            ))
+            .build(),
+    );
+
+    let mut host = DelegatingHost::new(mocked_host::MockedHost::default())
+        .callee(AnalyzedCode::analyze(init_code), Revision::Berlin);
+    let output = code.execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(100_000).build(),
+        Revision::Berlin,
+    );
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(&*output.output_data, EMPTY_CODE_HASH.as_bytes());
+}
+
 #[test]
 fn call_failing_with_value() {
     for op in [OpCode::CALL, OpCode::CALLCODE] {
@@ -381,6 +642,65 @@ fn call_failing_with_value() {
     }
 }
 
+#[test]
+fn call_self_with_full_balance() {
+    let contract = Address::from_low_u64_be(0xaa);
+
+    EvmTester::new()
+        .destination(contract)
+        .apply_host_fn(move |host, _| {
+            host.accounts.entry(contract).or_default().balance = 100.into();
+        })
+        .code(
+            Bytecode::new()
+                .append_bc(CallInstruction::call(0xaa).value(100))
+                .ret_top(),
+        )
+        .status(StatusCode::Success)
+        // No new account cost, the stipend is consumed by the mocked call.
+        .gas_used(7 * 3 + 700 + 9000 + 15)
+        .output_value(1)
+        .inspect_host(move |host, _| {
+            let r = host.recorded.lock();
+            assert_eq!(r.calls.len(), 1);
+            assert_eq!(r.calls[0].destination, contract);
+            assert_eq!(r.calls[0].sender, contract);
+            assert_eq!(r.calls[0].value, 100.into());
+        })
+        .check()
+}
+
+#[test]
+fn call_value_exceeding_balance() {
+    EvmTester::new()
+        .apply_host_fn(|host, msg| {
+            host.accounts.entry(msg.destination).or_default().balance = 1.into();
+            host.accounts
+                .entry(hex!("00000000000000000000000000000000000000aa").into())
+                .or_default();
+        })
+        .code(
+            Bytecode::new()
+                .pushv(0)
+                .pushv(0)
+                .pushv(0)
+                .pushv(0)
+                .pushv(2)
+                .pushv(0xaa)
+                .pushv(0x8000)
+                .opcode(OpCode::CALL)
+                .ret_top(),
+        )
+        .status(StatusCode::Success)
+        // The forwarded gas is not consumed, the stipend is returned to the caller.
+        .gas_used(7 * 3 + 700 + 9000 - 2300 + 15)
+        .output_value(0)
+        .inspect_host(|host, _| {
+            assert_eq!(host.recorded.lock().calls, []);
+        })
+        .check()
+}
+
 #[test]
 fn call_with_value() {
     let call_sender = hex!("5e4d00000000000000000000000000000000d4e5").into();
@@ -415,6 +735,55 @@ fn call_with_value() {
         .check()
 }
 
+#[test]
+fn call_with_value_stipend_after_gas_cap() {
+    // Cost is charged first, then 63/64 of the rest is available to the callee, and the stipend is
+    // added on top of that without being charged to the caller. This is the order of geth's
+    // `gasCall` (core/vm/gas_table.go), which charges 700 + 9000 (+ 25000 for a new account) and
+    // then the gas computed by `callGas` (core/vm/gas.go), and `opCall` (core/vm/instructions.go),
+    // which adds `params.CallStipend` only to the gas handed to the callee.
+    for (gas, new_account, forwarded, gas_used) in [
+        // Requested gas below the cap.
+        (
+            0xffff_u64,
+            false,
+            0xffff + 2300,
+            7 * 3 + 700 + 9000 + 0xffff,
+        ),
+        // Capped to 63/64 of 100000 - 21 - 700 - 9000 = 90279.
+        (0xffffff, false, 88869 + 2300, 7 * 3 + 700 + 9000 + 88869),
+        // Capped to 63/64 of 100000 - 21 - 700 - 9000 - 25000 = 65279.
+        (
+            0xffffff,
+            true,
+            64260 + 2300,
+            7 * 3 + 700 + 9000 + 25000 + 64260,
+        ),
+    ] {
+        let call_dst = Address::from_low_u64_be(0xaa);
+
+        EvmTester::new()
+            .revision(Revision::Istanbul)
+            .code(CallInstruction::call(0xaa).gas(gas).value(1))
+            .apply_host_fn(move |host, msg| {
+                host.accounts.entry(msg.destination).or_default().balance = 1.into();
+                if !new_account {
+                    host.accounts.entry(call_dst).or_default();
+                }
+            })
+            .gas(100000)
+            .status(StatusCode::Success)
+            // The callee uses up all gas, including the stipend.
+            .gas_used(gas_used)
+            .inspect_host(move |host, _| {
+                let r = host.recorded.lock();
+                assert_eq!(r.calls.len(), 1);
+                assert_eq!(r.calls[0].gas, forwarded);
+            })
+            .check()
+    }
+}
+
 #[test]
 fn call_with_value_depth_limit() {
     let mut call_dst = Address::zero();
@@ -539,6 +908,31 @@ fn call_output() {
     }
 }
 
+#[test]
+fn call_output_partial_write() {
+    EvmTester::new()
+        .apply_host_fn(|host, _| {
+            host.call_result.output_data = Bytes::from_static(&hex!("0a0b0c0d"));
+        })
+        .code(
+            Bytecode::new()
+                .mstore_value(0, U256::max_value())
+                .pushv(8)
+                .pushv(0)
+                .pushv(0)
+                .pushv(0)
+                .pushv(0)
+                .pushv(0xaa)
+                .pushv(0xffff)
+                .opcode(OpCode::CALL)
+                .opcode(OpCode::POP)
+                .ret(0, 8),
+        )
+        .status(StatusCode::Success)
+        .output_data(hex!("0a0b0c0dffffffff"))
+        .check()
+}
+
 #[test]
 fn call_high_gas() {
     for call_opcode in [OpCode::CALL, OpCode::CALLCODE, OpCode::DELEGATECALL] {
@@ -1188,3 +1582,566 @@ fn returndatacopy_outofrange() {
             .check()
     }
 }
+
+#[derive(Debug, PartialEq)]
+enum FrameEvent {
+    Enter {
+        depth: i32,
+        gas: i64,
+    },
+    Exit {
+        depth: i32,
+        gas_left: i64,
+        status: StatusCode,
+    },
+}
+
+/// Tracer recording sub-frame entries and exits. Clones share the records.
+#[derive(Clone, Default)]
+struct FrameRecorder(Rc<RefCell<Vec<FrameEvent>>>);
+
+impl Tracer for FrameRecorder {
+    fn notify_execution_start(&mut self, _: Revision, _: Message, _: Bytes) {}
+
+    fn notify_instruction_start(&mut self, _: usize, _: OpCode, _: &ExecutionState) {}
+
+    fn notify_execution_end(&mut self, _: &Output) {}
+
+    fn notify_call_enter(&mut self, depth: i32, gas: i64) {
+        self.0.borrow_mut().push(FrameEvent::Enter { depth, gas });
+    }
+
+    fn notify_call_exit(&mut self, depth: i32, gas_left: i64, status: &StatusCode) {
+        self.0.borrow_mut().push(FrameEvent::Exit {
+            depth,
+            gas_left,
+            status: status.clone(),
+        });
+    }
+}
+
+#[test]
+fn static_mode_propagates_to_nested_calls() {
+    let call = |op: OpCode, dst: u64| {
+        let mut code = Bytecode::new().pushv(0).pushv(0).pushv(0).pushv(0);
+        if matches!(op, OpCode::CALL | OpCode::CALLCODE) {
+            code = code.pushv(0);
+        }
+        code.pushv(dst).pushv(0xffff).opcode(op).ret_top()
+    };
+
+    for op in [
+        OpCode::CALL,
+        OpCode::CALLCODE,
+        OpCode::DELEGATECALL,
+        OpCode::STATICCALL,
+    ] {
+        let mut host = DelegatingHost::new(mocked_host::MockedHost::default())
+            .execute_calls(Revision::Byzantium);
+        host.inner_mut()
+            .accounts
+            .entry(Address::from_low_u64_be(0xaa))
+            .or_default()
+            .code = call(op, 0xbb).build().into();
+        host.inner_mut()
+            .accounts
+            .entry(Address::from_low_u64_be(0xbb))
+            .or_default()
+            .code = Bytecode::new().sstore(1, 1).build().into();
+
+        let output = AnalyzedCode::analyze(call(OpCode::STATICCALL, 0xaa).build()).execute(
+            &mut host,
+            &mut NoopTracer,
+            None,
+            Message::builder().gas(1_000_000).build(),
+            Revision::Byzantium,
+        );
+
+        assert_eq!(output.status_code, StatusCode::Success);
+        assert_eq!(
+            host.results(),
+            [
+                (2, StatusCode::StaticModeViolation),
+                (1, StatusCode::Success)
+            ]
+        );
+        assert!(host.inner().accounts[&Address::from_low_u64_be(0xbb)]
+            .storage
+            .is_empty());
+    }
+}
+
+#[test]
+fn call_custom_precompile() {
+    fn is_precompile(address: Address, revision: Revision) -> bool {
+        address == Address::from_low_u64_be(0x0100) || is_mainnet_precompile(address, revision)
+    }
+
+    let t = EvmTester::new()
+        .revision(Revision::Berlin)
+        .code(hex!("6000808080806101006000f150"))
+        .status(StatusCode::Success)
+        .inspect_host(|host, _| {
+            assert_eq!(
+                host.recorded.lock().calls[0].destination,
+                Address::from_low_u64_be(0x0100)
+            );
+        });
+
+    t.clone().gas_used(123 + 2500).check();
+    t.config(Config {
+        is_precompile,
+        ..Default::default()
+    })
+    .gas_used(123)
+    .check();
+}
+
+#[test]
+fn call_self_reentrancy() {
+    // Contract taking level n from the input:
+    // storage[n] = n + 1;
+    // if n != 0 {
+    //     mstore(0, n - 1);
+    //     call(gas(), address(), 0, 0, 32, 0, 0);
+    //     storage[0x100 + n] = mload(0) + 1;
+    // }
+    let code = hex!(
+        "6000356001810181558015602f576001810360005260006000602060006000305af150600160005101816101000155 5b00"
+    );
+    let contract = Address::from_low_u64_be(0xaa);
+
+    let mut host =
+        DelegatingHost::new(mocked_host::MockedHost::default()).execute_calls(Revision::Byzantium);
+    host.inner_mut().accounts.entry(contract).or_default().code = code[..].to_vec().into();
+
+    let output = AnalyzedCode::analyze(code).execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder()
+            .destination(contract)
+            .input_data(H256::from_low_u64_be(3).0.to_vec())
+            .gas(1_000_000)
+            .build(),
+        Revision::Byzantium,
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(
+        host.results(),
+        [
+            (3, StatusCode::Success),
+            (2, StatusCode::Success),
+            (1, StatusCode::Success)
+        ]
+    );
+
+    // Every level has written its own slot, and the memory of each frame survived the nested call.
+    let storage = &host.inner().accounts[&contract].storage;
+    let mut written = storage
+        .iter()
+        .map(|(key, v)| (key.to_low_u64_be(), v.value.to_low_u64_be()))
+        .collect::<Vec<_>>();
+    written.sort_unstable();
+    assert_eq!(
+        written,
+        [
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 4),
+            (0x101, 1),
+            (0x102, 2),
+            (0x103, 3)
+        ]
+    );
+}
+
+#[test]
+fn delegatecall_inherits_caller_and_value() {
+    let origin = Address::from_low_u64_be(0x01);
+    let a = Address::from_low_u64_be(0xaa);
+    let b = Address::from_low_u64_be(0xbb);
+
+    let mut host =
+        DelegatingHost::new(mocked_host::MockedHost::default()).execute_calls(Revision::Byzantium);
+    host.inner_mut().accounts.entry(b).or_default().code = Bytecode::new()
+        .opcode(OpCode::CALLER)
+        .mstore(0)
+        .opcode(OpCode::CALLVALUE)
+        .mstore(32)
+        .ret(0, 64)
+        .build()
+        .into();
+
+    let output = AnalyzedCode::analyze(
+        Bytecode::new()
+            .append_bc(
+                CallInstruction::delegatecall(0xbb)
+                    .gas(0xffff)
+                    .output(0, 64),
+            )
+            .ret(0, 64)
+            .build(),
+    )
+    .execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder()
+            .sender(origin)
+            .destination(a)
+            .value(5)
+            .gas(1_000_000)
+            .build(),
+        Revision::Byzantium,
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(host.results(), [(1, StatusCode::Success)]);
+    // B sees A's caller and value, not A itself.
+    assert_eq!(&output.output_data[12..32], origin.as_bytes());
+    assert_eq!(U256::from_big_endian(&output.output_data[32..]), 5.into());
+}
+
+#[test]
+fn call_nonexistent_account_without_value() {
+    let dst = Address::from_low_u64_be(0xdead);
+
+    let mut host =
+        DelegatingHost::new(mocked_host::MockedHost::default()).execute_calls(Revision::Berlin);
+
+    let output = AnalyzedCode::analyze(
+        Bytecode::new()
+            .append_bc(CallInstruction::call(0xdead).gas(0xffff))
+            .opcode(OpCode::RETURNDATASIZE)
+            .mstore(0)
+            .mstore(32)
+            .ret(0, 64)
+            .build(),
+    )
+    .execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(1_000_000).build(),
+        Revision::Berlin,
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    // The empty callee stops right away, returning all of its gas.
+    assert_eq!(host.results(), [(1, StatusCode::Success)]);
+    // 7 pushes, cold CALL, RETURNDATASIZE, two MSTOREs and RETURN.
+    assert_eq!(1_000_000 - output.gas_left, 21 + 2600 + 2 + 9 + 9 + 6);
+    assert_eq!(U256::from_big_endian(&output.output_data[..32]), 0.into());
+    assert_eq!(U256::from_big_endian(&output.output_data[32..]), 1.into());
+    assert!(!host.inner().accounts.contains_key(&dst));
+}
+
+#[test]
+fn call_denied_by_host() {
+    let call = |dst: u64| Bytecode::new().append_bc(CallInstruction::call(dst).gas(1000));
+
+    // storage[0] = call(0xbad); storage[1] = call(0xaa);
+    let code = call(0xbad)
+        .pushv(0)
+        .opcode(OpCode::SSTORE)
+        .append_bc(call(0xaa))
+        .pushv(1)
+        .opcode(OpCode::SSTORE);
+
+    EvmTester::new()
+        .code(code)
+        .apply_host_fn(|host, _| {
+            host.denied_calls.insert(Address::from_low_u64_be(0xbad));
+        })
+        .status(StatusCode::Success)
+        .inspect_host(|host, msg| {
+            let storage = &host.accounts[&msg.destination].storage;
+            let get = |key| {
+                storage
+                    .get(&H256::from_low_u64_be(key))
+                    .map(|v| v.value)
+                    .unwrap_or_default()
+            };
+            assert_eq!(get(0), H256::zero());
+            assert_eq!(get(1), H256::from_low_u64_be(1));
+
+            let calls = &host.recorded.lock().calls;
+            assert_eq!(calls.len(), 1);
+            assert_eq!(calls[0].destination, Address::from_low_u64_be(0xaa));
+        })
+        .check()
+}
+
+#[test]
+fn create_denied_by_host() {
+    EvmTester::new()
+        .revision(Revision::Constantinople)
+        .gas(100_000)
+        .apply_host_fn(|host, _| {
+            // Creations are sent to the zero address.
+            host.denied_calls.insert(Address::zero());
+            host.call_result.gas_left = 50_000;
+        })
+        .code(
+            Bytecode::new()
+                .pushv(0)
+                .opcode(OpCode::DUP1)
+                .opcode(OpCode::DUP1)
+                .opcode(OpCode::DUP1)
+                .opcode(OpCode::CREATE)
+                .ret_top(),
+        )
+        .status(StatusCode::Success)
+        // 32012 up to the create, which forwards 67988 - 67988 / 64 = 66926, all consumed, and 15 after it.
+        .gas_used(32012 + 66926 + 15)
+        .output_value(0)
+        .inspect_host(|host, _| {
+            assert!(host.recorded.lock().calls.is_empty());
+        })
+        .check()
+}
+
+#[test]
+fn call_frame_hooks() {
+    // Each contract calls the next one with at most 0xffff gas.
+    let call = |dst: u64| {
+        Bytecode::new()
+            .pushv(0)
+            .pushv(0)
+            .pushv(0)
+            .pushv(0)
+            .pushv(0)
+            .pushv(dst)
+            .pushv(0xffff)
+            .opcode(OpCode::CALL)
+            .opcode(OpCode::POP)
+    };
+
+    let mut frames = FrameRecorder::default();
+    let mut host = DelegatingHost::new(mocked_host::MockedHost::default())
+        .execute_calls(Revision::Byzantium)
+        .tracer(frames.clone());
+    host.inner_mut()
+        .accounts
+        .entry(Address::from_low_u64_be(0xaa))
+        .or_default()
+        .code = call(0xbb).build().into();
+
+    let output = AnalyzedCode::analyze(call(0xaa).build()).execute(
+        &mut host,
+        &mut frames,
+        None,
+        Message::builder().gas(1_000_000).build(),
+        Revision::Byzantium,
+    );
+    assert_eq!(output.status_code, StatusCode::Success);
+
+    // 0xaa pays for the pushes and CALL, then passes all but 1/64 of its gas on.
+    let gas_before_call = 0xffff - (7 * 3 + 700);
+    let inner_gas = gas_before_call - gas_before_call / 64;
+    assert_eq!(
+        *frames.0.borrow(),
+        [
+            FrameEvent::Enter {
+                depth: 1,
+                gas: 0xffff
+            },
+            FrameEvent::Enter {
+                depth: 2,
+                gas: inner_gas
+            },
+            FrameEvent::Exit {
+                depth: 2,
+                gas_left: inner_gas,
+                status: StatusCode::Success
+            },
+            FrameEvent::Exit {
+                depth: 1,
+                // 0xbb has no code, so only POP is charged after the call.
+                gas_left: gas_before_call - 2,
+                status: StatusCode::Success
+            },
+        ]
+    );
+}
+
+#[test]
+fn call_stateful_precompile() {
+    fn is_precompile(address: Address, revision: Revision) -> bool {
+        address == Address::from_low_u64_be(0x0100) || is_mainnet_precompile(address, revision)
+    }
+
+    let oracle = Address::from_low_u64_be(0x0100);
+    let key = H256::from_low_u64_be(0x0f);
+    let value = H256::from_low_u64_be(0xdeadbeef);
+
+    let mut inner = mocked_host::MockedHost::default();
+    inner.accounts.entry(oracle).or_default().storage.insert(
+        key,
+        mocked_host::StorageValue {
+            value,
+            ..Default::default()
+        },
+    );
+
+    // Returns the storage value of its own account under the key in the input.
+    let mut host = PrecompileHost::new(inner);
+    host.register(oracle, |host, msg| {
+        let value = host.get_storage(msg.destination, H256::from_slice(&msg.input_data));
+        Output {
+            status_code: StatusCode::Success,
+            gas_left: msg.gas - 100,
+            gas_refund: 0,
+            output_data: value.0.to_vec().into(),
+            create_address: None,
+            failure_location: None,
+            gas_breakdown: None,
+            opcode_trace: None,
+            access_list: None,
+            peak_memory: 0,
+        }
+    });
+
+    // m[0] = key; call(gas(), 0x0100, 0, 0, 32, 0, 32); return(0, 32)
+    let code = Bytecode::new()
+        .mstore_value(0, 0x0f)
+        .pushv(0x20)
+        .pushv(0)
+        .pushv(0x20)
+        .pushv(0)
+        .pushv(0)
+        .pushv(0x0100)
+        .opcode(OpCode::GAS)
+        .opcode(OpCode::CALL)
+        .opcode(OpCode::POP)
+        .ret(0, 0x20);
+
+    let output = AnalyzedCode::analyze(code.build()).execute_with_config(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(1_000_000).build(),
+        Revision::Berlin,
+        Config {
+            is_precompile,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(&*output.output_data, value.as_bytes());
+    assert!(host.inner().recorded.lock().calls.is_empty());
+}
+
+#[test]
+fn call_precompile_output_sizes() {
+    // ecrecover, sha256, ripemd160, identity of 5 bytes, ecadd, ecmul and ecpairing.
+    for (precompile, output_size) in [
+        (0x01, 32),
+        (0x02, 32),
+        (0x03, 32),
+        (0x04, 5),
+        (0x06, 64),
+        (0x07, 64),
+        (0x08, 32),
+    ] {
+        let mut host = PrecompileHost::new(mocked_host::MockedHost::default());
+        host.register(Address::from_low_u64_be(precompile), move |_, msg| {
+            let output_size = if precompile == 0x04 {
+                msg.input_data.len()
+            } else {
+                output_size
+            };
+            Output {
+                status_code: StatusCode::Success,
+                gas_left: msg.gas - 100,
+                gas_refund: 0,
+                output_data: vec![0xab; output_size].into(),
+                create_address: None,
+                failure_location: None,
+                gas_breakdown: None,
+                opcode_trace: None,
+                access_list: None,
+                peak_memory: 0,
+            }
+        });
+
+        // Output area m[0..0x60] filled with 0xff; call(gas(), precompile, 0, 0, 5, 0, 0x60);
+        // m[0x60] = returndatasize(); return(0, 0x80)
+        let code = Bytecode::new()
+            .mstore_value(0, U256::max_value())
+            .mstore_value(0x20, U256::max_value())
+            .mstore_value(0x40, U256::max_value())
+            .pushv(0x60)
+            .pushv(0)
+            .pushv(5)
+            .pushv(0)
+            .pushv(0)
+            .pushv(precompile)
+            .opcode(OpCode::GAS)
+            .opcode(OpCode::CALL)
+            .opcode(OpCode::POP)
+            .opcode(OpCode::RETURNDATASIZE)
+            .pushv(0x60)
+            .opcode(OpCode::MSTORE)
+            .ret(0, 0x80);
+
+        let output = AnalyzedCode::analyze(code.build()).execute(
+            &mut host,
+            &mut NoopTracer,
+            None,
+            Message::builder().gas(1_000_000).build(),
+            Revision::Istanbul,
+        );
+
+        assert_eq!(output.status_code, StatusCode::Success);
+        let (copied, rest) = output.output_data.split_at(0x60);
+        assert!(
+            copied[..output_size].iter().all(|&b| b == 0xab),
+            "{}",
+            precompile
+        );
+        assert!(
+            copied[output_size..].iter().all(|&b| b == 0xff),
+            "{}",
+            precompile
+        );
+        assert_eq!(
+            U256::from_big_endian(rest),
+            output_size.into(),
+            "{}",
+            precompile
+        );
+    }
+}
+
+#[cfg(feature = "catch-panic")]
+#[test]
+fn call_panicking_precompile() {
+    let mut host = PrecompileHost::new(mocked_host::MockedHost::default());
+    host.register(Address::from_low_u64_be(0x0100), |_, _| {
+        panic!("precompile bug")
+    });
+
+    let output = AnalyzedCode::analyze(
+        Bytecode::new()
+            .append_bc(CallInstruction::call(0x0100).gas(0xffff))
+            .build(),
+    )
+    .execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(1_000_000).build(),
+        Revision::latest(),
+    );
+
+    assert_eq!(
+        output.status_code,
+        StatusCode::InternalError("panic: precompile bug".to_string())
+    );
+    assert_eq!(output.gas_left, 0);
+}
@@ -1,5 +1,5 @@
-use ethereum_types::{H256, U256};
-use evmodin::{host::*, opcode::*, util::*, *};
+use ethereum_types::{Address, H256, U256};
+use evmodin::{host::*, opcode::*, tracing::NoopTracer, util::*, *};
 use hex_literal::hex;
 
 #[test]
@@ -21,11 +21,8 @@ fn eip2929_case1() {
                     msg.sender,
                     msg.destination,
                     hex!("0000000000000000000000000000000000000001").into(),
-                    hex!("0000000000000000000000000000000000000001").into(),
-                    hex!("0000000000000000000000000000000000000002").into(),
                     hex!("0000000000000000000000000000000000000002").into(),
                     hex!("0000000000000000000000000000000000000003").into(),
-                    hex!("0000000000000000000000000000000000000003").into(),
                     hex!("00000000000000000000000000000000000000f1").into(),
                     hex!("00000000000000000000000000000000000000f1").into(),
                     hex!("00000000000000000000000000000000000000f2").into(),
@@ -154,6 +151,41 @@ fn eip2929_extcodecopy_oog() {
         .check();
 }
 
+#[test]
+fn eip2929_extcodecopy_cold_and_warm() {
+    let copy = Bytecode::new()
+        .pushv(33)
+        .pushv(0)
+        .pushv(0)
+        .pushv(0xaa)
+        .opcode(OpCode::EXTCODECOPY);
+
+    let mut output = vec![0; 33];
+    output[..3].copy_from_slice(&hex!("aabbcc"));
+
+    let t = EvmTester::new()
+        .revision(Revision::Berlin)
+        .apply_host_fn(|host, _| {
+            host.accounts
+                .entry(Address::from_low_u64_be(0xaa))
+                .or_default()
+                .code = hex!("aabbcc").to_vec().into();
+        })
+        .status(StatusCode::Success)
+        .output_data(output);
+
+    // Pushes, warm cost, cold surcharge, copying and expanding 2 words, then the return.
+    t.clone()
+        .code(copy.clone().ret(0, 33))
+        .gas_used(12 + 100 + 2500 + 6 + 6 + 6)
+        .check();
+
+    // Second copy is warm and memory is already expanded.
+    t.code(copy.clone().append_bc(copy).ret(0, 33))
+        .gas_used((12 + 100 + 2500 + 6 + 6) + (12 + 100 + 6) + 6)
+        .check();
+}
+
 #[test]
 fn eip2929_sload_cold() {
     let key = H256(U256::one().into());
@@ -385,3 +417,415 @@ fn eip2929_delegatecall_cold() {
         })
         .check();
 }
+
+#[test]
+fn eip2929_call_precompile_warm() {
+    // Precompiles are warm from the start, so the host is not even asked about them.
+    for (revision, precompile) in [(Revision::Berlin, 0x01), (Revision::Cancun, 0x0a)] {
+        EvmTester::new()
+            .revision(revision)
+            .code(CallInstruction::call(precompile))
+            .status(StatusCode::Success)
+            .gas_used(7 * 3 + 100)
+            .inspect_host(move |host, msg| {
+                assert_eq!(
+                    host.recorded.lock().account_accesses,
+                    [
+                        msg.sender,
+                        msg.destination,
+                        Address::from_low_u64_be(precompile)
+                    ]
+                );
+            })
+            .check();
+    }
+
+    // Not a precompile before Cancun.
+    EvmTester::new()
+        .revision(Revision::Shanghai)
+        .code(CallInstruction::call(0x0a))
+        .status(StatusCode::Success)
+        .gas_used(7 * 3 + 2600)
+        .check();
+}
+
+#[test]
+fn eip2929_account_queries_precompile_warm() {
+    fn is_precompile(address: Address, revision: Revision) -> bool {
+        address == Address::from_low_u64_be(0x0100) || is_mainnet_precompile(address, revision)
+    }
+
+    // BALANCE, EXTCODESIZE, EXTCODEHASH and EXTCODECOPY of an empty range, all on a custom precompile.
+    EvmTester::new()
+        .revision(Revision::Berlin)
+        .config(Config {
+            is_precompile,
+            ..Default::default()
+        })
+        .code(
+            Bytecode::new()
+                .pushv(0x0100)
+                .opcode(OpCode::BALANCE)
+                .opcode(OpCode::POP)
+                .pushv(0x0100)
+                .opcode(OpCode::EXTCODESIZE)
+                .opcode(OpCode::POP)
+                .pushv(0x0100)
+                .opcode(OpCode::EXTCODEHASH)
+                .opcode(OpCode::POP)
+                .pushv(0)
+                .pushv(0)
+                .pushv(0)
+                .pushv(0x0100)
+                .opcode(OpCode::EXTCODECOPY),
+        )
+        .status(StatusCode::Success)
+        .gas_used(3 * (3 + 100 + 2) + 4 * 3 + 100)
+        .check();
+}
+
+#[test]
+fn eip2929_batched_access_and_get() {
+    let account = Address::from_low_u64_be(0xaa);
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .sload(1)
+            .pushv(0xaa)
+            .opcode(OpCode::BALANCE)
+            .opcode(OpCode::ADD)
+            .sload(1)
+            .opcode(OpCode::ADD)
+            .ret_top()
+            .build(),
+    );
+    let message = Message::builder().gas(10000).build();
+
+    let new_host = || {
+        let mut host = mocked_host::MockedHost::default();
+        host.accounts
+            .entry(Address::zero())
+            .or_default()
+            .storage
+            .entry(H256::from_low_u64_be(1))
+            .or_default()
+            .value = H256::from_low_u64_be(2);
+        host.accounts.entry(account).or_default().balance = 3.into();
+        host
+    };
+
+    let mut host = new_host();
+    let mut batching_host = DelegatingHost::new(new_host()).batch_accesses();
+
+    let split = code.execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        message.clone(),
+        Revision::Berlin,
+    );
+    let batched = code.execute(
+        &mut batching_host,
+        &mut NoopTracer,
+        None,
+        message.clone(),
+        Revision::Berlin,
+    );
+
+    assert_eq!(split.status_code, StatusCode::Success);
+    assert_eq!(split, batched);
+    assert_eq!(U256::from_big_endian(&batched.output_data), 7.into());
+    assert_eq!(batching_host.batched(), 3);
+
+    // Wrappers pass the combined queries on.
+    let mut wrapped_host = PrecompileHost::new(DelegatingHost::new(new_host()).batch_accesses());
+    let wrapped = code.execute(
+        &mut wrapped_host,
+        &mut NoopTracer,
+        None,
+        message,
+        Revision::Berlin,
+    );
+    assert_eq!(split, wrapped);
+    assert_eq!(wrapped_host.inner().batched(), 3);
+}
+
+#[test]
+fn eip2929_access_tracking_host() {
+    let sender = Address::zero();
+    let destination = Address::from(hex!("000000000000000000000000636F6E7472616374"));
+
+    for (code, gas_used) in [
+        (
+            &hex!("60013f5060023b506003315060f13f5060f23b5060f3315060f23f5060f33b5060f1315032315030315000")[..],
+            8653,
+        ),
+        (&hex!("60006000600060ff3c60006000600060ff3c600060006000303c00")[..], 2835),
+        (&hex!("60015450601160015560116002556011600255600254600154")[..], 44529),
+        (
+            &hex!("60008080808060046000f15060008080808060ff6000f15060008080808060ff6000fa50")[..],
+            2869,
+        ),
+    ] {
+        let message = Message::builder()
+            .sender(sender)
+            .destination(destination)
+            .gas(100_000)
+            .build();
+
+        let mut host = AccessTrackingHost::new(mocked_host::MockedHost::default());
+        host.prewarm_transaction(Revision::Berlin, sender, destination);
+
+        let output = AnalyzedCode::analyze(code).execute(
+            &mut host,
+            &mut NoopTracer,
+            None,
+            message,
+            Revision::Berlin,
+        );
+
+        assert_eq!(output.status_code, StatusCode::Success);
+        assert_eq!(100_000 - output.gas_left, gas_used);
+    }
+}
+
+#[test]
+fn eip2929_access_tracking_host_nested_frames() {
+    let succeeds = Address::from_low_u64_be(0xaa);
+    let reverts = Address::from_low_u64_be(0xbb);
+    let warm = Address::from_low_u64_be(0xdd);
+    let cold = Address::from_low_u64_be(0xee);
+
+    let mut inner = mocked_host::MockedHost::default();
+    inner.accounts.entry(succeeds).or_default().code = Bytecode::new()
+        .pushv(0xdd)
+        .opcode(OpCode::BALANCE)
+        .build()
+        .into();
+    inner.accounts.entry(reverts).or_default().code = Bytecode::new()
+        .pushv(0xee)
+        .opcode(OpCode::BALANCE)
+        .pushv(0)
+        .opcode(OpCode::DUP1)
+        .opcode(OpCode::REVERT)
+        .build()
+        .into();
+
+    let mut host = AccessTrackingHost::new(inner);
+    host.execute_frames_with(|host, msg| {
+        let code = host.inner().accounts[&msg.destination].code.to_vec();
+        AnalyzedCode::analyze(code).execute(
+            host,
+            &mut NoopTracer,
+            None,
+            msg.clone(),
+            Revision::Berlin,
+        )
+    });
+
+    let output = AnalyzedCode::analyze(
+        Bytecode::new()
+            .append_bc(CallInstruction::call(0xaa).gas(0xffff))
+            .append_bc(CallInstruction::call(0xbb).gas(0xffff))
+            .opcode(OpCode::ADD)
+            .ret_top()
+            .build(),
+    )
+    .execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(100_000).build(),
+        Revision::Berlin,
+    );
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), 1.into());
+
+    // Accesses of the successful frame are kept for the transaction, those of the reverted one are forgotten.
+    assert_eq!(host.access_account(warm), AccessStatus::Warm);
+    assert_eq!(host.access_account(cold), AccessStatus::Cold);
+    assert_eq!(host.access_account(reverts), AccessStatus::Warm);
+}
+
+#[test]
+fn eip2929_disabled_by_spec() {
+    fn spec(revision: Revision) -> Spec {
+        Spec {
+            eip2929: false,
+            ..Spec::mainnet(revision)
+        }
+    }
+
+    let key = H256(U256::one().into());
+
+    // London without EIP-2929 falls back to the flat Istanbul SLOAD cost, while BASEFEE stays available.
+    EvmTester::new()
+        .revision(Revision::London)
+        .config(Config {
+            spec,
+            ..Default::default()
+        })
+        .code(
+            Bytecode::new()
+                .sload(1)
+                .sload(1)
+                .opcode(OpCode::BASEFEE)
+                .opcode(OpCode::ADD)
+                .opcode(OpCode::ADD)
+                .opcode(OpCode::POP),
+        )
+        .status(StatusCode::Success)
+        .gas_used(2 * (3 + 800) + 2 + 2 * 3 + 2)
+        .inspect_host(move |host, msg| {
+            // Never asked to warm up the slot.
+            assert!(!host
+                .accounts
+                .get(&msg.destination)
+                .map_or(false, |account| account.storage.contains_key(&key)));
+        })
+        .check();
+}
+
+#[test]
+fn eip2929_custom_access_costs() {
+    fn cheap(revision: Revision) -> Spec {
+        Spec {
+            access_costs: AccessCosts {
+                cold_sload: 200,
+                warm_storage_read: 50,
+                cold_account_access: 1000,
+            },
+            ..Spec::mainnet(revision)
+        }
+    }
+
+    fn always_warm(revision: Revision) -> Spec {
+        Spec {
+            access_costs: AccessCosts {
+                cold_sload: 100,
+                warm_storage_read: 100,
+                cold_account_access: 100,
+            },
+            ..Spec::mainnet(revision)
+        }
+    }
+
+    let code = Bytecode::new()
+        .sload(1)
+        .sload(1)
+        .pushv(0xaa)
+        .opcode(OpCode::BALANCE)
+        .pushv(0xaa)
+        .opcode(OpCode::BALANCE);
+
+    let cases: [(fn(Revision) -> Spec, i64); 3] = [
+        (Spec::mainnet, 4 * 3 + 2100 + 100 + 2600 + 100),
+        (cheap, 4 * 3 + 200 + 50 + 1000 + 50),
+        (always_warm, 4 * 3 + 4 * 100),
+    ];
+    for (spec, gas_used) in cases {
+        EvmTester::new()
+            .revision(Revision::Berlin)
+            .config(Config {
+                spec,
+                ..Default::default()
+            })
+            .code(code.clone())
+            .status(StatusCode::Success)
+            .gas_used(gas_used)
+            .check();
+    }
+}
+
+#[test]
+fn access_list_collection() {
+    let contract = Address::from_low_u64_be(0xaa);
+    let code = Bytecode::new()
+        .sload(1)
+        .sload(2)
+        .sload(1)
+        .append_bc(CallInstruction::call(0xbb).gas(1000))
+        .build();
+    let t = EvmTester::new()
+        .revision(Revision::Berlin)
+        .destination(contract)
+        .code(code)
+        .status(StatusCode::Success);
+
+    assert_eq!(t.clone().check_and_get_result().access_list, None);
+
+    let access_list = t
+        .config(Config {
+            access_list: true,
+            ..Default::default()
+        })
+        .check_and_get_result()
+        .access_list
+        .unwrap();
+
+    let mut expected = AccessList::new();
+    expected
+        .entry(contract)
+        .or_default()
+        .extend([H256::from_low_u64_be(1), H256::from_low_u64_be(2)].iter());
+    expected.insert(Address::from_low_u64_be(0xbb), Default::default());
+    assert_eq!(access_list, expected);
+}
+
+#[test]
+fn access_list_of_nested_and_failed_frames() {
+    let contract = Address::from_low_u64_be(0xaa);
+    let callee = Address::from_low_u64_be(0xbb);
+    let config = Config {
+        access_list: true,
+        ..Default::default()
+    };
+
+    let mut nested = AccessList::new();
+    nested
+        .entry(callee)
+        .or_default()
+        .insert(H256::from_low_u64_be(3));
+    nested.insert(Address::from_low_u64_be(0xcc), Default::default());
+
+    // The accesses of a reverted callee are merged in.
+    let mut expected = nested.clone();
+    expected
+        .entry(contract)
+        .or_default()
+        .insert(H256::from_low_u64_be(1));
+    let access_list = EvmTester::new()
+        .revision(Revision::Berlin)
+        .config(config.clone())
+        .destination(contract)
+        .code(
+            Bytecode::new()
+                .sload(1)
+                .append_bc(CallInstruction::call(0xbb).gas(1000)),
+        )
+        .apply_host_fn(move |host, _| {
+            host.call_result.status_code = StatusCode::Revert;
+            host.call_result.access_list = Some(nested.clone());
+        })
+        .status(StatusCode::Success)
+        .check_and_get_result()
+        .access_list
+        .unwrap();
+    assert_eq!(access_list, expected);
+
+    // The list is kept if the frame fails.
+    let mut expected = AccessList::new();
+    expected
+        .entry(contract)
+        .or_default()
+        .insert(H256::from_low_u64_be(1));
+    let access_list = EvmTester::new()
+        .revision(Revision::Berlin)
+        .config(config)
+        .destination(contract)
+        .code(Bytecode::new().sload(1).opcode(OpCode::INVALID))
+        .status(StatusCode::InvalidInstruction)
+        .check_and_get_result()
+        .access_list
+        .unwrap();
+    assert_eq!(access_list, expected);
+}
@@ -0,0 +1,119 @@
+use evmodin::eof::*;
+use hex_literal::hex;
+
+#[test]
+fn eof_valid() {
+    // Minimal container: one code section with a single STOP, no data.
+    let c = EofContainer::parse(&hex!("ef0001 010004 0200010001 040000 00 00800000 00")).unwrap();
+    assert_eq!(c.version, 1);
+    assert_eq!(
+        c.types,
+        vec![EofTypes {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 0
+        }]
+    );
+    assert_eq!(c.code_sections, vec![hex!("00").to_vec()]);
+    assert!(c.data_section.is_empty());
+
+    // Two code sections and a data section.
+    let c = EofContainer::parse(&hex!(
+        "ef0001 010008 02000200030001 040002 00 00800001 00000000 5f5f00 e4 aabb"
+    ))
+    .unwrap();
+    assert_eq!(c.types.len(), 2);
+    assert_eq!(
+        c.code_sections,
+        vec![hex!("5f5f00").to_vec(), hex!("e4").to_vec()]
+    );
+    assert_eq!(c.data_section, hex!("aabb").to_vec());
+}
+
+#[test]
+fn eof_invalid() {
+    for (code, error) in [
+        (&hex!("")[..], EofError::InvalidMagic),
+        (&hex!("ef")[..], EofError::InvalidMagic),
+        (&hex!("ef01")[..], EofError::InvalidMagic),
+        (&hex!("ef00")[..], EofError::IncompleteHeader),
+        (&hex!("ef0000")[..], EofError::InvalidVersion(0)),
+        (&hex!("ef0002")[..], EofError::InvalidVersion(2)),
+        (&hex!("ef0001")[..], EofError::IncompleteHeader),
+        (
+            &hex!("ef0001 020001")[..],
+            EofError::InvalidSectionKind {
+                expected: 0x01,
+                found: 0x02,
+            },
+        ),
+        (&hex!("ef0001 010004 02")[..], EofError::IncompleteHeader),
+        (
+            &hex!("ef0001 010004 020000")[..],
+            EofError::InvalidCodeSectionCount(0),
+        ),
+        (
+            &hex!("ef0001 010004 0200010000")[..],
+            EofError::EmptyCodeSection(0),
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 030000")[..],
+            EofError::InvalidSectionKind {
+                expected: 0x04,
+                found: 0x03,
+            },
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040000 01")[..],
+            EofError::MissingTerminator,
+        ),
+        (
+            &hex!("ef0001 010008 0200010001 040000 00 00800000 00")[..],
+            EofError::InvalidTypeSectionSize(8),
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040000 00 00800000")[..],
+            EofError::InvalidContainerSize {
+                expected: 20,
+                found: 19,
+            },
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040001 00 00800000 00")[..],
+            EofError::InvalidContainerSize {
+                expected: 21,
+                found: 20,
+            },
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040000 00 00800000 00 00")[..],
+            EofError::InvalidContainerSize {
+                expected: 20,
+                found: 21,
+            },
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040000 00 01800000 00")[..],
+            EofError::InvalidFirstSectionType,
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040000 00 00000000 00")[..],
+            EofError::InvalidFirstSectionType,
+        ),
+        (
+            &hex!("ef0001 010004 0200010001 040000 00 00800400 00")[..],
+            EofError::InvalidType(0),
+        ),
+        (
+            &hex!("ef0001 010008 02000200010001 040000 00 00800000 80000000 00 00")[..],
+            EofError::InvalidType(1),
+        ),
+    ] {
+        assert_eq!(
+            EofContainer::parse(code),
+            Err(error),
+            "{}",
+            hex::encode(code)
+        );
+    }
+}
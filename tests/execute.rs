@@ -2,7 +2,12 @@
 
 use core::iter::repeat;
 use ethereum_types::U256;
-use evmodin::{opcode::*, util::*, *};
+use evmodin::{
+    opcode::*,
+    tracing::NoopTracer,
+    util::{mocked_host::MockedHost, *},
+    *,
+};
 use hex_literal::hex;
 use std::cmp::max;
 
@@ -18,6 +23,46 @@ fn empty_code() {
     }
 }
 
+#[test]
+fn zero_gas() {
+    // STOP costs nothing.
+    EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::STOP))
+        .gas(0)
+        .status(StatusCode::Success)
+        .gas_left(0)
+        .check();
+
+    // Gas is checked before the stack, so an empty stack does not matter.
+    let output = EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::ADD))
+        .gas(0)
+        .status(StatusCode::OutOfGas)
+        .gas_left(0)
+        .check_and_get_result();
+    assert_eq!(output.failure_location.unwrap().opcode, OpCode::ADD);
+}
+
+#[test]
+fn empty_code_output() {
+    EvmTester::new()
+        .code(hex!(""))
+        .status(StatusCode::Success)
+        .output_data(vec![])
+        .check()
+}
+
+#[test]
+fn single_jumpdest() {
+    EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::JUMPDEST))
+        .gas(1)
+        .gas_used(1)
+        .status(StatusCode::Success)
+        .output_data(vec![])
+        .check()
+}
+
 #[test]
 fn invalid_push() {
     EvmTester::new()
@@ -63,6 +108,53 @@ fn stack_underflow() {
         .check();
 }
 
+#[test]
+fn pop() {
+    EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::POP))
+        .status(StatusCode::StackUnderflow)
+        .gas_left(0)
+        .check();
+
+    EvmTester::new()
+        .code(Bytecode::new().pushv(1).opcode(OpCode::POP))
+        .status(StatusCode::Success)
+        .gas_used(3 + 2)
+        .check();
+}
+
+#[test]
+fn small_operands_across_64_bit_boundary() {
+    let values = [
+        U256::zero(),
+        U256::one(),
+        U256::from(u64::MAX - 1),
+        U256::from(u64::MAX),
+        U256::from(u64::MAX) + 1,
+        U256::from(u64::MAX) + 2,
+        U256::one() << 255,
+        U256::max_value(),
+    ];
+
+    for &a in &values {
+        for &b in &values {
+            for (op, expected) in [
+                (OpCode::ADD, a.overflowing_add(b).0),
+                (OpCode::SUB, a.overflowing_sub(b).0),
+                (OpCode::LT, U256::from((a < b) as u8)),
+                (OpCode::GT, U256::from((a > b) as u8)),
+                (OpCode::EQ, U256::from((a == b) as u8)),
+            ] {
+                EvmTester::new()
+                    .code(Bytecode::new().pushv(b).pushv(a).opcode(op).ret_top())
+                    .status(StatusCode::Success)
+                    .output_value(expected)
+                    .check();
+            }
+        }
+    }
+}
+
 #[test]
 fn add() {
     EvmTester::new()
@@ -105,6 +197,42 @@ fn dup_all_1() {
         .check()
 }
 
+#[test]
+fn dup_swap_all() {
+    // The n-th item from the top holds value n.
+    let mut prepared = Bytecode::new();
+    for v in (1..=17).rev() {
+        prepared = prepared.pushv(v);
+    }
+
+    for n in 1..=16 {
+        EvmTester::new()
+            .code(
+                prepared
+                    .clone()
+                    .opcode(OpCode(OpCode::DUP1.to_u8() + n - 1))
+                    .ret_top(),
+            )
+            .status(StatusCode::Success)
+            .output_value(n)
+            .check();
+
+        EvmTester::new()
+            .code(
+                prepared
+                    .clone()
+                    .opcode(OpCode(OpCode::SWAP1.to_u8() + n - 1))
+                    .ret_top(),
+            )
+            .status(StatusCode::Success)
+            .output_value(n + 1)
+            .check();
+    }
+
+    assert_eq!(OpCode(OpCode::DUP1.to_u8() + 15), OpCode::DUP16);
+    assert_eq!(OpCode(OpCode::SWAP1.to_u8() + 15), OpCode::SWAP16);
+}
+
 #[test]
 fn dup_stack_overflow() {
     let b = Bytecode::new()
@@ -171,6 +299,33 @@ fn msize() {
         .check()
 }
 
+#[test]
+fn memory_zeroed_in_new_frame() {
+    // Dirty the memory of the first frame, so that its buffer is released for reuse.
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .mstore_value(0, U256::max_value())
+                .mstore_value(0x400, U256::max_value()),
+        )
+        .status(StatusCode::Success)
+        .check();
+
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .pushv(0x400)
+                .opcode(OpCode::MLOAD)
+                .pushv(0)
+                .opcode(OpCode::MLOAD)
+                .opcode(OpCode::OR)
+                .ret_top(),
+        )
+        .status(StatusCode::Success)
+        .output_value(0)
+        .check()
+}
+
 #[test]
 fn gas() {
     EvmTester::new()
@@ -227,6 +382,58 @@ fn comparison() {
         .check()
 }
 
+#[test]
+fn comparison_sign_boundary() {
+    let min = U256::one() << 255;
+    let max = min - 1;
+    let minus_one = U256::max_value();
+
+    // Every result is a full word, so anything other than exactly 0 or 1 shows up.
+    for (op, a, b, expected) in [
+        (OpCode::SLT, min, max, 1),
+        (OpCode::SLT, max, min, 0),
+        (OpCode::SLT, minus_one, U256::zero(), 1),
+        (OpCode::SLT, minus_one, U256::one(), 1),
+        (OpCode::SLT, min, minus_one, 1),
+        (OpCode::SLT, minus_one, minus_one, 0),
+        (OpCode::SGT, min, max, 0),
+        (OpCode::SGT, max, min, 1),
+        (OpCode::SGT, U256::zero(), minus_one, 1),
+        (OpCode::SGT, minus_one, min, 1),
+        (OpCode::LT, min, max, 0),
+        (OpCode::LT, max, min, 1),
+        (OpCode::GT, minus_one, U256::zero(), 1),
+        (OpCode::EQ, min, min, 1),
+        (OpCode::EQ, min, max, 0),
+    ] {
+        EvmTester::new()
+            .code(Bytecode::new().pushv(b).pushv(a).opcode(op).ret_top())
+            .status(StatusCode::Success)
+            .output_value(expected)
+            .check()
+    }
+
+    for (a, expected) in [
+        (U256::zero(), 1),
+        (U256::one(), 0),
+        (min, 0),
+        (minus_one, 0),
+    ] {
+        EvmTester::new()
+            .code(Bytecode::new().pushv(a).opcode(OpCode::ISZERO).ret_top())
+            .status(StatusCode::Success)
+            .output_value(expected)
+            .check()
+    }
+
+    // NOT is bitwise, not boolean.
+    EvmTester::new()
+        .code(Bytecode::new().pushv(0).opcode(OpCode::NOT).ret_top())
+        .status(StatusCode::Success)
+        .output_value(minus_one)
+        .check()
+}
+
 #[allow(clippy::identity_op)]
 #[test]
 fn bitwise() {
@@ -367,6 +574,24 @@ fn jump_over_jumpdest() {
         .check()
 }
 
+#[test]
+fn jump_to_zero() {
+    // JUMPDEST PUSH1 0 JUMP loops until out of gas, 12 gas per iteration.
+    EvmTester::new()
+        .code(hex!("5b600056"))
+        .gas(12 * 100 + 11)
+        .status(StatusCode::OutOfGas)
+        .gas_left(0)
+        .check();
+
+    // Position 0 is not special, it must be a JUMPDEST too.
+    EvmTester::new()
+        .code(hex!("600056"))
+        .status(StatusCode::BadJumpDestination)
+        .gas_left(0)
+        .check();
+}
+
 #[test]
 fn pc_sum() {
     EvmTester::new()
@@ -763,6 +988,31 @@ fn calldataload_outofrange() {
         .check()
 }
 
+#[test]
+fn calldatasize() {
+    for input in [&[][..], &[0xff; 33][..]] {
+        EvmTester::new()
+            .code(Bytecode::new().opcode(OpCode::CALLDATASIZE).ret_top())
+            .input(input.to_vec())
+            .status(StatusCode::Success)
+            .output_value(input.len())
+            .check()
+    }
+}
+
+#[test]
+fn codesize() {
+    // CODESIZE, PUSH1 0, MSTORE, PUSH1 32, PUSH1 0, RETURN
+    let code = Bytecode::new().opcode(OpCode::CODESIZE).ret_top().build();
+    assert_eq!(code.len(), 10);
+
+    EvmTester::new()
+        .code(code)
+        .status(StatusCode::Success)
+        .output_value(10)
+        .check()
+}
+
 #[test]
 fn calldatacopy() {
     let code = Bytecode::new()
@@ -825,6 +1075,37 @@ fn caller_callvalue() {
         .check()
 }
 
+#[test]
+fn environment_base_gas() {
+    for op in [
+        OpCode::ADDRESS,
+        OpCode::ORIGIN,
+        OpCode::CALLER,
+        OpCode::CALLVALUE,
+        OpCode::CALLDATASIZE,
+        OpCode::CODESIZE,
+        OpCode::GASPRICE,
+        OpCode::RETURNDATASIZE,
+        OpCode::COINBASE,
+        OpCode::TIMESTAMP,
+        OpCode::NUMBER,
+        OpCode::DIFFICULTY,
+        OpCode::GASLIMIT,
+        OpCode::CHAINID,
+        OpCode::BASEFEE,
+    ] {
+        for (gas, status) in [(2, StatusCode::Success), (1, StatusCode::OutOfGas)] {
+            EvmTester::new()
+                .revision(Revision::London)
+                .code(Bytecode::new().opcode(op))
+                .gas(gas)
+                .status(status)
+                .gas_left(0)
+                .check()
+        }
+    }
+}
+
 #[test]
 fn undefined() {
     EvmTester::new()
@@ -922,6 +1203,38 @@ fn return_empty_buffer_at_high_offset() {
     }
 }
 
+#[test]
+fn return_memory_expansion_cost() {
+    // 12 gas for the MSTORE expanding memory to one word, 6 for the RETURN arguments.
+    for (offset, size, gas_used) in [
+        // Within active memory: no additional charge, regardless of size.
+        (0, 32, 18),
+        (16, 16, 18),
+        (0, 1, 18),
+        // Expanding: only the difference to two words, 6 - 3.
+        (0, 64, 21),
+        // Expanding to 33 words: 33 * 3 + 33 * 33 / 512 - 3.
+        (32, 1024, 18 + 98),
+    ] {
+        for (opcode, status) in [
+            (OpCode::RETURN, StatusCode::Success),
+            (OpCode::REVERT, StatusCode::Revert),
+        ] {
+            EvmTester::new()
+                .code(
+                    Bytecode::new()
+                        .mstore_value(0, 1)
+                        .pushv(size)
+                        .pushv(offset)
+                        .opcode(opcode),
+                )
+                .status(status)
+                .gas_used(gas_used)
+                .check();
+        }
+    }
+}
+
 #[test]
 fn shl() {
     EvmTester::new()
@@ -998,6 +1311,83 @@ fn undefined_instruction_analysis_overflow() {
         .check()
 }
 
+#[test]
+fn undefined_instructions_consume_all_gas() {
+    let undefined = (0..=u8::MAX)
+        .map(OpCode)
+        .filter(|op| op.name() == "UNDEFINED")
+        .collect::<Vec<_>>();
+    for op in [0x0c, 0x0f, 0x1e, 0x21, 0x2f, 0xa5, 0xef, 0xf6, 0xfc].map(OpCode) {
+        assert!(undefined.contains(&op), "{}", op);
+    }
+
+    for revision in Revision::iter() {
+        for &op in &undefined {
+            EvmTester::new()
+                .revision(revision)
+                .code(Bytecode::new().pushv(1).opcode(op).opcode(OpCode::STOP))
+                .gas(1000)
+                .status(StatusCode::UndefinedInstruction)
+                .gas_used(1000)
+                .check();
+        }
+    }
+
+    // Not yet activated instructions fail the same way.
+    for (op, revision) in [
+        (OpCode::SHL, Revision::Byzantium),
+        (OpCode::CHAINID, Revision::Petersburg),
+        (OpCode::BASEFEE, Revision::Berlin),
+    ] {
+        EvmTester::new()
+            .revision(revision)
+            .code(Bytecode::new().pushv(1).pushv(1).opcode(op))
+            .gas(1000)
+            .status(StatusCode::UndefinedInstruction)
+            .gas_used(1000)
+            .check();
+    }
+}
+
+#[test]
+fn undefined_instructions_push0() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .opcode(OpCode::PUSH0)
+            .pushv(0x5f)
+            .opcode(OpCode::SHL)
+            .opcode(OpCode::PUSH0)
+            .build(),
+    );
+
+    assert_eq!(
+        code.undefined_instructions(Revision::Istanbul),
+        vec![(0, OpCode::PUSH0), (4, OpCode::PUSH0)]
+    );
+    assert_eq!(
+        code.undefined_instructions(Revision::Byzantium),
+        vec![(0, OpCode::PUSH0), (3, OpCode::SHL), (4, OpCode::PUSH0)]
+    );
+    assert!(code.undefined_instructions(Revision::Shanghai).is_empty());
+}
+
+#[test]
+fn push0() {
+    EvmTester::new()
+        .revision(Revision::Shanghai)
+        .code(Bytecode::new().opcode(OpCode::PUSH0).ret_top())
+        .status(StatusCode::Success)
+        .gas_used(17)
+        .output_value(0)
+        .check();
+
+    EvmTester::new()
+        .revision(Revision::London)
+        .code(Bytecode::new().opcode(OpCode::PUSH0))
+        .status(StatusCode::UndefinedInstruction)
+        .check()
+}
+
 #[test]
 fn abort() {
     for r in Revision::iter() {
@@ -1048,6 +1438,36 @@ fn memory_big_allocation() {
         .check()
 }
 
+#[test]
+fn memory_expansion_overflow() {
+    // Size of 2^64 - 1 is rejected before any gas computation or allocation.
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .pushv(u64::MAX)
+                .pushv(0)
+                .pushv(0)
+                .opcode(OpCode::CALLDATACOPY),
+        )
+        .status(StatusCode::OutOfGas)
+        .gas_left(0)
+        .check();
+
+    // The largest region allowed is unaffordable.
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .pushv(u32::MAX)
+                .pushv(0)
+                .pushv(u32::MAX)
+                .opcode(OpCode::CALLDATACOPY),
+        )
+        .gas(1_000_000)
+        .status(StatusCode::OutOfGas)
+        .gas_left(0)
+        .check();
+}
+
 #[test]
 fn memory_grow_mstore8() {
     let code = Bytecode::new()
@@ -1090,6 +1510,66 @@ fn memory_grow_mstore8() {
         .check()
 }
 
+#[test]
+fn mload_unaligned_across_expansion() {
+    // m[0] = aa; m[31] = bb; MLOAD(1) reads bytes 1..33, expanding memory to 2 words.
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .mstore8_value(0, 0xaa)
+                .mstore8_value(31, 0xbb)
+                .pushv(1)
+                .opcode(OpCode::MLOAD)
+                .opcode(OpCode::MSIZE)
+                .mstore(0x20)
+                .mstore(0)
+                .ret(0, 0x40),
+        )
+        .status(StatusCode::Success)
+        .inspect_output(|output| {
+            let mut value = [0; 32];
+            value[30] = 0xbb;
+            assert_eq!(output[..32], value);
+            assert_eq!(U256::from_big_endian(&output[32..]), 64.into());
+        })
+        .check()
+}
+
+#[test]
+fn mstore8_writes_one_byte() {
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .mstore8_value(0, 0x1234)
+                .opcode(OpCode::MSIZE)
+                .mstore(0x20)
+                .ret(0, 0x40),
+        )
+        .status(StatusCode::Success)
+        .inspect_output(|output| {
+            assert_eq!(output[0], 0x34);
+            assert_eq!(output[1..32], [0; 31]);
+            assert_eq!(U256::from_big_endian(&output[32..]), 32.into());
+        })
+        .check();
+
+    // Surrounding bytes are left as they were.
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .mstore_value(0, U256::max_value())
+                .mstore8_value(1, 0x1234)
+                .ret(0, 0x20),
+        )
+        .status(StatusCode::Success)
+        .inspect_output(|output| {
+            assert_eq!(output[0], 0xff);
+            assert_eq!(output[1], 0x34);
+            assert_eq!(output[2..], [0xff; 30]);
+        })
+        .check();
+}
+
 #[test]
 fn mstore8_memory_cost() {
     for (gas, status) in [(12, StatusCode::Success), (11, StatusCode::OutOfGas)] {
@@ -1112,6 +1592,30 @@ fn keccak256_memory_cost() {
     }
 }
 
+#[test]
+fn keccak256_unaffordable_expansion() {
+    let keccak = Bytecode::new().pushv(1).pushv(0).opcode(OpCode::KECCAK256);
+
+    // 42 gas pay for the pushes, the static cost and one word of hashing once memory has been expanded.
+    EvmTester::new()
+        .code(
+            Bytecode::new()
+                .mstore8_value(0, 0)
+                .append_bc(keccak.clone()),
+        )
+        .gas(12 + 42)
+        .gas_used(12 + 42)
+        .status(StatusCode::Success)
+        .check();
+
+    // But not for the expansion on top.
+    EvmTester::new()
+        .code(keccak)
+        .gas(42)
+        .status(StatusCode::OutOfGas)
+        .check();
+}
+
 #[test]
 fn calldatacopy_memory_cost() {
     for (gas, status) in [(18, StatusCode::Success), (17, StatusCode::OutOfGas)] {
@@ -1129,6 +1633,59 @@ fn calldatacopy_memory_cost() {
     }
 }
 
+#[test]
+fn copy_cost_rounds_up_to_words() {
+    // (size, copy cost, memory expansion cost)
+    for (size, copy_cost, memory_cost) in [(0, 0, 0), (32, 3, 3), (33, 6, 6)] {
+        let copy = |op| Bytecode::new().pushv(size).pushv(0).pushv(0).opcode(op);
+        let base_cost = 3 * 3 + 3 + copy_cost + memory_cost;
+
+        for op in [OpCode::CALLDATACOPY, OpCode::CODECOPY] {
+            EvmTester::new()
+                .code(copy(op))
+                .status(StatusCode::Success)
+                .gas_used(base_cost)
+                .check()
+        }
+
+        EvmTester::new()
+            .code(
+                Bytecode::new()
+                    .pushv(size)
+                    .pushv(0)
+                    .pushv(0)
+                    .pushv(0xaa)
+                    .opcode(OpCode::EXTCODECOPY),
+            )
+            .status(StatusCode::Success)
+            .gas_used(4 * 3 + 700 + copy_cost + memory_cost)
+            .check();
+
+        // Fill return data with a zero-gas call first: 7 pushes, CALL and POP.
+        let call_cost = 7 * 3 + 700 + 2;
+        EvmTester::new()
+            .apply_host_fn(|host, _| {
+                host.call_result.output_data = vec![0xffu8; 33].into();
+            })
+            .code(
+                Bytecode::new()
+                    .pushv(0)
+                    .pushv(0)
+                    .pushv(0)
+                    .pushv(0)
+                    .pushv(0)
+                    .pushv(0xaa)
+                    .pushv(0)
+                    .opcode(OpCode::CALL)
+                    .opcode(OpCode::POP)
+                    .append_bc(copy(OpCode::RETURNDATACOPY)),
+            )
+            .status(StatusCode::Success)
+            .gas_used(call_cost + base_cost)
+            .check();
+    }
+}
+
 const MAX_CODE_SIZE: usize = 0x6000;
 
 #[test]
@@ -1353,3 +1910,373 @@ fn memory_access() {
         }
     }
 }
+
+#[test]
+fn every_defined_opcode_is_dispatched() {
+    for byte in 0..=u8::MAX {
+        let op = OpCode(byte);
+        if op.name() == "UNDEFINED" {
+            continue;
+        }
+
+        // Enough zeros on the stack for any instruction.
+        let mut code = repeat([OpCode::PUSH1.to_u8(), 0])
+            .take(17)
+            .flatten()
+            .collect::<Vec<_>>();
+        code.push(byte);
+
+        let output = EvmTester::new().code(code).check_and_get_result();
+        assert_ne!(
+            output.status_code,
+            StatusCode::UndefinedInstruction,
+            "{}",
+            op
+        );
+        // With catch-panic, reaching the fallback of the dispatch fails as an internal error.
+        assert!(
+            !matches!(output.status_code, StatusCode::InternalError(_)),
+            "{}: {}",
+            op,
+            output.status_code
+        );
+    }
+}
+
+#[test]
+fn output_size_limit() {
+    let config = Config {
+        max_output_size: Some(32),
+        ..Default::default()
+    };
+
+    for (opcode, status) in [
+        (OpCode::RETURN, StatusCode::Success),
+        (OpCode::REVERT, StatusCode::Revert),
+    ] {
+        let ret = |size: u64| Bytecode::new().pushv(size).pushv(0).opcode(opcode);
+
+        EvmTester::new()
+            .config(config.clone())
+            .code(ret(32))
+            .status(status)
+            .output_data([0; 32])
+            .check();
+
+        // Fails before memory is expanded, even with plenty of gas for it.
+        EvmTester::new()
+            .config(config.clone())
+            .code(ret(0x100000))
+            .gas(i64::MAX)
+            .status(StatusCode::OutputSizeLimitExceeded)
+            .gas_left(0)
+            .check();
+    }
+}
+
+#[test]
+fn step_limit() {
+    let config = Config {
+        max_steps: Some(30),
+        ..Default::default()
+    };
+
+    // JUMPDEST PUSH1 0 JUMP loops forever with enough gas.
+    let output = EvmTester::new()
+        .config(config.clone())
+        .code(hex!("5b600056"))
+        .gas(i64::MAX)
+        .status(StatusCode::StepLimitExceeded)
+        .gas_left(0)
+        .check_and_get_result();
+    assert_eq!(output.failure_location.unwrap().pc, 0);
+
+    // Exactly the limit is fine.
+    EvmTester::new()
+        .config(config)
+        .code(Bytecode::new().append(repeat(OpCode::JUMPDEST.to_u8()).take(30)))
+        .status(StatusCode::Success)
+        .gas_used(30)
+        .check();
+}
+
+#[test]
+fn opcode_trace() {
+    let code = asm("CALLDATASIZE PUSH1 7 JUMPI PUSH1 0 STOP JUMPDEST PUSH1 2 STOP");
+    let t = EvmTester::new().code(code);
+
+    assert_eq!(t.clone().check_and_get_result().opcode_trace, None);
+
+    let t = t.config(Config {
+        opcode_trace: true,
+        ..Default::default()
+    });
+
+    assert_eq!(
+        t.clone().check_and_get_result().opcode_trace,
+        Some(vec![
+            (0, OpCode::CALLDATASIZE),
+            (1, OpCode::PUSH1),
+            (3, OpCode::JUMPI),
+            (4, OpCode::PUSH1),
+            (6, OpCode::STOP),
+        ])
+    );
+
+    assert_eq!(
+        t.input(vec![0xff]).check_and_get_result().opcode_trace,
+        Some(vec![
+            (0, OpCode::CALLDATASIZE),
+            (1, OpCode::PUSH1),
+            (3, OpCode::JUMPI),
+            (7, OpCode::JUMPDEST),
+            (8, OpCode::PUSH1),
+            (10, OpCode::STOP),
+        ])
+    );
+
+    // A failed frame reports the trace up to the instruction it failed at.
+    assert_eq!(
+        EvmTester::new()
+            .code(asm("CALLDATASIZE POP POP PUSH1 0"))
+            .config(Config {
+                opcode_trace: true,
+                ..Default::default()
+            })
+            .status(StatusCode::StackUnderflow)
+            .check_and_get_result()
+            .opcode_trace,
+        Some(vec![
+            (0, OpCode::CALLDATASIZE),
+            (1, OpCode::POP),
+            (2, OpCode::POP),
+        ])
+    );
+}
+
+#[test]
+fn call_and_return_data_opcodes_activation() {
+    for (op, since) in [
+        (OpCode::DELEGATECALL, Revision::Homestead),
+        (OpCode::STATICCALL, Revision::Byzantium),
+        (OpCode::RETURNDATASIZE, Revision::Byzantium),
+        (OpCode::RETURNDATACOPY, Revision::Byzantium),
+        (OpCode::REVERT, Revision::Byzantium),
+    ] {
+        let mut code = repeat([OpCode::PUSH1.to_u8(), 0])
+            .take(6)
+            .flatten()
+            .collect::<Vec<_>>();
+        code.push(op.to_u8());
+
+        for revision in Revision::iter() {
+            let output = EvmTester::new()
+                .revision(revision)
+                .code(code.clone())
+                .check_and_get_result();
+            assert_eq!(
+                output.status_code == StatusCode::UndefinedInstruction,
+                revision < since,
+                "{} in {}",
+                op,
+                revision
+            );
+        }
+    }
+}
+
+#[test]
+fn metering_disabled() {
+    let config = Config {
+        metering: false,
+        max_steps: Some(3000),
+        ..Default::default()
+    };
+
+    // JUMPDEST PUSH1 0 JUMP loops until the step limit, way past the gas given.
+    let t = EvmTester::new().code(hex!("5b600056")).gas(10);
+    t.clone()
+        .config(Config {
+            metering: true,
+            ..config.clone()
+        })
+        .status(StatusCode::OutOfGas)
+        .check();
+    t.config(config.clone())
+        .status(StatusCode::StepLimitExceeded)
+        .check();
+
+    // No gas is reported as used.
+    EvmTester::new()
+        .config(config.clone())
+        .code(asm(
+            "PUSH1 1 PUSH1 2 ADD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN",
+        ))
+        .gas(3)
+        .status(StatusCode::Success)
+        .gas_used(0)
+        .output_value(3)
+        .check();
+
+    // Memory is capped to what the gas of the frame pays for: 3 for the first word, 27 for 9 words.
+    for (offset, status) in [(0, StatusCode::Success), (0x100, StatusCode::OutOfGas)] {
+        EvmTester::new()
+            .config(config.clone())
+            .code(Bytecode::new().mstore_value(offset, 1))
+            .gas(10)
+            .status(status)
+            .check();
+    }
+
+    // The callee gets the gas asked for up to the gas of the frame, which stays the same after the
+    // call.
+    EvmTester::new()
+        .config(config)
+        .code(
+            Bytecode::new()
+                .append_bc(CallInstruction::call(0xaa).gas(0xffffff))
+                .opcode(OpCode::GAS)
+                .ret_top(),
+        )
+        .gas(1000)
+        .status(StatusCode::Success)
+        .gas_used(0)
+        .output_value(1000)
+        .inspect_host(|host, _| {
+            let r = host.recorded.lock();
+            assert_eq!(r.calls.len(), 1);
+            assert_eq!(r.calls[0].gas, 1000);
+        })
+        .check();
+}
+
+#[test]
+fn peak_memory() {
+    // Expand to 3 words, then to 9, then touch only memory that is already there.
+    let output = EvmTester::new()
+        .code(
+            Bytecode::new()
+                .mstore8_value(0x40, 1)
+                .mstore_value(0x100, 2)
+                .pushv(0)
+                .opcode(OpCode::MLOAD)
+                .ret_top(),
+        )
+        .status(StatusCode::Success)
+        .check_and_get_result();
+    assert_eq!(output.peak_memory, 0x120);
+
+    let output = EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::STOP))
+        .status(StatusCode::Success)
+        .check_and_get_result();
+    assert_eq!(output.peak_memory, 0);
+}
+
+#[test]
+fn memory_zeroed_in_reused_buffer() {
+    let dirty = Bytecode::new().mstore_value(0x1000, U256::max_value());
+
+    for _ in 0..3 {
+        // Memory buffers of finished frames are reused by the next ones on the same thread.
+        EvmTester::new()
+            .code(dirty.clone().ret(0x1000, 0x20))
+            .status(StatusCode::Success)
+            .output_data([0xff; 32])
+            .check();
+        EvmTester::new()
+            .code(dirty.clone().opcode(OpCode::INVALID))
+            .status(StatusCode::InvalidInstruction)
+            .check();
+
+        EvmTester::new()
+            .code(
+                Bytecode::new()
+                    .pushv(0x1000)
+                    .opcode(OpCode::MLOAD)
+                    .opcode(OpCode::MSIZE)
+                    .mstore(0x20)
+                    .mstore(0)
+                    .ret(0, 0x40),
+            )
+            .status(StatusCode::Success)
+            .inspect_output(|output| {
+                assert_eq!(output[..32], [0; 32]);
+                assert_eq!(U256::from_big_endian(&output[32..]), 0x1020.into());
+            })
+            .check();
+    }
+}
+
+#[test]
+fn specialized_interpreter() {
+    fn check<S: RevisionSpec>(code: &AnalyzedCode) {
+        let message = Message::builder().gas(1_000_000).build();
+        // Rules differing from mainnet only in BLOCKHASH, which the code does not use, select the generic interpreter.
+        let generic = code.execute_with_config(
+            &mut MockedHost::default(),
+            &mut NoopTracer,
+            None,
+            message.clone(),
+            S::REVISION,
+            Config {
+                spec: |revision| Spec {
+                    block_hash_window: 257,
+                    ..Spec::mainnet(revision)
+                },
+                ..Config::default()
+            },
+        );
+        let specialized = code.execute_specialized::<S, _, _>(
+            &mut MockedHost::default(),
+            &mut NoopTracer,
+            None,
+            message,
+            Config::default(),
+        );
+        assert_eq!(specialized, generic, "{}", S::REVISION);
+    }
+
+    // EXP, SSTORE, BALANCE and CALL are all priced by revision.
+    let code = AnalyzedCode::analyze(asm("PUSH1 0xff PUSH1 3 EXP PUSH1 1 SSTORE \
+         PUSH1 0xbb BALANCE PUSH1 2 SSTORE \
+         PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0xcc PUSH2 0xffff CALL \
+         PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN"));
+
+    let checks: [fn(&AnalyzedCode); 13] = [
+        check::<FrontierSpec>,
+        check::<HomesteadSpec>,
+        check::<TangerineSpec>,
+        check::<SpuriousSpec>,
+        check::<ByzantiumSpec>,
+        check::<ConstantinopleSpec>,
+        check::<PetersburgSpec>,
+        check::<IstanbulSpec>,
+        check::<BerlinSpec>,
+        check::<LondonSpec>,
+        check::<ParisSpec>,
+        check::<ShanghaiSpec>,
+        check::<CancunSpec>,
+    ];
+    for check in checks.iter() {
+        check(&code);
+    }
+}
+
+#[test]
+#[should_panic(expected = "specialized interpreter requires mainnet rules")]
+fn specialized_interpreter_rejects_custom_spec() {
+    AnalyzedCode::analyze(asm("STOP")).execute_specialized::<BerlinSpec, _, _>(
+        &mut MockedHost::default(),
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(1_000_000).build(),
+        Config {
+            spec: |revision| Spec {
+                eip2929: false,
+                ..Spec::mainnet(revision)
+            },
+            ..Config::default()
+        },
+    );
+}
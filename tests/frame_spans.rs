@@ -0,0 +1,45 @@
+#![cfg(feature = "tracing")]
+
+use ethereum_types::Address;
+use evmodin::{opcode::*, tracing::NoopTracer, util::*, *};
+use tracing_test::traced_test;
+
+#[test]
+#[traced_test]
+fn frame_span_per_call() {
+    let call = |callee: u64| {
+        Bytecode::new()
+            .pushv(0)
+            .pushv(0)
+            .pushv(0)
+            .pushv(0)
+            .pushv(0)
+            .pushv(callee)
+            .pushv(0xffff)
+            .opcode(OpCode::CALL)
+    };
+
+    let mut host = DelegatingHost::new(mocked_host::MockedHost::default()).callee(
+        AnalyzedCode::analyze(Bytecode::new().opcode(OpCode::STOP).build()),
+        Revision::Istanbul,
+    );
+
+    let output = AnalyzedCode::analyze(call(0xaa).append_bc(call(0xbb)).build()).execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(100_000).build(),
+        Revision::Istanbul,
+    );
+    assert_eq!(output.status_code, StatusCode::Success);
+
+    for destination in [
+        Address::zero(),
+        Address::from_low_u64_be(0xaa),
+        Address::from_low_u64_be(0xbb),
+    ] {
+        assert!(logs_contain(&format!("destination={:?}", destination)));
+    }
+    assert!(logs_contain("depth=1"));
+    assert!(logs_contain("kind=\"Call\""));
+}
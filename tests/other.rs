@@ -52,3 +52,130 @@ fn jumpdest_with_high_offset() {
             .check()
     }
 }
+
+#[test]
+fn jumpdest_inside_push_dense_code() {
+    const CHUNKS: usize = 720;
+    const CHUNK_SIZE: usize = 33;
+    const PREFIX_SIZE: usize = 4;
+
+    let end = PREFIX_SIZE + CHUNKS * CHUNK_SIZE;
+    let code_to = |target: usize| {
+        let mut code = Bytecode::new()
+            .pushb((target as u16).to_be_bytes())
+            .opcode(OpCode::JUMP)
+            .build();
+        for _ in 0..CHUNKS {
+            code.push(OpCode::PUSH32.to_u8());
+            code.extend_from_slice(&[OpCode::JUMPDEST.to_u8(); 32]);
+        }
+        code.push(OpCode::JUMPDEST.to_u8());
+        code
+    };
+
+    for target in [
+        PREFIX_SIZE,
+        PREFIX_SIZE + 1,
+        PREFIX_SIZE + 32,
+        end - CHUNK_SIZE,
+        end - 1,
+    ] {
+        EvmTester::new()
+            .code(code_to(target))
+            .status(StatusCode::BadJumpDestination)
+            .check()
+    }
+
+    EvmTester::new()
+        .code(code_to(end))
+        .status(StatusCode::Success)
+        .check()
+}
+
+#[test]
+fn trailing_metadata() {
+    // CBOR metadata appended by Solidity: {"ipfs": hash, "solc": 0.8.17}, then its length.
+    // The hash starts at an instruction boundary with JUMPDEST PUSH1 0x5b.
+    let mut metadata = hex_literal::hex!("a264697066735822 1220 5b605b").to_vec();
+    metadata.extend_from_slice(&[0; 29]);
+    metadata.extend_from_slice(&hex_literal::hex!("64736f6c6343 000811 0033"));
+    assert_eq!(metadata.len(), 53);
+
+    let with_metadata = |runtime: Vec<u8>| [runtime, metadata.clone()].concat();
+
+    // Execution never reaches the metadata.
+    EvmTester::new()
+        .code(with_metadata(asm(
+            "PUSH1 1 PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN INVALID",
+        )))
+        .status(StatusCode::Success)
+        .output_value(1)
+        .check();
+    EvmTester::new()
+        .code(with_metadata(asm("PUSH1 0 POP STOP")))
+        .status(StatusCode::Success)
+        .gas_used(5)
+        .check();
+
+    let jump_to = |target: u8| with_metadata(asm(&format!("PUSH1 {} JUMP INVALID", target)));
+    let hash = 4 + 10;
+
+    // Bytes of the metadata are analyzed like code, and no target panics.
+    for target in 0..(4 + metadata.len() as u8) {
+        let status = EvmTester::new()
+            .code(jump_to(target))
+            .check_and_get_result()
+            .status_code;
+        assert_eq!(
+            status == StatusCode::BadJumpDestination,
+            target != hash,
+            "{}",
+            target
+        );
+    }
+    EvmTester::new()
+        .code(jump_to(hash))
+        .status(StatusCode::Success)
+        .check();
+}
+
+#[test]
+fn validate_code_limits() {
+    let valid = Bytecode::new().pushv(1).opcode(OpCode::STOP).build();
+    assert_eq!(validate_code(&valid, Revision::London), Ok(()));
+
+    let oversized = vec![OpCode::JUMPDEST.to_u8(); MAX_CODE_SIZE + 1];
+    assert_eq!(
+        validate_code(&oversized, Revision::London),
+        Err(CodeError::TooLarge {
+            size: MAX_CODE_SIZE + 1
+        })
+    );
+    assert_eq!(validate_code(&oversized, Revision::Tangerine), Ok(()));
+    assert_eq!(
+        validate_code(&oversized[..MAX_CODE_SIZE], Revision::London),
+        Ok(())
+    );
+
+    let prefixed = [0xef, 0x00];
+    assert_eq!(
+        validate_code(&prefixed, Revision::London),
+        Err(CodeError::ReservedPrefix)
+    );
+    assert_eq!(validate_code(&prefixed, Revision::Berlin), Ok(()));
+
+    // PUSH0 is fine as push data, but not as an instruction before Shanghai.
+    let push0 = Bytecode::new()
+        .pushb([OpCode::PUSH0.to_u8()])
+        .opcode(OpCode::PUSH0)
+        .build();
+    assert_eq!(validate_code(&push0, Revision::London), Ok(()));
+    assert_eq!(
+        validate_code_strict(&push0, Revision::London),
+        Err(CodeError::UndefinedInstruction {
+            pc: 2,
+            opcode: OpCode::PUSH0.to_u8()
+        })
+    );
+    assert_eq!(validate_code_strict(&push0, Revision::Shanghai), Ok(()));
+}
@@ -0,0 +1,297 @@
+use ethereum_types::{Address, H256, U256};
+use evmodin::{host::*, opcode::*, rpc::*, tracing::NoopTracer, util::*, *};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+/// Endpoint answering from canned responses and recording every request.
+#[derive(Default)]
+struct CannedTransport {
+    responses: HashMap<(String, String), Value>,
+    requests: RefCell<Vec<(String, Value)>>,
+}
+
+impl CannedTransport {
+    fn respond(mut self, method: &str, params: Value, result: Value) -> Self {
+        self.responses
+            .insert((method.to_string(), params.to_string()), result);
+        self
+    }
+}
+
+impl RpcTransport for CannedTransport {
+    fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        self.requests
+            .borrow_mut()
+            .push((method.to_string(), params.clone()));
+        self.responses
+            .get(&(method.to_string(), params.to_string()))
+            .cloned()
+            .ok_or_else(|| format!("no canned response for {}", params))
+    }
+}
+
+#[test]
+fn forked_execution() {
+    let contract = Address::from_low_u64_be(0xaa);
+    let contract_hex = format!("0x{:040x}", 0xaa);
+    let other_hex = format!("0x{:040x}", 0xbb);
+
+    // Read the balance of 0xbb and slot 1 twice each, store their sum into slot 1 and return it.
+    let code = asm("PUSH1 0xbb BALANCE POP PUSH1 0xbb BALANCE \
+         PUSH1 1 SLOAD POP PUSH1 1 SLOAD ADD \
+         PUSH1 1 SSTORE \
+         PUSH1 1 SLOAD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN");
+
+    let transport = CannedTransport::default()
+        .respond(
+            "eth_getTransactionCount",
+            json!([contract_hex, "0xa"]),
+            json!("0x1"),
+        )
+        .respond("eth_getBalance", json!([contract_hex, "0xa"]), json!("0x0"))
+        .respond(
+            "eth_getCode",
+            json!([contract_hex, "0xa"]),
+            json!(format!("0x{}", hex::encode(&code))),
+        )
+        .respond(
+            "eth_getTransactionCount",
+            json!([other_hex, "0xa"]),
+            json!("0x0"),
+        )
+        .respond("eth_getBalance", json!([other_hex, "0xa"]), json!("0x100"))
+        .respond("eth_getCode", json!([other_hex, "0xa"]), json!("0x"))
+        .respond(
+            "eth_getStorageAt",
+            json!([contract_hex, "0x1", "0xa"]),
+            json!("0x0000000000000000000000000000000000000000000000000000000000000023"),
+        );
+
+    let mut host = RpcHost::new(
+        &transport,
+        10,
+        Revision::Berlin,
+        TxContext::builder().build(),
+    );
+
+    let output = AnalyzedCode::analyze(host.code(contract).to_vec()).execute(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder()
+            .destination(contract)
+            .gas(100_000)
+            .build(),
+        Revision::Berlin,
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(output.output_data, H256::from_low_u64_be(0x123).as_bytes());
+
+    // The write is kept locally.
+    assert_eq!(
+        host.storage_changes().collect::<Vec<_>>(),
+        [(
+            contract,
+            H256::from_low_u64_be(1),
+            H256::from_low_u64_be(0x123)
+        )]
+    );
+
+    // Every account and slot was fetched exactly once, and nothing was written to the endpoint.
+    let mut requests = transport
+        .requests
+        .borrow()
+        .iter()
+        .map(|(method, params)| (method.clone(), params.to_string()))
+        .collect::<Vec<_>>();
+    requests.sort_unstable();
+    let mut expected = transport.responses.keys().cloned().collect::<Vec<_>>();
+    expected.sort_unstable();
+    assert_eq!(requests, expected);
+}
+
+/// Canned responses for an account without storage at block 10.
+fn account(
+    transport: CannedTransport,
+    address: Address,
+    balance: &str,
+    code: &[u8],
+) -> CannedTransport {
+    let address = format!("0x{:040x}", address);
+    transport
+        .respond(
+            "eth_getTransactionCount",
+            json!([address, "0xa"]),
+            json!("0x0"),
+        )
+        .respond("eth_getBalance", json!([address, "0xa"]), json!(balance))
+        .respond(
+            "eth_getCode",
+            json!([address, "0xa"]),
+            json!(format!("0x{}", hex::encode(code))),
+        )
+}
+
+#[test]
+fn forked_nested_calls() {
+    let contract = Address::from_low_u64_be(0xaa);
+    let stores = Address::from_low_u64_be(0xbb);
+    let reverts = Address::from_low_u64_be(0xcc);
+
+    // Call 0xbb with value 1, which writes slot 2, then 0xcc, which writes slot 3 and reverts.
+    // Return the sum of the call results.
+    let code = Bytecode::new()
+        .append_bc(CallInstruction::call(0xbb).gas(0xffff).value(1))
+        .append_bc(CallInstruction::call(0xcc).gas(0xffff))
+        .opcode(OpCode::ADD)
+        .ret_top()
+        .build();
+
+    let transport = CannedTransport::default();
+    let transport = account(transport, contract, "0x10", &code);
+    let transport = account(transport, stores, "0x0", &asm("PUSH1 0x42 PUSH1 2 SSTORE"));
+    let transport = account(
+        transport,
+        reverts,
+        "0x0",
+        &asm("PUSH1 0x43 PUSH1 3 SSTORE PUSH1 0 DUP1 REVERT"),
+    );
+    let transport = transport
+        .respond(
+            "eth_getStorageAt",
+            json!([format!("0x{:040x}", stores), "0x2", "0xa"]),
+            json!("0x0"),
+        )
+        .respond(
+            "eth_getStorageAt",
+            json!([format!("0x{:040x}", reverts), "0x3", "0xa"]),
+            json!("0x0"),
+        );
+
+    let mut host = RpcHost::new(
+        &transport,
+        10,
+        Revision::Berlin,
+        TxContext::builder().build(),
+    );
+    let output = host.execute(
+        &Message::builder()
+            .destination(contract)
+            .gas(100_000)
+            .build(),
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), U256::one());
+
+    // Only the changes of the successful call are kept.
+    assert_eq!(
+        host.storage_changes().collect::<Vec<_>>(),
+        [(
+            stores,
+            H256::from_low_u64_be(2),
+            H256::from_low_u64_be(0x42)
+        )]
+    );
+    assert_eq!(host.get_balance(contract), 0xf.into());
+    assert_eq!(host.get_balance(stores), 1.into());
+
+    // So are the accesses.
+    assert_eq!(
+        host.access_storage(stores, H256::from_low_u64_be(2)),
+        AccessStatus::Warm
+    );
+    assert_eq!(
+        host.access_storage(reverts, H256::from_low_u64_be(3)),
+        AccessStatus::Cold
+    );
+    host.warm_storage(contract, H256::from_low_u64_be(4));
+    assert_eq!(
+        host.access_storage(contract, H256::from_low_u64_be(4)),
+        AccessStatus::Warm
+    );
+}
+
+/// Serve JSON-RPC over HTTP on a local port, answering `eth_blockNumber` and failing anything else.
+fn serve(requests: usize) -> (String, thread::JoinHandle<Vec<Value>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+
+    let server = thread::spawn(move || {
+        let mut received = Vec::new();
+        for stream in listener.incoming().take(requests) {
+            let mut stream = BufReader::new(stream.unwrap());
+
+            let mut request_line = String::new();
+            stream.read_line(&mut request_line).unwrap();
+            assert_eq!(request_line, "POST /rpc HTTP/1.1\r\n");
+
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            stream.read_exact(&mut body).unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+
+            let response = if request["method"] == "eth_blockNumber" {
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": "0x10" })
+            } else {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32601, "message": "method not found" },
+                })
+            }
+            .to_string();
+            write!(
+                stream.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+
+            received.push(request);
+        }
+        received
+    });
+
+    (url, server)
+}
+
+#[test]
+fn http_transport() {
+    let (url, server) = serve(2);
+    let transport = HttpTransport::new(&url).unwrap();
+
+    assert_eq!(
+        transport.request("eth_blockNumber", json!([])),
+        Ok(json!("0x10"))
+    );
+    assert!(transport
+        .request("eth_foo", json!([1]))
+        .unwrap_err()
+        .contains("method not found"));
+
+    let received = server.join().unwrap();
+    assert_eq!(received[0]["method"], "eth_blockNumber");
+    assert_eq!(received[1]["params"], json!([1]));
+    assert_ne!(received[0]["id"], received[1]["id"]);
+
+    assert!(HttpTransport::new("https://localhost:8545").is_err());
+}
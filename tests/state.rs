@@ -1,6 +1,7 @@
-use ethereum_types::{Address, H256};
+use ethereum_types::{Address, H256, U256};
 use evmodin::{
     opcode::*,
+    tracing::NoopTracer,
     util::{mocked_host::*, *},
     *,
 };
@@ -122,6 +123,27 @@ fn sstore_out_of_block_gas() {
     }
 }
 
+#[test]
+fn sstore_out_of_gas_location() {
+    for (gas, pc, opcode) in [(20008, 6, OpCode::SSTORE), (20010, 7, OpCode::POP)] {
+        let output = EvmTester::new()
+            .code(Bytecode::new().pushv(0).sstore(0, 1).opcode(OpCode::POP))
+            .gas(gas)
+            .status(StatusCode::OutOfGas)
+            .check_and_get_result();
+        assert_eq!(
+            output.failure_location,
+            Some(FailureLocation { pc, opcode })
+        );
+    }
+
+    let output = EvmTester::new()
+        .code(Bytecode::new().sstore(0, 1))
+        .status(StatusCode::Success)
+        .check_and_get_result();
+    assert_eq!(output.failure_location, None);
+}
+
 #[test]
 fn sstore_cost() {
     for revision in [
@@ -254,6 +276,136 @@ fn sstore_cost() {
     }
 }
 
+#[test]
+fn sstore_dirty_slot_rewrite() {
+    for (revision, gas_used) in [
+        (Revision::Byzantium, 25012),
+        (Revision::Constantinople, 5212),
+        (Revision::Petersburg, 25012),
+        (Revision::Istanbul, 5812),
+    ] {
+        // Delete the slot and set it again: no net metering before Constantinople and in Petersburg.
+        EvmTester::new()
+            .revision(revision)
+            .code(Bytecode::new().sstore(1, 0).sstore(1, 1))
+            .apply_host_fn(|host, msg| {
+                host.accounts
+                    .entry(msg.destination)
+                    .or_default()
+                    .storage
+                    .entry(H256::from_low_u64_be(1))
+                    .or_default()
+                    .value = H256::from_low_u64_be(1);
+            })
+            .status(StatusCode::Success)
+            .gas_used(gas_used)
+            .check()
+    }
+}
+
+#[test]
+fn sstore_unchanged_and_modified_again() {
+    // The slot holds 2; it held 1 before an earlier write in the transaction unless `dirty` is false.
+    for (revision, dirty, value, gas_used, refund) in [
+        // Unchanged.
+        (Revision::Istanbul, false, 2, 806, 0),
+        (Revision::Berlin, false, 2, 2206, 0),
+        // Modified again.
+        (Revision::Istanbul, true, 3, 806, 0),
+        (Revision::Berlin, true, 3, 2206, 0),
+        // Modified again back to the original value.
+        (Revision::Istanbul, true, 1, 806, 4200),
+        (Revision::Berlin, true, 1, 2206, 2800),
+    ] {
+        let output = EvmTester::new()
+            .revision(revision)
+            .code(Bytecode::new().sstore(1, value))
+            .apply_host_fn(move |host, msg| {
+                let slot = host
+                    .accounts
+                    .entry(msg.destination)
+                    .or_default()
+                    .storage
+                    .entry(H256::from_low_u64_be(1))
+                    .or_default();
+                slot.value = H256::from_low_u64_be(2);
+                if dirty {
+                    slot.original = H256::from_low_u64_be(1);
+                    slot.dirty = true;
+                }
+            })
+            .status(StatusCode::Success)
+            .gas_used(gas_used)
+            .check_and_get_result();
+        assert_eq!(output.gas_refund, refund, "{:?} {}", revision, value);
+    }
+}
+
+#[test]
+fn sstore_refund() {
+    // Slot 1 holds 1 before execution.
+    let x_to_0 = Bytecode::new().sstore(1, 0);
+    let x_to_0_to_x = Bytecode::new().sstore(1, 0).sstore(1, 1);
+    let x_to_0_to_z = Bytecode::new().sstore(1, 0).sstore(1, 2);
+    let x_to_y_to_x = Bytecode::new().sstore(1, 2).sstore(1, 1);
+    let x_to_y_to_0 = Bytecode::new().sstore(1, 2).sstore(1, 0);
+
+    for (revision, code, refund) in [
+        (Revision::Byzantium, x_to_0.clone(), 15000),
+        (Revision::Byzantium, x_to_0_to_x.clone(), 15000),
+        (Revision::Istanbul, x_to_0.clone(), 15000),
+        // The clear refund is taken back when the original value is restored.
+        (Revision::Istanbul, x_to_0_to_x.clone(), 4200),
+        (Revision::Istanbul, x_to_0_to_z, 0),
+        (Revision::Istanbul, x_to_y_to_x.clone(), 4200),
+        (Revision::Istanbul, x_to_y_to_0, 15000),
+        (Revision::Berlin, x_to_0.clone(), 15000),
+        (Revision::Berlin, x_to_0_to_x.clone(), 2800),
+        (Revision::Berlin, x_to_y_to_x, 2800),
+        (Revision::London, x_to_0, 4800),
+        (Revision::London, x_to_0_to_x, 2800),
+    ] {
+        let output = EvmTester::new()
+            .revision(revision)
+            .code(code)
+            .apply_host_fn(|host, msg| {
+                host.accounts
+                    .entry(msg.destination)
+                    .or_default()
+                    .storage
+                    .entry(H256::from_low_u64_be(1))
+                    .or_default()
+                    .value = H256::from_low_u64_be(1);
+            })
+            .status(StatusCode::Success)
+            .check_and_get_result();
+        assert_eq!(output.gas_refund, refund, "{:?}", revision);
+    }
+
+    // Deleting a slot that was added in the same frame refunds the set cost: 0 -> Y -> 0.
+    let output = EvmTester::new()
+        .revision(Revision::Istanbul)
+        .code(Bytecode::new().sstore(1, 1).sstore(1, 0))
+        .status(StatusCode::Success)
+        .check_and_get_result();
+    assert_eq!(output.gas_refund, 19200);
+
+    // A reverted frame refunds nothing.
+    let output = EvmTester::new()
+        .revision(Revision::Istanbul)
+        .code(
+            Bytecode::new()
+                .sstore(1, 1)
+                .sstore(1, 0)
+                .pushv(0)
+                .opcode(OpCode::DUP1)
+                .opcode(OpCode::REVERT),
+        )
+        .status(StatusCode::Revert)
+        .check_and_get_result();
+    assert_eq!(output.gas_refund, 0);
+}
+
 #[test]
 fn sstore_below_stipend() {
     let code = Bytecode::new().sstore(0, 0);
@@ -330,6 +482,65 @@ fn tx_context() {
         .check()
 }
 
+#[test]
+fn chainid_wide() {
+    let chain_id = hex!("0000000000000000000000000000000100000000000000000000000000000bad");
+    EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::CHAINID).ret_top())
+        .revision(Revision::Istanbul)
+        .apply_host_fn(move |host, _| {
+            host.tx_context.chain_id = chain_id.into();
+        })
+        .status(StatusCode::Success)
+        .output_data(chain_id)
+        .check();
+
+    EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::CHAINID).ret_top())
+        .revision(Revision::Istanbul)
+        .apply_host_fn(|host, _| {
+            host.tx_context = host.tx_context.clone().with_chain_id(u64::MAX);
+        })
+        .status(StatusCode::Success)
+        .output_value(u64::MAX)
+        .check()
+}
+
+#[test]
+fn block_context_u64_width() {
+    for (op, value) in [
+        (OpCode::TIMESTAMP, u64::MAX),
+        (OpCode::NUMBER, u64::MAX - 1),
+        (OpCode::GASLIMIT, u64::MAX - 2),
+    ] {
+        EvmTester::new()
+            .code(Bytecode::new().opcode(op).ret_top())
+            .apply_host_fn(|host, _| {
+                host.tx_context.block_timestamp = u64::MAX;
+                host.tx_context.block_number = u64::MAX - 1;
+                host.tx_context.block_gas_limit = u64::MAX - 2;
+            })
+            .status(StatusCode::Success)
+            .output_value(value)
+            .check()
+    }
+}
+
+#[test]
+fn prevrandao() {
+    let prevrandao = hex!("ce124dee50136f3f93f19667fb4198c6b94eecbacfa300469e5280012757be94");
+    EvmTester::new()
+        .code(Bytecode::new().opcode(OpCode::PREVRANDAO).ret_top())
+        .revision(Revision::Paris)
+        .apply_host_fn(move |host, _| {
+            host.tx_context.block_difficulty = prevrandao.into();
+        })
+        .status(StatusCode::Success)
+        .gas_used(17)
+        .output_data(prevrandao)
+        .check()
+}
+
 #[test]
 fn balance() {
     EvmTester::new()
@@ -484,6 +695,148 @@ fn log0_empty() {
         .check()
 }
 
+#[test]
+fn dry_run() {
+    let key = H256::from_low_u64_be(1);
+    for revision in [Revision::Byzantium, Revision::Istanbul, Revision::Berlin] {
+        let t = EvmTester::new()
+            .revision(revision)
+            .code(
+                Bytecode::new()
+                    .sstore(1, 1)
+                    .sstore(1, 2)
+                    .pushv(0)
+                    .pushv(0)
+                    .opcode(OpCode::LOG0)
+                    .sload(1)
+                    .ret_top(),
+            )
+            .status(StatusCode::Success)
+            .output_value(2);
+
+        let committed = t
+            .clone()
+            .inspect_host(move |host, msg| {
+                assert_eq!(
+                    host.accounts[&msg.destination].storage[&key].value,
+                    H256::from_low_u64_be(2)
+                );
+                assert_eq!(host.recorded.lock().logs.len(), 1);
+            })
+            .check_and_get_result();
+
+        let dry = t
+            .config(Config {
+                dry_run: true,
+                ..Default::default()
+            })
+            .inspect_host(move |host, msg| {
+                assert!(host
+                    .accounts
+                    .get(&msg.destination)
+                    .and_then(|account| account.storage.get(&key))
+                    .map(|slot| slot.value.is_zero())
+                    .unwrap_or(true));
+                assert!(host.recorded.lock().logs.is_empty());
+            })
+            .check_and_get_result();
+
+        assert_eq!(committed.gas_left, dry.gas_left);
+    }
+}
+
+#[test]
+fn dry_run_read_only_host() {
+    let key = H256::from_low_u64_be(1);
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .sstore(1, 2)
+            .pushv(0)
+            .pushv(0)
+            .opcode(OpCode::LOG0)
+            .sload(1)
+            .ret_top()
+            .build(),
+    );
+
+    let mut host = DelegatingHost::new(MockedHost::default()).read_only();
+    host.inner_mut()
+        .accounts
+        .entry(Address::zero())
+        .or_default()
+        .storage
+        .entry(key)
+        .or_default()
+        .value = H256::from_low_u64_be(1);
+
+    let output = code.execute_dry_run(
+        &mut host,
+        &mut NoopTracer,
+        None,
+        Message::builder().gas(1_000_000).build(),
+        Revision::Berlin,
+        Config::default(),
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), 2.into());
+    assert_eq!(
+        host.inner().accounts[&Address::zero()].storage[&key].value,
+        H256::from_low_u64_be(1)
+    );
+    assert!(host.inner().logs().is_empty());
+}
+
+#[test]
+fn log2_captured() {
+    let destination = Address::from_low_u64_be(0xdd);
+    EvmTester::new()
+        .destination(destination)
+        .code(
+            Bytecode::new()
+                .mstore_value(0, 0xabcd)
+                .pushv(0xbb)
+                .pushv(0xaa)
+                .pushv(2)
+                .pushv(30)
+                .opcode(OpCode::LOG2),
+        )
+        .status(StatusCode::Success)
+        .logs([LogRecord {
+            creator: destination,
+            data: vec![0xab, 0xcd].into(),
+            topics: vec![H256::from_low_u64_be(0xaa), H256::from_low_u64_be(0xbb)],
+        }])
+        .check()
+}
+
+#[test]
+fn log_data_limit() {
+    let config = Config {
+        max_log_data: Some(32),
+        ..Default::default()
+    };
+    let log = |size: u64| Bytecode::new().pushv(size).pushv(0).opcode(OpCode::LOG0);
+
+    EvmTester::new()
+        .config(config.clone())
+        .code(log(32))
+        .status(StatusCode::Success)
+        .inspect_host(|host, _| {
+            assert_eq!(host.recorded.lock().logs[0].data.len(), 32);
+        })
+        .check();
+
+    EvmTester::new()
+        .config(config)
+        .code(log(0x100000))
+        .status(StatusCode::LogDataLimitExceeded)
+        .inspect_host(|host, _| {
+            assert!(host.recorded.lock().logs.is_empty());
+        })
+        .check()
+}
+
 #[test]
 fn log_data_cost() {
     for op in [
@@ -937,6 +1290,200 @@ fn blockhash() {
     .check();
 }
 
+#[test]
+fn blockhash_bounds() {
+    for (current, number, recorded) in [
+        // Genesis has no previous blocks.
+        (0, U256::zero(), None),
+        (1000, 1000.into(), None),
+        (1000, 1001.into(), None),
+        (1000, 999.into(), Some(999)),
+        (1000, 744.into(), Some(744)),
+        (1000, 743.into(), None),
+        (1000, U256::max_value(), None),
+    ] {
+        EvmTester::new()
+            .code(
+                Bytecode::new()
+                    .pushv(number)
+                    .opcode(OpCode::BLOCKHASH)
+                    .ret_top(),
+            )
+            .apply_host_fn(move |host, _| {
+                host.tx_context.block_number = current;
+                host.block_hash = H256::repeat_byte(0x13);
+            })
+            .status(StatusCode::Success)
+            .inspect(move |host, _, output| {
+                let expected = if recorded.is_some() { 0x13 } else { 0 };
+                assert_eq!(output, [expected; 32], "{}", number);
+                assert_eq!(
+                    host.recorded.lock().blockhashes,
+                    recorded.into_iter().collect::<Vec<u64>>(),
+                    "{}",
+                    number
+                );
+            })
+            .check();
+    }
+}
+
+#[test]
+fn blockhash_custom_window() {
+    fn history_contract(revision: Revision) -> Spec {
+        Spec {
+            block_hash_window: 8192,
+            ..Spec::mainnet(revision)
+        }
+    }
+
+    fn previous_only(revision: Revision) -> Spec {
+        Spec {
+            block_hash_window: 1,
+            ..Spec::mainnet(revision)
+        }
+    }
+
+    let cases: [(fn(Revision) -> Spec, u64, Option<u64>); 6] = [
+        (history_contract, 9999, Some(9999)),
+        (history_contract, 1808, Some(1808)),
+        (history_contract, 1807, None),
+        (history_contract, 10000, None),
+        (previous_only, 9999, Some(9999)),
+        (previous_only, 9998, None),
+    ];
+    for (spec, number, recorded) in cases {
+        EvmTester::new()
+            .config(Config {
+                spec,
+                ..Default::default()
+            })
+            .code(
+                Bytecode::new()
+                    .pushv(number)
+                    .opcode(OpCode::BLOCKHASH)
+                    .ret_top(),
+            )
+            .apply_host_fn(|host, _| {
+                host.tx_context.block_number = 10000;
+                host.block_hash = H256::repeat_byte(0x13);
+            })
+            .status(StatusCode::Success)
+            .inspect(move |host, _, output| {
+                let expected = if recorded.is_some() { 0x13 } else { 0 };
+                assert_eq!(output, [expected; 32], "{}", number);
+                assert_eq!(
+                    host.recorded.lock().blockhashes,
+                    recorded.into_iter().collect::<Vec<u64>>(),
+                    "{}",
+                    number
+                );
+            })
+            .check();
+    }
+}
+
+#[test]
+fn blockhash_from_history_contract() {
+    fn eip2935(revision: Revision) -> Spec {
+        Spec {
+            eip2935: true,
+            ..Spec::mainnet(revision)
+        }
+    }
+
+    let key = H256::from_low_u64_be(9999 % HISTORY_SERVE_WINDOW);
+    let t = EvmTester::new()
+        .code(
+            Bytecode::new()
+                .pushv(9999)
+                .opcode(OpCode::BLOCKHASH)
+                .ret_top(),
+        )
+        .apply_host_fn(move |host, _| {
+            host.tx_context.block_number = 10000;
+            host.block_hash = H256::repeat_byte(0x13);
+            host.accounts
+                .entry(HISTORY_STORAGE_ADDRESS)
+                .or_default()
+                .storage
+                .insert(
+                    key,
+                    StorageValue {
+                        value: H256::repeat_byte(0x35),
+                        ..Default::default()
+                    },
+                );
+        })
+        .status(StatusCode::Success);
+
+    // Without EIP-2935 the host is asked for the hash.
+    t.clone()
+        .inspect(|host, _, output| {
+            assert_eq!(output, [0x13; 32]);
+            assert_eq!(host.recorded.lock().blockhashes, [9999]);
+        })
+        .check();
+
+    let output = t
+        .config(Config {
+            spec: eip2935,
+            access_list: true,
+            ..Default::default()
+        })
+        .inspect(|host, _, output| {
+            assert_eq!(output, [0x35; 32]);
+            let r = host.recorded.lock();
+            assert_eq!(r.blockhashes, [] as [u64; 0]);
+            assert_eq!(r.account_accesses, [HISTORY_STORAGE_ADDRESS]);
+        })
+        .check_and_get_result();
+    assert_eq!(
+        output.access_list.unwrap()[&HISTORY_STORAGE_ADDRESS]
+            .iter()
+            .collect::<Vec<_>>(),
+        [&key]
+    );
+
+    // A wider window is capped to what the history contract keeps.
+    fn wide_eip2935(revision: Revision) -> Spec {
+        Spec {
+            block_hash_window: 10000,
+            ..eip2935(revision)
+        }
+    }
+    for (number, expected) in [(10000 - 8191, [0x35_u8; 32]), (10000 - 8192, [0; 32])] {
+        EvmTester::new()
+            .code(
+                Bytecode::new()
+                    .pushv(number)
+                    .opcode(OpCode::BLOCKHASH)
+                    .ret_top(),
+            )
+            .config(Config {
+                spec: wide_eip2935,
+                ..Default::default()
+            })
+            .apply_host_fn(move |host, _| {
+                host.tx_context.block_number = 10000;
+                host.accounts
+                    .entry(HISTORY_STORAGE_ADDRESS)
+                    .or_default()
+                    .storage
+                    .insert(
+                        H256::from_low_u64_be(number % HISTORY_SERVE_WINDOW),
+                        StorageValue {
+                            value: H256::repeat_byte(0x35),
+                            ..Default::default()
+                        },
+                    );
+            })
+            .status(StatusCode::Success)
+            .output_data(expected)
+            .check();
+    }
+}
+
 #[test]
 fn extcode() {
     let addr = hex!("fffffffffffffffffffffffffffffffffffffffe").into();
@@ -1189,3 +1736,43 @@ fn extcodecopy_buffer_overflow() {
         }
     }
 }
+
+#[test]
+fn gas_breakdown() {
+    let code = Bytecode::new()
+        .mstore_value(0, 1)
+        .mstore_value(0x20, 2)
+        .sstore(1, 1)
+        .sstore(1, 2)
+        .pushv(0x40)
+        .pushv(0)
+        .opcode(OpCode::KECCAK256)
+        .opcode(OpCode::POP);
+    let t = EvmTester::new()
+        .revision(Revision::Petersburg)
+        .code(code)
+        .status(StatusCode::Success)
+        .gas_used(25086);
+
+    assert_eq!(t.clone().check_and_get_result().gas_breakdown, None);
+
+    let breakdown = t
+        .config(Config {
+            gas_breakdown: true,
+            ..Default::default()
+        })
+        .check_and_get_result()
+        .gas_breakdown
+        .unwrap();
+    assert_eq!(
+        breakdown,
+        GasBreakdown {
+            base: 2 * 9 + 2 * 6 + 36 + 2,
+            memory: 2 * 3,
+            storage: 20000 + 5000,
+            calls: 0,
+            other: 2 * 6,
+        }
+    );
+    assert_eq!(breakdown.total(), 25086);
+}
@@ -0,0 +1,679 @@
+use ethereum_types::{Address, H256, U256};
+use evmodin::{
+    continuation::{interrupt::*, interrupt_data::CallScheme, resume_data::*, *},
+    opcode::*,
+    util::{mocked_host::MockedHost, *},
+    *,
+};
+
+fn message() -> Message {
+    Message {
+        kind: CallKind::Call,
+        is_static: false,
+        depth: 0,
+        gas: i64::MAX,
+        destination: Address::zero(),
+        sender: Address::zero(),
+        input_data: vec![].into(),
+        value: U256::zero(),
+    }
+}
+
+#[test]
+fn run_three_steps() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .pushv(1)
+            .pushv(2)
+            .opcode(OpCode::ADD)
+            .pushv(4)
+            .opcode(OpCode::MUL)
+            .ret_top()
+            .build(),
+    );
+
+    let mut host = MockedHost::default();
+
+    let interrupt = code
+        .execute_resumable(true, message(), Revision::latest())
+        .resume(());
+
+    let result = interrupt.run_n_steps_with_host(&mut host, 3);
+    let stack = result
+        .state()
+        .expect("expected execution to be paused")
+        .stack();
+    assert_eq!(stack.len(), 1);
+    assert_eq!(*stack.get(0), 3.into());
+
+    let paused = match result {
+        RunResult::Paused(i) => i,
+        _ => panic!("expected execution to be paused"),
+    };
+
+    assert_eq!(paused.data().pc, 5);
+    assert_eq!(paused.data().opcode, OpCode::PUSH1);
+
+    let output = match InterruptVariant::InstructionStart(paused)
+        .run_n_steps_with_host(&mut host, usize::MAX)
+    {
+        RunResult::Halted(output) => output,
+        _ => panic!("expected execution to finish"),
+    };
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), 12.into());
+}
+
+#[test]
+fn run_steps_until_host_input() {
+    let code = AnalyzedCode::analyze(Bytecode::new().sload(0).build());
+
+    let interrupt = code
+        .execute_resumable(true, message(), Revision::Byzantium)
+        .resume(());
+
+    assert!(matches!(
+        interrupt.run_n_steps(10),
+        RunResult::NeedHostInput(InterruptVariant::GetStorage(_))
+    ));
+}
+
+#[test]
+fn run_until_log() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .mstore_value(0, 0xfe)
+            .pushv(0xaa)
+            .pushv(32)
+            .pushv(0)
+            .opcode(OpCode::LOG1)
+            .sstore(0, 1)
+            .build(),
+    );
+
+    let mut host = MockedHost::default();
+
+    let interrupt = code
+        .execute_resumable(false, message(), Revision::Byzantium)
+        .resume(());
+
+    let log = match interrupt.run_until_log_with_host(&mut host) {
+        RunResult::NeedHostInput(InterruptVariant::EmitLog(i)) => i,
+        _ => panic!("expected execution to be paused on log"),
+    };
+
+    assert_eq!(log.data().topics.as_slice(), [H256::from_low_u64_be(0xaa)]);
+    assert_eq!(U256::from_big_endian(&log.data().data), 0xfe.into());
+    assert!(host.logs().is_empty());
+
+    let output = match InterruptVariant::EmitLog(log).run_n_steps_with_host(&mut host, usize::MAX) {
+        RunResult::Halted(output) => output,
+        _ => panic!("expected execution to finish"),
+    };
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(host.logs().len(), 1);
+    assert_eq!(
+        host.accounts[&Address::zero()].storage[&H256::zero()].value,
+        H256::from_low_u64_be(1)
+    );
+}
+
+#[test]
+fn run_until_breakpoint() {
+    // Count down from 3, with the counter on top of the stack at the DUP1 at pc 7.
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .pushv(3)
+            .opcode(OpCode::JUMPDEST)
+            .pushv(1)
+            .opcode(OpCode::SWAP1)
+            .opcode(OpCode::SUB)
+            .opcode(OpCode::DUP1)
+            .pushv(2)
+            .opcode(OpCode::JUMPI)
+            .build(),
+    );
+    let destination = Address::from_low_u64_be(0xaa);
+
+    let mut host = MockedHost::default();
+
+    let mut interrupt = code
+        .execute_resumable(
+            true,
+            Message {
+                destination,
+                ..message()
+            },
+            Revision::latest(),
+        )
+        .resume(());
+
+    let mut counters = vec![];
+    let output = loop {
+        match interrupt.run_until_breakpoint_with_host(&mut host, 7) {
+            RunResult::Paused(i) => {
+                assert_eq!(i.data().opcode, OpCode::DUP1);
+                counters.push(i.data().state.stack().get(0).as_u64());
+                interrupt = InterruptVariant::InstructionStart(i);
+            }
+            RunResult::Halted(output) => break output,
+            RunResult::NeedHostInput(_) => panic!("host input is answered by the host"),
+        }
+    };
+
+    assert_eq!(counters, [2, 1, 0]);
+    assert_eq!(output.status_code, StatusCode::Success);
+
+    // Paused elsewhere, execution runs on to the breakpoint.
+    let interrupt = match code
+        .execute_resumable(
+            true,
+            Message {
+                destination,
+                ..message()
+            },
+            Revision::latest(),
+        )
+        .resume(())
+        .run_n_steps(2)
+    {
+        RunResult::Paused(i) => InterruptVariant::InstructionStart(i),
+        _ => panic!("paused expected"),
+    };
+    match interrupt.run_until_breakpoint_with_host(&mut host, 5) {
+        RunResult::Paused(i) => assert_eq!(i.data().opcode, OpCode::SWAP1),
+        _ => panic!("paused at the breakpoint expected"),
+    }
+
+    // Breakpoints inside push data are never hit.
+    let interrupt = code
+        .execute_resumable(
+            true,
+            Message {
+                destination,
+                ..message()
+            },
+            Revision::latest(),
+        )
+        .resume(());
+    assert!(matches!(
+        interrupt.run_until_breakpoint_with_host(&mut host, 1),
+        RunResult::Halted(_)
+    ));
+}
+
+fn script_code() -> AnalyzedCode {
+    // Return storage[0] + balance(0xaa).
+    AnalyzedCode::analyze(
+        Bytecode::new()
+            .sload(0)
+            .pushv(0xaa)
+            .opcode(OpCode::BALANCE)
+            .opcode(OpCode::ADD)
+            .ret_top()
+            .build(),
+    )
+}
+
+#[test]
+fn run_scripted_interrupts() {
+    let output = run_with_script(
+        script_code().execute_resumable(true, message(), Revision::Istanbul),
+        vec![
+            ScriptStep::answer::<GetStorageInterrupt>(StorageValue {
+                value: H256::from_low_u64_be(2),
+            }),
+            ScriptStep::answer::<GetBalanceInterrupt>(Balance { balance: 40.into() }),
+        ],
+    );
+
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), 42.into());
+}
+
+#[test]
+#[should_panic(
+    expected = "interrupts diverged from the script\n  expected: [\"GetBalance\", \"GetStorage\"]\n    actual: [\"GetStorage\"]"
+)]
+fn run_scripted_interrupts_out_of_order() {
+    run_with_script(
+        script_code().execute_resumable(false, message(), Revision::Istanbul),
+        vec![
+            ScriptStep::answer::<GetBalanceInterrupt>(Balance { balance: 40.into() }),
+            ScriptStep::answer::<GetStorageInterrupt>(StorageValue {
+                value: H256::from_low_u64_be(2),
+            }),
+        ],
+    );
+}
+
+#[test]
+#[should_panic(expected = "actual: [\"GetStorage\", \"GetBalance\"]")]
+fn run_scripted_interrupts_left_over() {
+    run_with_script(
+        script_code().execute_resumable(false, message(), Revision::Istanbul),
+        vec![
+            ScriptStep::answer::<GetStorageInterrupt>(StorageValue {
+                value: H256::from_low_u64_be(2),
+            }),
+            ScriptStep::answer::<GetBalanceInterrupt>(Balance { balance: 40.into() }),
+            ScriptStep::answer::<SetStorageInterrupt>(StorageStatusInfo {
+                status: host::StorageStatus::Added,
+            }),
+        ],
+    );
+}
+
+#[test]
+fn peek_gas_cost_of_memory_expansion() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .mstore_value(0, 1)
+            .mstore_value(100, 2)
+            .pushv(0)
+            .build(),
+    );
+
+    let mut host = MockedHost::default();
+
+    let interrupt = code
+        .execute_resumable(true, message(), Revision::latest())
+        .resume(());
+
+    let paused = match interrupt.run_n_steps_with_host(&mut host, 5) {
+        RunResult::Paused(i) => i,
+        _ => panic!("expected execution to be paused"),
+    };
+    assert_eq!(paused.data().opcode, OpCode::MSTORE);
+
+    let gas_before = *paused.data().state.gas_left();
+    // Base cost and growth from 1 to 5 words.
+    let predicted = peek_gas_cost(paused.data().opcode, &paused.data().state, &host);
+    assert_eq!(predicted, Some(3 + 12));
+
+    let paused =
+        match InterruptVariant::InstructionStart(paused).run_n_steps_with_host(&mut host, 1) {
+            RunResult::Paused(i) => i,
+            _ => panic!("expected execution to be paused"),
+        };
+    assert_eq!(
+        predicted,
+        Some(gas_before - *paused.data().state.gas_left())
+    );
+}
+
+#[test]
+fn peek_gas_cost_on_full_stack() {
+    let code = AnalyzedCode::analyze(
+        (1024 * Bytecode::new().pushv(1))
+            .opcode(OpCode::ADD)
+            .build(),
+    );
+
+    let mut host = MockedHost::default();
+
+    let paused = match code
+        .execute_resumable(true, message(), Revision::latest())
+        .resume(())
+        .run_n_steps_with_host(&mut host, 1024)
+    {
+        RunResult::Paused(i) => i,
+        _ => panic!("expected execution to be paused"),
+    };
+    assert_eq!(paused.data().state.stack().len(), 1024);
+
+    // Instructions growing the stack would overflow it, the others can still run.
+    let state = &paused.data().state;
+    assert_eq!(peek_gas_cost(OpCode::PUSH1, state, &host), None);
+    assert_eq!(peek_gas_cost(OpCode::DUP1, state, &host), None);
+    assert_eq!(peek_gas_cost(OpCode::ADD, state, &host), Some(3));
+    assert_eq!(peek_gas_cost(OpCode::POP, state, &host), Some(2));
+}
+
+#[test]
+fn peek_gas_cost_of_access() {
+    // Cold and warm SLOAD and BALANCE, BALANCE of a precompile and cold EXTCODEHASH.
+    let code = AnalyzedCode::analyze(asm("PUSH1 1 SLOAD POP PUSH1 1 SLOAD POP \
+         PUSH1 0xaa BALANCE POP PUSH1 0xaa BALANCE POP PUSH1 1 BALANCE POP \
+         PUSH1 0xbb EXTCODEHASH POP"));
+
+    let mut host = MockedHost::default();
+
+    let mut result = code
+        .execute_resumable(true, message(), Revision::Berlin)
+        .resume(())
+        .run_n_steps_with_host(&mut host, 0);
+    let mut steps = 0;
+    while let RunResult::Paused(paused) = result {
+        let opcode = paused.data().opcode;
+        let gas_before = *paused.data().state.gas_left();
+        let predicted = peek_gas_cost(opcode, &paused.data().state, &host);
+
+        result = InterruptVariant::InstructionStart(paused).run_n_steps_with_host(&mut host, 1);
+        let gas_after = match &result {
+            RunResult::Paused(i) => *i.data().state.gas_left(),
+            RunResult::Halted(output) => output.gas_left,
+            RunResult::NeedHostInput(_) => panic!("expected the host to answer"),
+        };
+        assert_eq!(predicted, Some(gas_before - gas_after), "{}", opcode);
+        steps += 1;
+    }
+    assert!(steps >= 18);
+}
+
+#[test]
+fn peek_gas_cost_over_limits() {
+    let host = MockedHost::default();
+    let mut state = ExecutionState::with_config(
+        message(),
+        Revision::Berlin,
+        Config {
+            max_output_size: Some(32),
+            max_log_data: Some(32),
+            ..Config::default()
+        },
+    );
+    // Offset 0, size 33.
+    state.stack_mut().push(33.into());
+    state.stack_mut().push(0.into());
+    assert_eq!(peek_gas_cost(OpCode::RETURN, &state, &host), None);
+    assert_eq!(peek_gas_cost(OpCode::LOG0, &state, &host), None);
+
+    // Size 32: one word of memory, and 8 per byte of log data.
+    *state.stack_mut().get_mut(1) = 32.into();
+    assert_eq!(peek_gas_cost(OpCode::RETURN, &state, &host), Some(3));
+    assert_eq!(
+        peek_gas_cost(OpCode::LOG0, &state, &host),
+        Some(375 + 3 + 32 * 8)
+    );
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .pushv(1)
+            .pushv(2)
+            .opcode(OpCode::ADD)
+            .mstore(0)
+            .opcode(OpCode::JUMPDEST)
+            .pushv(0)
+            .opcode(OpCode::DUP1)
+            .opcode(OpCode::REVERT)
+            .build(),
+    );
+
+    let mut host = MockedHost::default();
+
+    let interrupt = code
+        .execute_resumable(true, message(), Revision::latest())
+        .resume(());
+
+    let paused = match interrupt.run_n_steps_with_host(&mut host, 2) {
+        RunResult::Paused(i) => i,
+        _ => panic!("expected execution to be paused"),
+    };
+    assert_eq!(paused.data().opcode, OpCode::ADD);
+    let pc = paused.data().pc;
+    let snapshot = paused.data().snapshot();
+    let gas_left = *paused.data().state.gas_left();
+
+    let paused =
+        match InterruptVariant::InstructionStart(paused).run_n_steps_with_host(&mut host, 3) {
+            RunResult::Paused(i) => i,
+            _ => panic!("expected execution to be paused"),
+        };
+    assert_eq!(paused.data().opcode, OpCode::JUMPDEST);
+    assert_eq!(paused.data().state.memory().len(), 32);
+    assert!(paused.data().state.stack().is_empty());
+
+    // Roll back from JUMPDEST to ADD, execution pauses at ADD again.
+    let paused = match paused.resume_with_action(StepAction::Restore(snapshot.clone())) {
+        InterruptVariant::InstructionStart(i) => i,
+        _ => panic!("expected execution to be paused"),
+    };
+    assert_eq!(paused.data().pc, pc);
+    assert_eq!(paused.data().opcode, OpCode::ADD);
+    let state = &paused.data().state;
+    assert_eq!(state.stack().as_slice(), [U256::from(1), U256::from(2)]);
+    assert!(state.memory().is_empty());
+    assert_eq!(*state.gas_left(), gas_left);
+
+    // Executing again from the snapshot gets to the same state.
+    let paused =
+        match InterruptVariant::InstructionStart(paused).run_n_steps_with_host(&mut host, 3) {
+            RunResult::Paused(i) => i,
+            _ => panic!("expected execution to be paused"),
+        };
+    assert_eq!(paused.data().opcode, OpCode::JUMPDEST);
+    assert_eq!(paused.data().state.memory().len(), 32);
+    assert_eq!(*paused.data().state.gas_left(), gas_left - 3 - 3 - (3 + 3));
+}
+
+#[test]
+fn call_interrupt_scheme() {
+    for (op, args, scheme) in [
+        (OpCode::CALL, 7, CallScheme::Call),
+        (OpCode::CALLCODE, 7, CallScheme::CallCode),
+        (OpCode::DELEGATECALL, 6, CallScheme::DelegateCall),
+        (OpCode::STATICCALL, 6, CallScheme::StaticCall),
+        (OpCode::CREATE, 3, CallScheme::Create),
+        (OpCode::CREATE2, 4, CallScheme::Create2),
+    ] {
+        let mut code = Bytecode::new();
+        for _ in 0..args {
+            code = code.pushv(0);
+        }
+        let code = AnalyzedCode::analyze(code.opcode(op).build());
+
+        let interrupt = code
+            .execute_resumable(false, message(), Revision::Istanbul)
+            .resume(());
+
+        match interrupt {
+            InterruptVariant::Call(i) => assert_eq!(i.data().scheme, scheme, "{}", op),
+            _ => panic!("expected call interrupt for {}", op),
+        }
+    }
+}
+
+#[test]
+fn maximum_call_depth_without_native_recursion() {
+    // Every frame calls itself with all the gas it may forward and returns the call's success flag.
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .append_bc(CallInstruction::call(0).gas(U256::max_value()))
+            .ret_top()
+            .build(),
+    );
+
+    // Frames are suspended generators kept on the heap, so nesting them does not grow the native stack.
+    let mut frames = Vec::new();
+    let mut max_frames = 0;
+    let mut interrupt = code
+        .execute_resumable(false, message(), Revision::Istanbul)
+        .resume(());
+    let output = loop {
+        interrupt = match interrupt {
+            InterruptVariant::Call(i) => {
+                let message = i.data().message.clone();
+                frames.push(i);
+                max_frames = max_frames.max(frames.len());
+                code.execute_resumable(false, message, Revision::Istanbul)
+                    .resume(())
+            }
+            InterruptVariant::Complete(result) => {
+                let output = Output::from(result.unwrap());
+                match frames.pop() {
+                    Some(parent) => parent.resume(resume_data::CallOutput { output }),
+                    None => break output,
+                }
+            }
+            other => panic!("unexpected {}", <&'static str>::from(&other)),
+        };
+    };
+
+    // Depths 0 to 1023 issue calls, the CALL at depth 1024 fails without one.
+    assert_eq!(max_frames, 1024);
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), 1.into());
+}
+
+#[test]
+fn extcodehash_asks_host_for_hash() {
+    let hash = H256::repeat_byte(0xcc);
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .pushv(0xaa)
+            .opcode(OpCode::EXTCODEHASH)
+            .ret_top()
+            .build(),
+    );
+
+    let mut interrupt = code
+        .execute_resumable(false, message(), Revision::Istanbul)
+        .resume(());
+
+    // The hash is used as is, the code is never fetched.
+    let output = loop {
+        interrupt = match interrupt {
+            InterruptVariant::GetCodeHash(i) => {
+                assert_eq!(i.data().address, Address::from_low_u64_be(0xaa));
+                i.resume(resume_data::CodeHash { hash })
+            }
+            InterruptVariant::Complete(res) => break res.unwrap(),
+            other => panic!("unexpected {} interrupt", <&'static str>::from(&other)),
+        }
+    };
+
+    assert_eq!(&*output.output_data, hash.as_bytes());
+}
+
+#[test]
+fn prefetch_storage_keys() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .sload(1)
+            .sload(0x1234)
+            .sload(1)
+            // Computed key is not known statically.
+            .pushv(2)
+            .pushv(3)
+            .opcode(OpCode::ADD)
+            .opcode(OpCode::SLOAD)
+            // Push data that looks like SLOAD is skipped.
+            .pushv(0x54)
+            .build(),
+    );
+    let keys = vec![H256::from_low_u64_be(1), H256::from_low_u64_be(0x1234)];
+    assert_eq!(code.constant_storage_keys(Revision::Istanbul), keys);
+
+    // PUSH0 only pushes a key since Shanghai.
+    let push0 = AnalyzedCode::analyze(asm("PUSH0 SLOAD PUSH1 1 SLOAD PUSH1 0 SLOAD"));
+    assert_eq!(
+        push0.constant_storage_keys(Revision::London),
+        [H256::from_low_u64_be(1), H256::zero()]
+    );
+    assert_eq!(
+        push0.constant_storage_keys(Revision::Shanghai),
+        [H256::zero(), H256::from_low_u64_be(1)]
+    );
+
+    let config = Config {
+        prefetch_storage: true,
+        ..Default::default()
+    };
+    match code
+        .execute_resumable_with_config(false, message(), Revision::Istanbul, config)
+        .resume(())
+    {
+        InterruptVariant::PrefetchStorage(i) => {
+            assert_eq!(i.data().address, message().destination);
+            assert_eq!(i.data().keys, keys);
+        }
+        other => panic!(
+            "expected prefetch interrupt, got {}",
+            <&'static str>::from(&other)
+        ),
+    }
+
+    // Not sent unless enabled.
+    assert!(matches!(
+        code.execute_resumable(false, message(), Revision::Istanbul)
+            .resume(()),
+        InterruptVariant::GetStorage(_)
+    ));
+}
+
+#[test]
+fn create_frame_flag() {
+    let code = AnalyzedCode::analyze(Bytecode::new().opcode(OpCode::CODESIZE).ret_top().build());
+
+    for (kind, is_create) in [
+        (CallKind::Call, false),
+        (CallKind::DelegateCall, false),
+        (CallKind::Create, true),
+        (
+            CallKind::Create2 {
+                salt: H256::repeat_byte(0x01),
+            },
+            true,
+        ),
+    ] {
+        let msg = Message { kind, ..message() };
+        match code
+            .execute_resumable(true, msg, Revision::latest())
+            .resume(())
+        {
+            InterruptVariant::InstructionStart(i) => {
+                assert_eq!(i.data().state.is_create(), is_create, "{:?}", kind)
+            }
+            _ => panic!("expected instruction start"),
+        }
+    }
+}
+
+#[test]
+fn mock_call_with_step_action() {
+    let code = AnalyzedCode::analyze(
+        Bytecode::new()
+            .append_bc(CallInstruction::call(0xbb).gas(1000))
+            .ret_top()
+            .build(),
+    );
+
+    let run = |action: fn() -> StepAction| {
+        let mut interrupt = code
+            .execute_resumable(true, message(), Revision::latest())
+            .resume(());
+        loop {
+            interrupt = match interrupt {
+                InterruptVariant::InstructionStart(i) if i.data().opcode == OpCode::CALL => {
+                    i.resume_with_action(action())
+                }
+                InterruptVariant::InstructionStart(i) => i.resume(None),
+                InterruptVariant::Complete(result) => break Output::from(result.unwrap()),
+                // The host is never asked, not even for the account access.
+                other => panic!("unexpected {}", <&'static str>::from(&other)),
+            };
+        }
+    };
+
+    // 7 pushes and the warm CALL, then 15 for returning the result.
+    let output = run(|| StepAction::Override {
+        push: vec![U256::one()],
+    });
+    assert_eq!(output.status_code, StatusCode::Success);
+    assert_eq!(U256::from_big_endian(&output.output_data), 1.into());
+    assert_eq!(output.gas_left, i64::MAX - (7 * 3 + 100 + 15));
+
+    let output = run(|| StepAction::Halt {
+        status_code: StatusCode::Revert,
+        output_data: vec![0xde, 0xad].into(),
+    });
+    assert_eq!(output.status_code, StatusCode::Revert);
+    assert_eq!(&*output.output_data, [0xde, 0xad]);
+    assert_eq!(output.gas_left, i64::MAX - 7 * 3);
+}